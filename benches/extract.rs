@@ -0,0 +1,98 @@
+//! Compares reading a large scanned invoice into memory with the memory mapped, lazy attachment lookup of extract.
+//!
+//!     cargo bench --bench extract
+//!
+//! The size of the generated PDF can be set in MiB with ZUGFERD_BENCH_SIZE (default: 200).
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use zugferd::testing::TestPdf;
+use zugferd::{associated_files, Error, FileData, RawFile};
+
+
+const RUNS: usize = 5;
+
+
+/// Finds the attachment through the /AF array and returns its size
+fn lookup(data: &[u8]) -> Result<usize, Error> {
+    let file = RawFile::open(data)?;
+    let file_spec = associated_files(&file)?.into_iter().next().ok_or(Error::from(20, String::from("No attachment")))?;
+    let stream = file.resolve(file_spec.stream_id().ok_or(Error::from(41, String::from("No embedded file stream")))?)?;
+    Ok(file.stream_data(&stream)?.len())
+}
+
+/// Resolves every object like a fully loaded document with an object cache
+fn resolve_all(data: &[u8]) -> Result<usize, Error> {
+    let file = RawFile::open(data)?;
+    let objects: Vec<_> = file.entries().keys().map(|id| file.resolve(*id)).collect::<Result<_, _>>()?;
+    Ok(objects.len())
+}
+
+fn succeed<T>(result: Result<T, Error>) -> T {
+    result.unwrap_or_else(|error| panic!("{}", error.message))
+}
+
+/// Resident memory of the process in KiB (Linux only)
+fn resident_kib() -> Option<i64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Runs the function RUNS times and prints the fastest run and the most resident memory, which the data returned by
+/// the function added to this process
+fn bench<T>(name: &str, in_process: bool, mut function: impl FnMut() -> T) {
+    let mut fastest = Duration::MAX;
+    let mut resident = None;
+    for _ in 0..RUNS {
+        let before = resident_kib();
+        let start = Instant::now();
+        let result = function();
+        fastest = fastest.min(start.elapsed());
+        let added = before.zip(resident_kib()).map(|(before, after)| after - before).filter(|_| in_process);
+        resident = resident.max(added);
+        drop(result);
+    }
+
+    let resident = resident.map_or(String::from("n/a"), |kib| format!("{:+.1} MiB", kib as f64 / 1024.0));
+    println!("{:<36} {:>10.2} ms {:>14} resident", name, fastest.as_secs_f64() * 1000.0, resident);
+}
+
+fn main() {
+    let size: usize = std::env::var("ZUGFERD_BENCH_SIZE").ok().and_then(|size| size.parse().ok()).unwrap_or(200);
+    let directory = std::env::temp_dir().join(format!("zugferd-bench-{}", std::process::id()));
+    fs::create_dir_all(&directory).expect("failed to create the benchmark directory");
+    let input = directory.join("scan.pdf");
+    let output = directory.join("scan.pdf.xml");
+
+    let pdf = TestPdf { scan_size: size * 1024 * 1024, ..TestPdf::default() };
+    fs::write(&input, pdf.build()).expect("failed to write the benchmark PDF");
+    println!("Extracting the attachment of a {} MiB PDF (fastest of {} runs)", size, RUNS);
+
+    bench("read + resolve all objects", true, || {
+        let data = fs::read(&input).expect("failed to read the PDF");
+        succeed(resolve_all(&data));
+        data
+    });
+    bench("read + lazy lookup", true, || {
+        let data = fs::read(&input).expect("failed to read the PDF");
+        succeed(lookup(&data));
+        data
+    });
+    bench("mapped + lazy lookup", true, || {
+        let data = FileData::open(&input).expect("failed to map the PDF");
+        succeed(lookup(&data));
+        data
+    });
+    bench("extract binary", false, || run_extract(&input, &output));
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+fn run_extract(input: &Path, output: &Path) {
+    let status = Command::new(env!("CARGO_BIN_EXE_extract")).arg(input).arg(output).status().expect("failed to run extract");
+    assert!(status.success(), "extract failed with {}", status);
+}
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zugferd::{split_mbox, Limits, MailMessage};
+
+// E-mails come from anyone, so the MIME parser must cope with any bytes
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits { max_stream_size: 1024 * 1024, ..Limits::default() };
+    for message in split_mbox(data) {
+        let _ = MailMessage::parse(message, &limits).map(|message| message.description());
+    }
+});
@@ -0,0 +1,46 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zugferd::{FileSpecification, FileType, Limits, RawFile, associated_files, carve_invoices, check_embedded_files, embedded_files, decode_stages, needs_recovery, repair, revisions, signatures, unsigned_attachment_changes};
+
+// Everything extract and inspect do with our raw object layer on arbitrary PDF bytes
+fuzz_target!(|data: &[u8]| {
+    // Small limits keep the runs fast, the limit checks themselves are part of the tested code
+    let limits = Limits { max_stream_size: 1024 * 1024, max_nesting_depth: 32, max_name_tree_entries: 1000, ..Limits::default() };
+
+    let _ = revisions(data);
+    let _ = needs_recovery(data);
+    let _ = repair(data, &limits);
+
+    for file in [RawFile::open(data), RawFile::recover(data, limits)] {
+        let Ok(mut file) = file else { continue; };
+        file.set_limits(limits);
+        if file.set_password(b"").is_err() {
+            continue;
+        }
+
+        let _ = check_embedded_files(&file);
+        let mut file_specs = associated_files(&file).unwrap_or_default();
+        file_specs.extend(embedded_files(&file).unwrap_or_default().into_iter().map(|(_, file_spec)| file_spec));
+        for file_spec in &file_specs {
+            let _ = file_spec.params(&file);
+        }
+        for stream_id in file_specs.iter().filter_map(FileSpecification::stream_id) {
+            let _ = file.resolve(stream_id).and_then(|stream| file.stream_data(&stream));
+        }
+        for id in file.entries().keys().copied().collect::<Vec<_>>() {
+            let Ok(object) = file.resolve(id) else { continue; };
+            if let (Ok(encoded), Ok(chain)) = (file.decrypted_stream_data(&object), file.filter_chain(&object)) {
+                let _ = decode_stages(&encoded, &chain);
+            }
+            if let Ok(decoded) = file.stream_data(&object) {
+                let _ = FileType::detect(&decoded);
+            }
+        }
+
+        let _ = carve_invoices(&file);
+        for signature in signatures(&file) {
+            let _ = unsigned_attachment_changes(&file, &signature);
+        }
+    }
+});
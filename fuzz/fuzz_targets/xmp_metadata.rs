@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zugferd::{xmp_dates, FileMatcher, Invoice, PdfDate};
+
+// The /Metadata stream, the invoice XML and dates are read from arbitrary strings
+fuzz_target!(|xmp: &str| {
+    let _ = FileMatcher::from_metadata(xmp);
+    let _ = Invoice::parse(xmp);
+    let _ = xmp_dates(xmp);
+    for date in [PdfDate::parse(xmp), PdfDate::parse_xmp(xmp)].into_iter().flatten() {
+        let _ = date.timestamp();
+    }
+});
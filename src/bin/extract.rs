@@ -1,25 +1,21 @@
-use pdf::primitive::PdfString;
-use zugferd::{FileMatcher, Error};
+use zugferd::{Archive, FileMatcher, Error, Invoice, CsvOptions, CsvTable, CsvWriter, DatevMapping, DatevOptions, DatevWriter, FileData, FileSpecification, FileType, Limits, MailAttachment, MailMessage, RawFile, RawObject, TrustStore, associated_files, carve_invoices, embedded_files, needs_recovery, revisions, revision_data, signatures, split_mbox, unsigned_attachment_changes, verify_signature};
 
+use std::collections::HashSet;
 use std::io::Write;
 use std::fs;
 use std::process::ExitCode;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
 
-use regex::Regex;
-
-use clap::Parser;
-
-use pdf::file::FileOptions;
-use pdf::object::{FileSpec, Resolve};
-
-
-type PDFFile = pdf::file::File<Vec<u8>, std::sync::Arc<pdf::file::SyncCache<pdf::object::PlainRef, Result<pdf::any::AnySync, std::sync::Arc<pdf::PdfError>>>>, std::sync::Arc<pdf::file::SyncCache<pdf::object::PlainRef, Result<std::sync::Arc<[u8]>, std::sync::Arc<pdf::PdfError>>>>, pdf::file::NoLog>;
+use clap::{Parser, ValueEnum};
 
 
 fn main() -> ExitCode {
-    let cli = Extract::parse();
+    let cli = Arc::new(Extract::parse());
 
-    match cli.extract() {
+    match cli.run() {
         Err(error) => {
             error.print();
             error.exit_code
@@ -32,21 +28,72 @@ fn main() -> ExitCode {
 
 
 // Error codes:
-//  1-9 : Basic File IO Errors
+//  1-9 : Basic File IO Errors (2 is also returned for a missing /AF array)
 // 10-19: /Metadata problem
 // 20-29: /AF Array problem
 // 30-39: /EmbeddedFiles problem
 // 40-49: Error while extracting file content
+// 50-59: Signature problem (attachment modified after signing, invalid or untrusted signature with --trust-store)
+// 60-69: Invoice XML problem (only when parsing the invoice for --format invoice-json, the CSV or the DATEV export)
+// 70-79: E-mail or ZIP archive problem (only for .eml, .mbox, Maildir and .zip inputs)
+// 80-89: Export problem
+// 90-99: PDF file structure problem (only when selecting a --revision or recovering a broken file)
+// 100-109: Trust store problem (only with --trust-store)
+// 110-119: Encryption problem (wrong password or unsupported security handler)
+// 120-129: Resource limit exceeded (see the --max-* options)
+// 130    : Internal error (a bug triggered by the file)
+// 140-149: Font problem (only in render)
+
+
+/// Input file of the run with the output paths for it
+struct Target {
+    /// The input path as given on the command line (written to the CSV export)
+    input: PathBuf,
+    /// The file to extract if it is not the input itself, but contained in it (an attachment of an e-mail or a file of a ZIP archive)
+    entry: Option<Entry>,
+    output: PathBuf,
+    json_output: PathBuf,
+}
+
+impl Target {
+    /// The input path, followed by the name of the entry for contained files (written to the CSV export)
+    fn source(&self) -> String {
+        match self.entry.as_ref() {
+            Some(entry) => format!("{}: {}", self.input.display(), entry.name),
+            None => self.input.display().to_string(),
+        }
+    }
+}
+
+/// A PDF or XML file contained in an input file
+struct Entry {
+    name: String,
+    data: Vec<u8>,
+    /// PDFs are extracted like PDF inputs, XML files are taken as they are
+    file_type: FileType,
+}
+
+
+/// Output formats for the extracted invoice
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// The raw attachment as stored in the PDF
+    Xml,
+    /// The parsed invoice model as JSON (see schema/invoice.schema.json)
+    InvoiceJson,
+}
 
 
 // Command line args
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 pub struct Extract {
-    /// PDF input file
+    /// PDF input file, e-mail input (.eml, .mbox or a Maildir directory), ZIP archive or a directory to extract every PDF, e-mail
+    /// and ZIP file in it (a failing file does not stop the others)
     pdf_input: std::path::PathBuf,
 
-    /// Attachment output path (default = pdfInput + ".xml")
+    /// Attachment output path (default = pdfInput + ".xml") or the output directory if pdfInput is a directory, an e-mail input
+    /// or a ZIP archive (default: the input directory or the directory of the e-mail or ZIP file)
     attachment_output: Option<std::path::PathBuf>,
 
     /// Specifies the name of the attachment to extract (default: derived from Metadata or "factur-x.xml" or "xrechnung.xml")
@@ -61,6 +108,96 @@ pub struct Extract {
     /// If not set the tool will try to extract any .xml file somehow
     #[arg(short, long, default_value_t=false)]
     strict: bool,
+
+    /// Verify the signatures of the PDF against the PEM certificates (.pem, .crt, .cer) in the given directory.
+    /// Invalid or untrusted signatures fail in strict mode and are reported as warning otherwise
+    #[arg(long)]
+    trust_store: Option<std::path::PathBuf>,
+
+    /// Password of encrypted PDFs (the user or the owner password, default: empty password)
+    #[arg(short, long, conflicts_with="password_file")]
+    password: Option<String>,
+
+    /// Read the password of encrypted PDFs from the first line of the given file
+    #[arg(long)]
+    password_file: Option<std::path::PathBuf>,
+
+    /// Extract the attachment as it was at the given revision (1 = original document, see inspect --revisions)
+    #[arg(short, long)]
+    revision: Option<usize>,
+
+    /// Rebuild the cross-reference table by scanning the file for objects (done automatically if the cross-reference sections are broken)
+    #[arg(long, default_value_t=false)]
+    recover: bool,
+
+    /// Maximum size of a decoded stream in MiB
+    #[arg(long, default_value_t=256)]
+    max_stream_size: usize,
+
+    /// Maximum ratio of decoded to encoded size of a stream (only checked for streams larger than 1 MiB)
+    #[arg(long, default_value_t=1000)]
+    max_compression_ratio: usize,
+
+    /// Maximum nesting depth of arrays, dictionaries and /EmbeddedFiles name tree nodes
+    #[arg(long, default_value_t=100)]
+    max_nesting_depth: usize,
+
+    /// Maximum number of entries and nodes of the /EmbeddedFiles name tree
+    #[arg(long, default_value_t=100000)]
+    max_name_tree_entries: usize,
+
+    /// Maximum processing time in seconds (0 = unlimited)
+    #[arg(long, default_value_t=120)]
+    max_time: u64,
+
+    /// Output format(s) to write (separate by comma to write both).
+    /// The JSON is written to the attachment output path with the extension .json unless it is the only format
+    #[arg(short, long, value_enum, value_delimiter=',', default_value="xml")]
+    format: Vec<Format>,
+
+    /// Append the invoice lines as CSV to the given file (the header is only written to new files)
+    #[arg(long)]
+    csv_lines: Option<std::path::PathBuf>,
+
+    /// Append the VAT breakdown as CSV to the given file (the header is only written to new files)
+    #[arg(long)]
+    csv_vat: Option<std::path::PathBuf>,
+
+    /// Delimiter of the CSV export (German Excel expects ';')
+    #[arg(long, default_value_t=',')]
+    csv_delimiter: char,
+
+    /// Decimal separator of the CSV export (German Excel expects ',')
+    #[arg(long, default_value_t='.')]
+    csv_decimal_separator: char,
+
+    /// Start new CSV files with a UTF-8 byte order mark, which Excel needs to detect the encoding
+    #[arg(long, default_value_t=false)]
+    csv_bom: bool,
+
+    /// Append the invoice as bookings to the given DATEV Buchungsstapel file (the header is only written to new files)
+    #[arg(long, requires_all=["datev_mapping", "datev_consultant", "datev_client"])]
+    datev: Option<std::path::PathBuf>,
+
+    /// Mapping file of supplier VAT ID and tax rate to DATEV accounts (lines of vat_id;rate;account;contra_account[;bu_key])
+    #[arg(long)]
+    datev_mapping: Option<std::path::PathBuf>,
+
+    /// DATEV consultant number (Beraternummer)
+    #[arg(long)]
+    datev_consultant: Option<u32>,
+
+    /// DATEV client number (Mandantennummer)
+    #[arg(long)]
+    datev_client: Option<u32>,
+
+    /// Start of the fiscal year as YYYYMMDD (default: January 1st of the invoice year)
+    #[arg(long)]
+    datev_fiscal_year_start: Option<String>,
+
+    /// Length of the general ledger account numbers (Sachkontenlänge), shorter accounts of the mapping are padded with zeros
+    #[arg(long, default_value_t=4)]
+    datev_account_length: u8,
 }
 
 impl Extract {
@@ -83,62 +220,147 @@ impl Extract {
         Self::resolve_path(&path)
     }
 
+    fn is_batch(&self) -> bool {
+        self.input_path().is_dir()
+    }
+
+    /// Whether the path is an e-mail input: a single message (.eml), an mbox mailbox (.mbox) or a Maildir directory
+    fn is_mail(path: &Path) -> bool {
+        Self::is_maildir(path) || Self::has_extension(path, "eml") || Self::has_extension(path, "mbox")
+    }
+
+    fn is_maildir(path: &Path) -> bool {
+        path.join("cur").is_dir() && path.join("new").is_dir()
+    }
+
+    fn has_extension(path: &Path, extension: &str) -> bool {
+        path.extension().is_some_and(|path_extension| path_extension.eq_ignore_ascii_case(extension))
+    }
+
+    /// The output directory of batch, e-mail and ZIP inputs (default: the input directory or the directory of the input file)
+    fn output_directory(&self) -> PathBuf {
+        let input = self.input_path();
+        match self.attachment_output.as_ref() {
+            Some(path) => Self::resolve_path(path),
+            None if input.is_dir() => input,
+            None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+        }
+    }
+
+    /// Every PDF, e-mail and ZIP file of the input directory (sorted by name)
+    fn batch_inputs(&self) -> Result<Vec<PathBuf>, Error> {
+        let entries = fs::read_dir(&self.pdf_input).map_err(|err| Error::from(1, format!("Failed to read directory {}: {}", self.input_path().display(), err)))?;
+        let mut inputs: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && (Self::has_extension(path, "pdf") || Self::has_extension(path, "zip") || Self::is_mail(path)))
+            .collect();
+        inputs.sort();
+        Ok(inputs)
+    }
+
+    /// A PDF file of the input directory with its outputs in the output directory
+    fn batch_target(input: PathBuf, output_directory: &Path) -> Target {
+        let mut output = output_directory.join(input.file_name().unwrap_or_default());
+        output.set_extension("pdf.xml");
+        let json_output = output.with_extension("json");
+        Target { input, entry: None, output, json_output }
+    }
+
+    fn json_output_path(&self) -> std::path::PathBuf {
+        if self.format == [Format::InvoiceJson] && self.attachment_output.is_some() {
+            return self.output_path();
+        }
+
+        let mut output = self.output_path();
+        output.set_extension("json");
+        output
+    }
+
     fn resolve_path(path: &std::path::PathBuf) -> std::path::PathBuf {
         // Resolve to absolute path if necessary
         if path.is_relative() {
-            std::env::current_dir().unwrap().join(path)
+            std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.clone())
         } else {
             path.clone()
         }
     }
 
+    /// Returns the password from --password or --password-file (empty if none was given)
+    fn password(&self) -> Result<Vec<u8>, Error> {
+        if let Some(password) = self.password.as_ref() {
+            return Ok(password.as_bytes().to_vec());
+        }
+
+        match self.password_file.as_ref() {
+            Some(path) => {
+                let path = Self::resolve_path(path);
+                let content = fs::read(&path).map_err(|err| Error::from(4, format!("Failed to read password file {}: {}", path.display(), err)))?;
+                let line = content.split(|byte| *byte == b'\n').next().unwrap_or_default();
+                Ok(line.strip_suffix(b"\r").unwrap_or(line).to_vec())
+            },
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_stream_size: self.max_stream_size.saturating_mul(1024 * 1024),
+            max_compression_ratio: self.max_compression_ratio,
+            max_nesting_depth: self.max_nesting_depth,
+            max_name_tree_entries: self.max_name_tree_entries,
+            max_time: Some(Duration::from_secs(self.max_time)).filter(|max_time| !max_time.is_zero()),
+        }
+    }
+
     fn verbose_log(&self, message: String) {
         if self.verbose {
             println!("{}", message);
         }
     }
 
-    /// Retrieves the content of the /Metadata stream as string if available and parsable as UTF-8
-    fn get_metadata(&self, pdf_file: &PDFFile) -> Result<String, Error> {
-        let metadata = pdf_file.get_root().metadata.ok_or(Error::from(10, String::from("No /Metadata found!")))?;
-        let resolver = pdf_file.resolver();
+    /// Reads the decoded data of the stream with the given id through our raw object layer, which enforces the resource
+    /// limits while decoding. An exceeded limit is returned as it is, other errors get the given exit codes.
+    fn read_stream(&self, file: &RawFile, id: u64, description: &str, resolve_code: u8, data_code: u8) -> Result<Vec<u8>, Error> {
+        let unless_limit = |code: u8, message: String| move |error: Error| {
+            if error.is_limit_exceeded() { error } else { Error::from(code, format!("{}: {}", message, error.message)) }
+        };
 
-        let file_stream = resolver.get(metadata).map_err(|pdf_err| Error::from(11, format!("Failed to resolve /Metadata stream ref with: {}", pdf_err.to_string())))?;
-        let metadata_bytes = (&*file_stream).data(&resolver).map_err(|pdf_err| Error::from(12, format!("Failed to get /Metadata stream data: {}", pdf_err.to_string())))?;
+        let stream = file.resolve(id).map_err(unless_limit(resolve_code, format!("Failed to resolve {} ref with", description)))?;
+        file.stream_data(&stream).map_err(unless_limit(data_code, format!("Failed to get {} data", description)))
+    }
+
+    /// Retrieves the content of the /Metadata stream as string if available and parsable as UTF-8
+    fn get_metadata(&self, file: &RawFile) -> Result<String, Error> {
+        let catalog = file.catalog()?;
+        let (metadata, _) = catalog.as_dictionary().and_then(|catalog| catalog.get("Metadata")).and_then(RawObject::as_reference).ok_or(Error::from(10, String::from("No /Metadata found!")))?;
+        let metadata_bytes = self.read_stream(file, metadata, "/Metadata stream", 11, 12)?;
 
-        String::from_utf8(metadata_bytes.to_vec()).map_err(|utf8_err| Error::from(13, format!("Failed to decode /Metadata stream a valid utf8 string: {}", utf8_err)))
+        String::from_utf8(metadata_bytes).map_err(|utf8_err| Error::from(13, format!("Failed to decode /Metadata stream a valid utf8 string: {}", utf8_err)))
     }
 
-    fn get_xml_filematcher_from_metadata(&self, content_string: &String) -> Result<FileMatcher, Error> {
-        // We could also add an XML parser, but regex chould be the easier solution at the moment
-        // First we find the matching <rdf:Description> tag, which can either be an immediately closed tag with attributes or
-        // an open tag with sub elements
-        let description_regex = Regex::new(r#"(?ms)<rdf:Description [^>]*xmlns:fx="urn:factur-x:pdfa:CrossIndustryDocument:invoice[^/>]+(/>|>.*?</rdf:Description>)"#).unwrap();
-        let filename_regex = Regex::new(r#"fx:DocumentFileName((>(?<name1>.*?)</fx:DocumentFileName>)|(="(?<name2>.*?)"))"#).unwrap();
-        
-        let description_match = description_regex.find(content_string).ok_or(Error::from(14, String::from("Missing <rdf:Description> element in /Metadata stream")))?;
-        let filename_match = filename_regex.captures(description_match.as_str()).ok_or(Error::from(15, String::from("Failed to locate fx:DocumentFileName in /Metadata stream")))?;
-        
-        let name = filename_match.name("name1").filter(|m| m.len() > 0).or_else(|| filename_match.name("name2").filter(|m| m.len() > 0)).unwrap().as_str();
-        self.verbose_log(format!("/Metadata contains following XML file name to look for: '{}'", name));
-        Ok(FileMatcher::from_name(name))
+    fn get_xml_filematcher_from_metadata(&self, content_string: &str) -> Result<FileMatcher, Error> {
+        let matcher = FileMatcher::from_metadata(content_string)?;
+        self.verbose_log(format!("/Metadata contains following XML file name to look for: {}", matcher));
+        Ok(matcher)
     }
 
     /// Returns the file matcher to use for this PDF file, which is either the
     /// passed name or if no name is passed the one from the metadata XML.
     /// If no metadata is set and we are not in strict mode it will fallback to factor-x.xml/xrechnung.xml
-    fn get_matcher(&self, pdf_file: &PDFFile) -> Result<FileMatcher, Error> {
+    fn get_matcher(&self, file: &RawFile) -> Result<FileMatcher, Error> {
         if let Some(name) = self.name.as_ref() {
             return Ok(FileMatcher::from_name(name));
         }
 
-        let matcher = self.get_metadata(pdf_file).and_then(|ref metadata| self.get_xml_filematcher_from_metadata(metadata));
+        let matcher = self.get_metadata(file).and_then(|ref metadata| self.get_xml_filematcher_from_metadata(metadata));
 
         if self.strict {
             matcher
         } else {
-            // fall back to default names
+            // fall back to default names (but never ignore an exceeded resource limit)
             matcher.or_else(|error| {
+                if error.is_limit_exceeded() {
+                    return Err(error);
+                }
                 // still print the error
                 error.print();
                 self.verbose_log(String::from("Searching for default XML files instead (factur-x.xml or xrechnung.xml)"));
@@ -148,11 +370,11 @@ impl Extract {
     }
     
     /// Returns the matching filespec for the given file matcher from the /AF array
-    fn get_af_file_spec(&self, pdf_file: &PDFFile, matcher: &FileMatcher) -> Result<(PdfString, FileSpec), Error> {
-        let af_filespecs = pdf_file.get_root().associated_files.as_ref().and_then(|af_ref| Some(af_ref.data())).ok_or(Error::from(2, String::from("No /AF Array found!")))?;
+    fn get_af_file_spec(&self, file: &RawFile, matcher: &FileMatcher) -> Result<(String, FileSpecification), Error> {
+        let af_filespecs = associated_files(file)?;
 
         let matching_filespec = af_filespecs.iter()
-            .find_map(|file_spec| matcher.matching_name(file_spec).map(|pdf_name| (pdf_name.clone(), file_spec.clone())))
+            .find_map(|file_spec| matcher.matching_name(file_spec).map(|name| (String::from(name), file_spec.clone())))
             .ok_or(Error::from(20, format!("No embedded file matching {} found in /AF array", matcher)));
 
         if self.strict {
@@ -164,27 +386,19 @@ impl Extract {
             error.print();
             self.verbose_log(String::from("Trying to extract any .xml file from /AF array"));
             af_filespecs.iter()
-                .find_map(|file_spec| FileMatcher::matching_suffix(file_spec, ".xml").map(|pdf_name| (pdf_name.clone(), file_spec.clone())))
-                .ok_or(Error::from(21, format!("No embedded .xml file found in /AF array")))
+                .find_map(|file_spec| FileMatcher::matching_suffix(file_spec, ".xml").map(|name| (String::from(name), file_spec.clone())))
+                .ok_or(Error::from(21, String::from("No embedded .xml file found in /AF array")))
         })
     }
 
     /// Used as a fallback in non-strict mode in case no /AF array exists. In that case we search all filespecs of the /EmbeddedFiles structure
-    fn get_ef_file_spec(&self, pdf_file: &PDFFile, matcher: &FileMatcher) -> Result<(PdfString, FileSpec), Error> {
-        let names = pdf_file.trailer.root.names.as_ref().ok_or(Error::from(31, String::from("names dictionary not found while looking for /EmbeddedFiles")))?;
-        let embedded_files = names.data().embedded_files.as_ref().ok_or(Error::from(32, String::from("No /EmbeddedFiles found")))?;
-        let resolver = pdf_file.resolver();
-        let mut matched_spec: Option<(PdfString, FileSpec)> = None;
-        
-        embedded_files.walk(&resolver, &mut |_pdf_str, file_spec| {
-            if matched_spec.is_none() {
-                if let Some(matching_name) = matcher.matching_name(file_spec) {
-                    matched_spec = Some((matching_name.clone(), file_spec.clone()));
-                }
-            }
-        }).map_err(|pdf_err| Error::from(33, format!("Iteration over /EmbeddedFiles failed with: {}", pdf_err)))?;
+    fn get_ef_file_spec(&self, file: &RawFile, matcher: &FileMatcher) -> Result<(String, FileSpecification), Error> {
+        let embedded_files = embedded_files(file)?;
+        self.verbose_log(format!("/EmbeddedFiles contains {} entries", embedded_files.len()));
 
-        let result = matched_spec.ok_or(Error::from(34, format!("No embedded file matching {} found in /EmbeddedFiles structure", matcher)));
+        let result = embedded_files.iter()
+            .find_map(|(_, file_spec)| matcher.matching_name(file_spec).map(|name| (String::from(name), file_spec.clone())))
+            .ok_or(Error::from(34, format!("No embedded file matching {} found in /EmbeddedFiles structure", matcher)));
         if self.strict {
             return result;
         }
@@ -193,72 +407,516 @@ impl Extract {
             error.print();
             self.verbose_log(String::from("Trying to extract any .xml file from /EmbeddedFiles structure"));
 
-            let mut matched_spec: Option<(PdfString, FileSpec)> = None;
-            embedded_files.walk(&resolver, &mut |_pdf_str, file_spec| {
-                if matched_spec.is_none() {
-                    if let Some(matching_name) = FileMatcher::matching_suffix(file_spec, ".xml") {
-                        matched_spec = Some((matching_name.clone(), file_spec.clone()));
+            embedded_files.iter()
+                .find_map(|(_, file_spec)| FileMatcher::matching_suffix(file_spec, ".xml").map(|name| (String::from(name), file_spec.clone())))
+                .ok_or(Error::from(36, String::from("No embedded .xml files found in /EmbeddedFiles structure")))
+        })
+    }
+
+
+
+
+    /// Checks that no incremental update after the /ByteRange of a signature added, replaced or removed
+    /// any attachment related object and verifies the signatures if a trust store is given.
+    /// This fails in strict mode and only prints a warning otherwise.
+    fn check_signatures(&self, file: &RawFile) -> Result<(), Error> {
+        let data = file.data;
+        let trust_store = self.trust_store.as_ref().map(|path| TrustStore::load(&Self::resolve_path(path))).transpose()?;
+
+        let signatures = signatures(file);
+        if trust_store.is_some() && signatures.is_empty() {
+            self.verbose_log(String::from("The PDF is not signed"));
+        }
+
+        for signature in signatures {
+            self.verbose_log(format!("Found signature {} covering the file up to byte {} of {}", signature.id, signature.signed_end(), data.len()));
+
+            if let Some(trust_store) = trust_store.as_ref() {
+                let verification = verify_signature(data, &signature, trust_store);
+                self.verbose_log(format!("Signed by {} at {}: {}, {}", verification.signer.as_deref().unwrap_or("unknown"), verification.signing_time.as_deref().unwrap_or("unknown time"), verification.integrity, verification.trust));
+
+                if !verification.is_valid() {
+                    let error = Error::from(52, format!("Signature {} is {}, {}", signature.id, verification.integrity, verification.trust));
+                    if self.strict {
+                        return Err(error);
                     }
+                    error.print();
                 }
-            }).map_err(|pdf_err| Error::from(35, format!("Iteration over /EmbeddedFiles failed with: {}", pdf_err)))?;
+            }
 
-            matched_spec.ok_or(Error::from(36, String::from("No embedded .xml files found in /EmbeddedFiles structure")))
-        })
+            let changes = unsigned_attachment_changes(file, &signature).map_err(|error| Error::from(51, format!("Failed to check signature {}: {}", signature.id, error.message)));
+            let error = match changes {
+                Ok(changes) if changes.is_empty() => continue,
+                Ok(changes) => {
+                    let objects: Vec<String> = changes.iter().map(|change| format!("  {}", change)).collect();
+                    Error::from(50, format!("Attachments were modified after signature {} (signed up to byte {}):\n{}", signature.id, signature.signed_end(), objects.join("\n")))
+                },
+                Err(error) => error,
+            };
+
+            if self.strict {
+                return Err(error);
+            }
+            error.print();
+        }
+
+        Ok(())
     }
 
 
+    /// The extract main function, which extracts the input file, the invoices of an e-mail input or ZIP archive or all PDF,
+    /// e-mail and ZIP files of the input directory
+    fn run(self: &Arc<Self>) -> Result<(), Error> {
+        if Self::is_mail(&self.input_path()) {
+            return self.extract_mails(&self.pdf_input, &self.output_directory());
+        }
+        if Self::has_extension(&self.pdf_input, "zip") {
+            return self.extract_archive(&self.pdf_input, &self.output_directory());
+        }
 
+        if !self.is_batch() {
+            return self.extract_contained(Target { input: self.pdf_input.clone(), entry: None, output: self.output_path(), json_output: self.json_output_path() });
+        }
 
-    /// The extract main function
-    fn extract(&self) -> Result<(), Error> {
-        let input_path = self.input_path();
-        let output_path = self.output_path();
-    
-        self.verbose_log(format!("Reading: {}", input_path.display().to_string()));
-    
-        
-        let pdf_file: PDFFile = FileOptions::cached().open(&input_path).map_err(|err| Error::from(1, format!("Failed to open {}: {}", &input_path.display().to_string(), err)))?;
-    
-        
+        let output_directory = self.output_directory();
+        let inputs = self.batch_inputs()?;
+        let count = inputs.len();
+        let mut failed = 0;
+        let mut last_error = None;
+        for input in inputs {
+            let result = if Self::is_mail(&input) {
+                self.extract_mails(&input, &output_directory)
+            } else if Self::has_extension(&input, "zip") {
+                self.extract_archive(&input, &output_directory)
+            } else {
+                self.extract_contained(Self::batch_target(input.clone(), &output_directory))
+            };
+            if let Err(error) = result {
+                eprintln!("{}: {}", input.display(), error.message);
+                failed += 1;
+                last_error = Some(error);
+            }
+        }
+
+        self.verbose_log(format!("Extracted {} of {} files", count - failed, count));
+        match last_error {
+            // Exit with the code of the last failure
+            Some(error) => Err(Error { message: format!("Failed to extract {} of {} files", failed, count), exit_code: error.exit_code }),
+            None => Ok(()),
+        }
+    }
+
+    /// Extracts a single file on its own thread, so that neither a panic nor a file exceeding --max-time stops the run.
+    /// Decoding a stream cannot be interrupted, so a thread which is out of time is abandoned and ends with the process.
+    /// The outputs and exports are written here from the result of the thread, so an abandoned thread never writes
+    /// anything (like appending to the CSV files while the next file is processed).
+    fn extract_contained(self: &Arc<Self>, target: Target) -> Result<(), Error> {
+        let extract = Arc::clone(self);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let result = Error::catch_panic(|| extract.extract(&target));
+            let _ = sender.send((target, result));
+        });
+
+        let limits = self.limits();
+        let result = match limits.max_time {
+            Some(max_time) => receiver.recv_timeout(max_time).map_err(|error| match error {
+                mpsc::RecvTimeoutError::Timeout => limits.time_error(),
+                mpsc::RecvTimeoutError::Disconnected => Error::from(130, String::from("Internal error: the extraction ended without result")),
+            }),
+            None => receiver.recv().map_err(|_| Error::from(130, String::from("Internal error: the extraction ended without result"))),
+        };
+        let (target, result) = result?;
+        let (file_name, file_bytes) = result?;
+        Error::catch_panic(|| self.write_invoice(&target, &file_name, file_bytes))
+    }
+
+    /// Extracts the invoices of every message of an e-mail input (a single .eml message, an .mbox mailbox or the cur and
+    /// new messages of a Maildir directory). A failing message does not stop the others, the run fails with the code
+    /// of the last failed message.
+    fn extract_mails(self: &Arc<Self>, input: &Path, output_directory: &Path) -> Result<(), Error> {
+        let input_path = Self::resolve_path(&input.to_path_buf());
+        let files = if input_path.is_dir() {
+            let mut files = Vec::new();
+            for directory in ["cur", "new"] {
+                let entries = fs::read_dir(input_path.join(directory)).map_err(|err| Error::from(1, format!("Failed to read directory {}: {}", input_path.join(directory).display(), err)))?;
+                files.extend(entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_file()));
+            }
+            files.sort();
+            files
+        } else {
+            vec![input_path.clone()]
+        };
+
+        let limits = self.limits();
+        let mut count = 0;
+        let mut failed = 0;
+        let mut last_error = None;
+        for path in files {
+            self.verbose_log(format!("Reading: {}", path.display()));
+            let data = match FileData::open(&path) {
+                Ok(data) => data,
+                // A message file which cannot be opened (like one moved away by the mail client) fails like a broken message
+                Err(err) => {
+                    let error = Error::from(1, format!("Failed to open {}: {}", path.display(), err));
+                    error.print();
+                    count += 1;
+                    failed += 1;
+                    last_error = Some(error);
+                    continue;
+                },
+            };
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+            // Outputs are named after the file of the message and (in a mailbox) its number
+            let messages = if Self::has_extension(&path, "mbox") { split_mbox(&data) } else { vec![&data[..]] };
+            let numbered = messages.len() > 1 || Self::has_extension(&path, "mbox");
+            for (index, message) in messages.into_iter().enumerate() {
+                let prefix = if numbered { format!("{}.{}", file_name, index + 1) } else { file_name.clone() };
+                count += 1;
+
+                let result = Error::catch_panic(|| MailMessage::parse(message, &limits))
+                    .and_then(|message| self.extract_message(&path, message, &sanitize_file_name(&prefix), output_directory));
+                if let Err(error) = result {
+                    let message = if numbered { format!(" (message {})", index + 1) } else { String::new() };
+                    eprintln!("{}{}: {}", path.display(), message, error.message);
+                    failed += 1;
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        self.verbose_log(format!("Extracted the invoices of {} of {} messages", count - failed, count));
+        match last_error {
+            Some(error) => Err(Error { message: format!("Failed to extract the invoices of {} of {} messages of {}", failed, count, input.display()), exit_code: error.exit_code }),
+            None => Ok(()),
+        }
+    }
+
+    /// Extracts every PDF and XML attachment of the message and reports each invoice with the message-id and the sender.
+    /// Mails often carry other PDFs (like terms and conditions) as well, so in lenient mode an attachment without invoice
+    /// is only reported as warning as long as another attachment of the message contains an invoice.
+    fn extract_message(self: &Arc<Self>, input: &Path, message: MailMessage, prefix: &str, output_directory: &Path) -> Result<(), Error> {
+        let description = message.description();
+        self.verbose_log(format!("Found {} with {} attachments", description, message.attachments.len()));
+
+        let entries: Vec<Entry> = message.attachments.into_iter().enumerate()
+            .filter_map(|(index, attachment)| mail_entry(index, attachment))
+            .collect();
+        if entries.is_empty() {
+            return Err(Error::from(72, format!("No PDF or XML attachment found in {}", description)));
+        }
+
+        let mut names = HashSet::new();
+        let mut extracted = 0;
+        let mut last_error = None;
+        for (index, entry) in entries.into_iter().enumerate() {
+            // Attachments with the same name get their number to keep their outputs apart
+            let mut output_name = format!("{}.{}", prefix, sanitize_file_name(&entry.name));
+            if !names.insert(output_name.clone()) {
+                output_name = format!("{}.{}.{}", prefix, index + 1, sanitize_file_name(&entry.name));
+            }
+            if entry.file_type == FileType::Pdf {
+                output_name.push_str(".xml");
+            }
+
+            let output = output_directory.join(output_name);
+            let json_output = output.with_extension("json");
+            let name = entry.name.clone();
+            match self.extract_contained(Target { input: input.to_path_buf(), entry: Some(entry), output: output.clone(), json_output }) {
+                Ok(()) => {
+                    println!("Extracted the invoice of {} ({}) to {}", name, description, output.display());
+                    extracted += 1;
+                },
+                Err(error) => {
+                    eprintln!("{}: {}", name, error.message);
+                    last_error = Some(error);
+                },
+            }
+        }
+
+        match last_error {
+            Some(error) if self.strict || extracted == 0 => Err(Error { message: format!("Failed to extract the invoices of {}", description), exit_code: error.exit_code }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Extracts every PDF and XML file of a ZIP archive into a directory named after the archive (bundle.zip to bundle/),
+    /// which mirrors the directories of the archive. Other files (including nested archives) are skipped. A failing
+    /// entry does not stop the others, the run fails with the code of the last failed entry.
+    fn extract_archive(self: &Arc<Self>, input: &Path, output_directory: &Path) -> Result<(), Error> {
+        let input_path = Self::resolve_path(&input.to_path_buf());
+        self.verbose_log(format!("Reading: {}", input_path.display()));
+        let data = FileData::open(&input_path).map_err(|err| Error::from(1, format!("Failed to open {}: {}", input_path.display(), err)))?;
+        let mut archive = Error::catch_panic(|| Archive::open(&data, self.limits()))?;
+        let archive_directory = output_directory.join(input_path.file_stem().unwrap_or_default());
+        self.verbose_log(format!("The archive contains {} entries", archive.len()));
+
+        let mut extracted = 0;
+        let mut failed = 0;
+        let mut last_error = None;
+        for index in 0..archive.len() {
+            let name = archive.name(index).unwrap_or_default().to_string();
+            let result = Error::catch_panic(|| archive.entry(index)).and_then(|entry| {
+                let Some(entry) = entry else { return Ok(()); };
+                let entry_name = entry.path.to_string_lossy().to_string();
+                let Some(file_type) = invoice_file_type(&entry_name, &entry.data) else {
+                    self.verbose_log(format!("Skipping {} ({})", entry_name, FileType::detect(&entry.data)));
+                    return Ok(());
+                };
+
+                let mut output = archive_directory.join(&entry.path);
+                if file_type == FileType::Pdf {
+                    let mut path = output.into_os_string();
+                    path.push(".xml");
+                    output = PathBuf::from(path);
+                }
+                if let Some(directory) = output.parent() {
+                    fs::create_dir_all(directory).map_err(|err| Error::from(2, format!("Failed to create {}: {}", directory.display(), err)))?;
+                }
+
+                let json_output = output.with_extension("json");
+                self.extract_contained(Target { input: input_path.clone(), entry: Some(Entry { name: entry_name, data: entry.data, file_type }), output, json_output })?;
+                extracted += 1;
+                Ok(())
+            });
+
+            if let Err(error) = result {
+                eprintln!("{}: {}: {}", input.display(), name, error.message);
+                failed += 1;
+                last_error = Some(error);
+            }
+        }
+
+        if extracted == 0 && last_error.is_none() {
+            return Err(Error::from(76, format!("No PDF or XML file found in {}", input.display())));
+        }
+
+        self.verbose_log(format!("Extracted {} of {} files of {}", extracted, extracted + failed, input.display()));
+        match last_error {
+            Some(error) => Err(Error { message: format!("Failed to extract {} files of {}", failed, input.display()), exit_code: error.exit_code }),
+            None => Ok(()),
+        }
+    }
+
+    /// Finds the invoice of a single input file or of the PDF or XML file contained in it and returns its name and content
+    fn extract(&self, target: &Target) -> Result<(String, Vec<u8>), Error> {
+        match target.entry.as_ref() {
+            Some(entry) if entry.file_type == FileType::Xml => {
+                self.verbose_log(format!("Taking the XML file {} as it is", entry.name));
+                Ok((entry.name.clone(), entry.data.clone()))
+            },
+            Some(entry) => {
+                self.verbose_log(format!("Reading: {}", entry.name));
+                self.extract_pdf(&entry.data, &entry.name)
+            },
+            None => {
+                let input_path = &Self::resolve_path(&target.input);
+                self.verbose_log(format!("Reading: {}", input_path.display()));
+
+                // The file is mapped instead of read, so only the parts we parse are loaded from disk
+                let data = FileData::open(input_path).map_err(|err| Error::from(1, format!("Failed to open {}: {}", &input_path.display().to_string(), err)))?;
+                self.extract_pdf(&data, &input_path.display().to_string())
+            },
+        }
+    }
+
+    /// Finds the invoice in the given PDF data and returns its name and content
+    fn extract_pdf(&self, data: &[u8], input_name: &str) -> Result<(String, Vec<u8>), Error> {
+        let limits = self.limits();
+        if self.verbose {
+            let revisions = revisions(data).map(|revisions| revisions.len()).unwrap_or(1);
+            if revisions > 1 {
+                self.verbose_log(format!("The file contains {} revisions (incremental updates)", revisions));
+            }
+        }
+
+        // Cut off all incremental updates after the requested revision
+        let data = match self.revision {
+            Some(number) => {
+                let data = revision_data(data, number)?;
+                self.verbose_log(format!("Using revision {} ({} bytes)", number, data.len()));
+                data
+            },
+            None => data,
+        };
+
+        // Files with broken cross-reference sections are read through the objects found by scanning the file
+        let recover = self.recover || needs_recovery(data);
+        if recover {
+            eprintln!("The cross-reference table of {} is broken, it has been recovered by scanning the file for objects", input_name);
+        }
+
+        let mut file = if recover { RawFile::recover(data, limits)? } else { RawFile::open(data)? };
+        file.set_limits(limits);
+        file.set_password(&self.password()?)?;
+        if let Some(handler) = file.security_handler() {
+            self.verbose_log(format!("The file is encrypted with {} (opened with the {} password)", handler.stream_method, handler.authenticated));
+        }
+
+        // Attachments must not be touched by incremental updates after a signature
+        self.check_signatures(&file)?;
+
+        match self.get_attachment(&file) {
+            Ok(attachment) => Ok(attachment),
+            Err(error) if !self.strict && !error.is_limit_exceeded() => {
+                error.print();
+                self.carve_attachment(&file)
+            },
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes the extracted invoice in the requested formats and exports it
+    fn write_invoice(&self, target: &Target, file_name: &str, file_bytes: Vec<u8>) -> Result<(), Error> {
+        if self.format.contains(&Format::Xml) {
+            self.write_output(&target.output, &file_bytes)?;
+        }
+
+        // Only parse the invoice if any output needs the invoice model
+        if self.format.contains(&Format::InvoiceJson) || self.csv_lines.is_some() || self.csv_vat.is_some() || self.datev.is_some() {
+            let xml = String::from_utf8(file_bytes).map_err(|utf8_err| Error::from(60, format!("Failed to decode {} as utf8: {}", file_name, utf8_err)))?;
+            let invoice = Invoice::parse(&xml)?;
+
+            if self.format.contains(&Format::InvoiceJson) {
+                self.write_output(&target.json_output, invoice.to_json()?.as_bytes())?;
+            }
+
+            self.export_csv(&invoice, &target.source())?;
+            self.export_datev(&invoice)?;
+        }
+
+        Ok(())
+    }
+
+    /// Finds the invoice attachment through the /AF array (with /EmbeddedFiles as fallback) and returns its name and content
+    fn get_attachment(&self, file: &RawFile) -> Result<(String, Vec<u8>), Error> {
         // Helper to match the attachment name
-        let matcher = self.get_matcher(&pdf_file)?;
+        let matcher = self.get_matcher(file)?;
 
         
         // Get the matched filename and its filespec from the /AF array (with /EmbeddedFiles as fallback)
-        let (file_name, file_spec) = self.get_af_file_spec(&pdf_file, &matcher).or_else(|error| {
+        let (file_name, file_spec) = self.get_af_file_spec(file, &matcher).or_else(|error| {
             if self.strict {
                 Err(error)
             } else {
                 error.print();
                 self.verbose_log(String::from("Retrying in /EmbeddedFiles"));
-                self.get_ef_file_spec(&pdf_file, &matcher)
+                self.get_ef_file_spec(file, &matcher)
             }
         })?;
 
         self.verbose_log(format!("Found {:?}", file_name));
     
-        // Extract the /EF entry
-        let ef_entry = file_spec.ef.as_ref().ok_or(Error::from(40, format!("Missing /EF in filespec of {}", file_name.to_string_lossy())))?;
-    
         // Extract the /F or /UF reference from the /EF entry
-        let file_ref = ef_entry.f.or_else(|| ef_entry.uf).ok_or(Error::from(41, String::from("Missing /F or /UF reference in /EF entry")))?;
-    
-        let resolver = pdf_file.resolver();
-    
-        // Resolve the ref into a Stream<EmbeddedFile>
-        let file_stream = resolver.get(file_ref).map_err(|pdf_err| Error::from(42, format!("Failed to resolve file ref with: {}", pdf_err.to_string())))?;
+        if file_spec.ef.is_none() {
+            return Err(Error::from(40, format!("Missing /EF in filespec of {}", file_name)));
+        }
+        let stream_id = file_spec.stream_id().ok_or(Error::from(41, String::from("Missing /F or /UF reference in /EF entry")))?;
     
-        // Read the binary file data from the stream
-        let file_bytes = (&*file_stream).data(&resolver).map_err(|pdf_err| Error::from(43, format!("Failed to get stream data: {}", pdf_err.to_string())))?;
-        
-        
-        // Write the file
-        self.verbose_log(format!("Writing: {}", output_path.display().to_string()));
-        
-        let mut file = fs::OpenOptions::new().write(true).truncate(true).create(true).open(&output_path).map_err(|err| Error::from(2,format!("Failed to open {}: {}", &output_path.display().to_string(), err)))?;
-        file.write_all(&*file_bytes).map_err(|err| Error::from(3, format!("Failed to write {}: {}", &output_path.display().to_string(), err)))
+        // Read the binary file data from the embedded file stream
+        let file_bytes = self.read_stream(file, stream_id, "file", 42, 43)?;
+        Ok((file_name, file_bytes))
+    }
+
+    /// Last resort in lenient mode if the file specifications are missing or broken: carves the invoice XML out of any stream
+    /// of the file. The result is reported as warning, as such a file is not a conformant e-invoice.
+    fn carve_attachment(&self, file: &RawFile) -> Result<(String, Vec<u8>), Error> {
+        self.verbose_log(String::from("Searching all streams of the file for an invoice XML"));
+        let candidates = carve_invoices(file);
+        for candidate in &candidates {
+            self.verbose_log(format!("Found <{}> in stream {} 0 R ({} bytes, confidence {}%)", candidate.root, candidate.id, candidate.xml.len(), candidate.confidence));
+        }
+
+        let best = candidates.into_iter().next().ok_or(Error::from(44, String::from("No invoice XML found in any stream of the file")))?;
+        eprintln!("Using the invoice XML carved from stream {} 0 R (<{}>, confidence {}%). The PDF is not a conformant e-invoice, as the XML is not attached properly!", best.id, best.root, best.confidence);
+        Ok((format!("stream {} 0 R", best.id), best.xml))
+    }
+
+    /// Appends the invoice to the requested CSV files
+    fn export_csv(&self, invoice: &Invoice, source: &str) -> Result<(), Error> {
+        let options = CsvOptions {
+            delimiter: self.csv_delimiter,
+            decimal_separator: self.csv_decimal_separator,
+            bom: self.csv_bom,
+        };
+
+        for (path, table) in [(&self.csv_lines, CsvTable::Lines), (&self.csv_vat, CsvTable::VatBreakdown)] {
+            if let Some(path) = path {
+                let path = Self::resolve_path(path);
+                self.verbose_log(format!("Appending to: {}", path.display()));
+                CsvWriter::append(&path, table, options)?.write_invoice(invoice, source)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Appends the invoice to the requested DATEV Buchungsstapel
+    fn export_datev(&self, invoice: &Invoice) -> Result<(), Error> {
+        let (Some(path), Some(mapping_path)) = (self.datev.as_ref(), self.datev_mapping.as_ref()) else {
+            return Ok(());
+        };
+
+        let mapping = DatevMapping::load(&Self::resolve_path(mapping_path))?;
+        let options = DatevOptions {
+            consultant: self.datev_consultant.unwrap_or_default(),
+            client: self.datev_client.unwrap_or_default(),
+            fiscal_year_start: self.datev_fiscal_year_start.clone(),
+            account_length: self.datev_account_length,
+        };
+
+        let path = Self::resolve_path(path);
+        self.verbose_log(format!("Appending to: {}", path.display()));
+        DatevWriter::append(&path, &options, invoice)?.write_invoice(invoice, &mapping)
+    }
+
+    fn write_output(&self, output_path: &std::path::Path, bytes: &[u8]) -> Result<(), Error> {
+        self.verbose_log(format!("Writing: {}", output_path.display()));
+
+        let mut file = fs::OpenOptions::new().write(true).truncate(true).create(true).open(output_path).map_err(|err| Error::from(2,format!("Failed to open {}: {}", output_path.display(), err)))?;
+        file.write_all(bytes).map_err(|err| Error::from(3, format!("Failed to write {}: {}", output_path.display(), err)))
     }
 
 
 }
 
+
+/// Turns an attachment of an e-mail into an entry to extract if it is a PDF or an XML file (by its content type, its
+/// file name or its content). Attachments without name are named after their position in the message.
+fn mail_entry(index: usize, attachment: MailAttachment) -> Option<Entry> {
+    let MailAttachment { name, content_type, data } = attachment;
+    let file_type = if content_type == "application/pdf" {
+        FileType::Pdf
+    } else if content_type.ends_with("/xml") || content_type.ends_with("+xml") {
+        FileType::Xml
+    } else {
+        invoice_file_type(name.as_deref().unwrap_or_default(), &data)?
+    };
+
+    let name = name.unwrap_or_else(|| format!("attachment{}.{}", index + 1, if file_type == FileType::Pdf { "pdf" } else { "xml" }));
+    Some(Entry { name, data, file_type })
+}
+
+/// Whether the file is a PDF (by its extension or content) or an XML file (by its extension), which are the files to extract
+fn invoice_file_type(name: &str, data: &[u8]) -> Option<FileType> {
+    let extension = name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+    if extension.as_deref() == Some("pdf") || FileType::detect(data) == FileType::Pdf {
+        Some(FileType::Pdf)
+    } else if extension.as_deref() == Some("xml") {
+        Some(FileType::Xml)
+    } else {
+        None
+    }
+}
+
+/// Makes a file name taken from an e-mail safe to write: only the last component of a path is kept and characters which
+/// are invalid on Windows are replaced, so the name can neither leave the output directory nor fail to be created
+fn sanitize_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name.chars()
+        .map(|c| if c.is_control() || matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() { String::from("attachment") } else { name.to_string() }
+}
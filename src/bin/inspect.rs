@@ -1,10 +1,71 @@
+use std::{cell::RefCell, collections::HashSet, fs::OpenOptions, io::Write, path::{Path, PathBuf}, process::ExitCode, rc::Rc, sync::Arc};
+use zugferd::{associated_files, decode_stages, embedded_files, needs_recovery, xmp_dates, EmbeddedFileParams, FileSpecification, PdfDate, revision_data, revisions, signatures, unsigned_attachment_changes, verify_signature, Error, FileData, FileType, FilterStage, Limits, RawDictionary, RawFile, RawObject, Revision, SectionKind, Signature, SignatureVerification, TrustStore, UnsignedChange, XRefEntry};
+use clap::{Parser, ValueEnum};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{json, Map, Value};
+use rustyline::{completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter, history::DefaultHistory, validate::Validator, Context, Editor, Helper};
 
-use std::{fs::OpenOptions, io::Write, path::Path, process::ExitCode, sync::Arc};
-use pdf::{any::AnySync, file::{NoLog, Storage, StorageResolver, SyncCache}, object::{EmbeddedFile, ParseOptions, PlainRef, Resolve, Stream}, primitive::{Dictionary, Primitive}};
-use zugferd::Error;
-use clap::Parser;
 
 
+/// Output formats of the printed objects
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// PDF like syntax for reading
+    Text,
+    /// JSON document with all requested outputs for scripts (the interactive shell is not affected)
+    Json,
+}
+
+
+/// Which data of a stream to print or export
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StreamMode {
+    /// The data with all filters applied
+    Decoded,
+    /// The still encoded data as stored in the file
+    Raw,
+}
+
+
+/// How to show the content of streams
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum View {
+    /// Text for text data (like XML) and a hexdump for binary data
+    Auto,
+    Text,
+    Hex,
+}
+
+/// Byte range of the stream content to show (end exclusive)
+#[derive(Clone, Copy)]
+pub struct ByteRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+/// Parses a byte range like `256..512`, `0x100..`, `..1024` or `0x100+64` (start and length)
+fn parse_range(text: &str) -> Result<ByteRange, String> {
+    let number = |text: &str| -> Result<usize, String> {
+        let text = text.trim();
+        match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => text.parse::<usize>(),
+        }.map_err(|_err| format!("'{}' is not a valid offset", text))
+    };
+
+    if let Some((start, end)) = text.split_once("..") {
+        let start = if start.trim().is_empty() { 0 } else { number(start)? };
+        let end = if end.trim().is_empty() { None } else { Some(number(end)?) };
+        Ok(ByteRange { start, end })
+    } else if let Some((start, length)) = text.split_once('+') {
+        let start = number(start)?;
+        let end = start.checked_add(number(length)?).ok_or(format!("'{}' is not a valid offset", text.trim()))?;
+        Ok(ByteRange { start, end: Some(end) })
+    } else {
+        Err(String::from("expected START..END, START.., ..END or START+LENGTH"))
+    }
+}
+
 
 // Command line args
 #[derive(Parser)]
@@ -13,17 +74,97 @@ pub struct Args {
     /// PDF input file
     pdf_input: std::path::PathBuf,
 
-    /// resolve the ref with the given id (separate by comma if passing multiple)
+    /// resolve the ref with the given id or reference like '12 1 R' (separate by comma if passing multiple)
     #[arg(short='r', long="ref")]
     references: Option<String>,
 
-    /// print the content of the stream(s) behind the given ref(s) to the console
+    /// resolve the given path expression(s) like '/Root/Names/EmbeddedFiles', '/Root/AF[0]/EF/F' or '12 1 R/Type' (separate by comma if passing multiple)
+    #[arg(short, long)]
+    query: Option<String>,
+
+    /// print the content of the stream(s) behind the given ref(s) or path(s) to the console
     #[arg(short, long)]
     print: Option<String>,
 
-    /// export the raw stream behind the given ref(s) and write it to a new file (next to the input PDF)
+    /// export the raw stream behind the given ref(s) or path(s) and write it to a new file (next to the input PDF)
     #[arg(short, long)]
     export: Option<String>,
+
+    /// whether to print and export the decoded or the raw (still encoded) stream data
+    #[arg(short='m', long, value_enum, default_value_t=StreamMode::Decoded)]
+    stream_mode: StreamMode,
+
+    /// how to show the content of printed streams
+    #[arg(long, value_enum, default_value_t=View::Auto)]
+    view: View,
+
+    /// only print the given byte range of the stream(s) like 256..512, 0x100.., ..1024 or 0x100+64
+    #[arg(long, value_parser=parse_range)]
+    range: Option<ByteRange>,
+
+    /// show the /Filter chain with its /DecodeParms and the output size of each stage for the stream(s) behind the given ref(s) or path(s)
+    #[arg(long)]
+    filters: Option<String>,
+
+    /// list all revisions (the original document and its incremental updates) with the objects changed by each of them
+    #[arg(long, default_value_t=false)]
+    revisions: bool,
+
+    /// look at the document as it was at the given revision (1 = original document, see --revisions)
+    #[arg(long)]
+    revision: Option<usize>,
+
+    /// rebuild the cross-reference table by scanning the file for objects (done automatically if the cross-reference sections are broken)
+    #[arg(long, default_value_t=false)]
+    recover: bool,
+
+    /// list the digital signatures with signer, signing time, integrity and whether they cover the whole file
+    #[arg(long, default_value_t=false)]
+    signatures: bool,
+
+    /// directory with PEM certificates (.pem, .crt, .cer) to trust as issuers of the signer certificates for --signatures
+    #[arg(long, requires="signatures")]
+    trust_store: Option<PathBuf>,
+
+    /// password of encrypted PDFs (the user or the owner password, default: empty password)
+    #[arg(long, conflicts_with="password_file")]
+    password: Option<String>,
+
+    /// read the password of encrypted PDFs from the first line of the given file
+    #[arg(long)]
+    password_file: Option<PathBuf>,
+
+    /// show the encryption dictionary: security handler, algorithms, key length, permissions and the password used
+    #[arg(long, default_value_t=false)]
+    encryption: bool,
+
+    /// list the embedded files of /AF and /EmbeddedFiles with MIME type, size and dates, and the dates of the document (/Info and XMP metadata)
+    #[arg(short, long, default_value_t=false)]
+    attachments: bool,
+
+    /// list all objects of the cross-reference table(s) and xref streams including objects inside object streams
+    #[arg(short, long, default_value_t=false)]
+    xref: bool,
+
+    /// print the object tree starting at /Root (or the given ref or path) with all references resolved recursively
+    #[arg(short, long, num_args=0..=1, default_missing_value="/Root")]
+    tree: Option<String>,
+
+    /// maximum number of references to follow for --tree (default: unlimited)
+    #[arg(short, long, requires="tree")]
+    depth: Option<usize>,
+
+    /// output format of the catalog, resolved refs, queries, trees, printed streams and the xref listing
+    #[arg(short, long, value_enum, default_value_t=Format::Text)]
+    format: Format,
+
+    /// include the base64 encoded stream data (see --stream-mode) of all streams in the JSON output
+    #[arg(long, default_value_t=false)]
+    data: bool,
+
+    /// start an interactive shell to navigate the document (type 'help' inside the shell for a list of commands)
+    #[arg(short, long, default_value_t=false)]
+    interactive: bool,
 }
 
 
@@ -35,130 +176,1221 @@ impl Args {
     fn resolve_path(path: &std::path::PathBuf) -> std::path::PathBuf {
         // Resolve to absolute path if necessary
         if path.is_relative() {
-            std::env::current_dir().unwrap().join(path)
+            std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.clone())
         } else {
             path.clone()
         }
     }
+
+    /// Returns the password from --password or --password-file (empty if none was given)
+    fn password(&self) -> Result<Vec<u8>, Error> {
+        if let Some(password) = self.password.as_ref() {
+            return Ok(password.as_bytes().to_vec());
+        }
+
+        match self.password_file.as_ref() {
+            Some(path) => {
+                let path = Self::resolve_path(path);
+                let content = std::fs::read(&path).map_err(|err| Error::from(16, format!("Failed to read password file {:?} with: {}", path, err)))?;
+                let line = content.split(|byte| *byte == b'\n').next().unwrap_or_default();
+                Ok(line.strip_suffix(b"\r").unwrap_or(line).to_vec())
+            },
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+
+/// A reference to an indirect object
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct PlainRef {
+    id: u64,
+    gen: u16,
+}
+
+impl PlainRef {
+    /// The reference of a reference object (None for all other objects)
+    fn of(object: &RawObject) -> Option<PlainRef> {
+        object.as_reference().map(|(id, gen)| PlainRef { id, gen })
+    }
+}
+
+impl From<PlainRef> for RawObject {
+    fn from(plain_ref: PlainRef) -> RawObject {
+        RawObject::Reference(plain_ref.id, plain_ref.gen)
+    }
+}
+
+
+/// A single step of a path expression
+#[derive(Clone, Debug)]
+enum PathSegment {
+    /// Dictionary key (also applies to the info dictionary of streams)
+    Key(String),
+    /// Array index
+    Index(usize),
+}
+
+impl std::fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Key(key) => write!(f, "/{}", key),
+            PathSegment::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+/// A path expression like `/Root/AF[0]/EF/F`, which walks from the trailer through dictionary keys and array indexes.
+/// The path may also start at an explicit reference (`12 1 R/EF/F`) or a plain object id (`12` is short for `12 0 R`).
+/// References are resolved automatically on each step.
+struct ObjectPath {
+    start: Option<PlainRef>,
+    segments: Vec<PathSegment>,
+}
+
+impl ObjectPath {
+    fn parse(expression: &str) -> Result<ObjectPath, Error> {
+        let invalid = |reason: &str| Error::from(12, format!("Invalid path expression '{}': {}", expression, reason));
+        let is_separator = |c: char| c == '/' || c == '[';
+
+        // Everything before the first segment is the optional start reference
+        let expression_trimmed = expression.trim();
+        let (prefix, mut rest) = expression_trimmed.split_at(expression_trimmed.find(is_separator).unwrap_or(expression_trimmed.len()));
+        let start = match prefix.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [] => None,
+            [id] => Some(PlainRef { id: id.parse().map_err(|_err| invalid("object id must be an integer"))?, gen: 0 }),
+            [id, gen, "R"] => Some(PlainRef {
+                id: id.parse().map_err(|_err| invalid("object id must be an integer"))?,
+                gen: gen.parse().map_err(|_err| invalid("generation must be an integer"))?,
+            }),
+            _ => return Err(invalid("expected an object id or a reference like '12 0 R' before the first key")),
+        };
+
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(tail) = rest.strip_prefix('/') {
+                let end = tail.find(is_separator).unwrap_or(tail.len());
+                if end == 0 && !tail.is_empty() {
+                    return Err(invalid("empty key"));
+                } else if end > 0 {
+                    segments.push(PathSegment::Key(String::from(&tail[..end])));
+                }
+                rest = &tail[end..];
+            } else if let Some(tail) = rest.strip_prefix('[') {
+                let end = tail.find(']').ok_or(invalid("missing ']'"))?;
+                let index = tail[..end].trim().parse::<usize>().map_err(|_err| invalid("array index must be a non-negative integer"))?;
+                segments.push(PathSegment::Index(index));
+                rest = &tail[end + 1..];
+            } else {
+                return Err(invalid("expected '/' or '[' after ']'"));
+            }
+        }
+
+        Ok(ObjectPath { start, segments })
+    }
 }
 
 
 /// This struct will hold the most important data structures in one place and provides methods
 /// for easier navigation of the data structure.
 struct Inspector<'a> {
-    storage: Storage<Vec<u8>, Arc<SyncCache<PlainRef, Result<AnySync, Arc<pdf::PdfError>>>>, Arc<SyncCache<PlainRef, Result<Arc<[u8]>, Arc<pdf::PdfError>>>>, NoLog>,
-    trailer_dict: Dictionary,
-    resolver: Option<StorageResolver<'a, Vec<u8>, Arc<SyncCache<PlainRef, Result<AnySync, Arc<pdf::PdfError>>>>, Arc<SyncCache<PlainRef, Result<Arc<[u8]>, Arc<pdf::PdfError>>>>, NoLog>>
+    /// The document opened with our own raw object layer
+    file: RawFile<'a>,
+    trailer_dict: RawDictionary,
+    stream_mode: StreamMode,
 }
 
 
 impl<'a> Inspector<'a> {
-    /// The (incomplete) constructor. Because of the borrow checker, we cannot initialize the resolver inside the constructor as there is no way 
-    /// to initialize the resolver in a way to reference the storage field inside the struct itself.
-    fn new(path: &Path) -> Result<Self, Error> {
-        let backend_data = std::fs::read(&path).map_err(|err| Error::from(1, format!("Failed to open {:?} with: {}", path, err)))?;
-        let mut storage = Storage::with_cache(backend_data, ParseOptions::strict(), SyncCache::new(), SyncCache::new(), NoLog)
-            .map_err(|err| Error::from(2, format!("Failed parse {:?} with: {}", path, err)))?;
-
-        let dict = storage.load_storage_and_trailer_password(b"").map_err(|err| Error::from(3, format!("Failed to load trailer dictionary with: {}", err)))?;
-
-        Ok(Inspector {
-            storage: storage,
-            trailer_dict: dict,
-            resolver: None,
-        })
-    }
-
-    /// 2. part of constructor - must be called before calling any other methods of this class
-    /// sad that there seems to be no better way of handling this
-    fn with_resolver(&'a mut self) -> &'a Self {
-        // FIXME: The StorageResolver::new() call is the only reason, why we must use a modified version of pdf-rs...
-        self.resolver = Some(StorageResolver::new(&self.storage));
-        self
+    fn new(file: RawFile<'a>, stream_mode: StreamMode) -> Result<Self, Error> {
+        let trailer_dict = file.xref.trailer().cloned().ok_or(Error::from(3, String::from("Failed to load trailer dictionary")))?;
+        Ok(Inspector { file, trailer_dict, stream_mode })
     }
 
-
-    /// Resolve reference to primitive if it is a reference, otherwise simply return a copy to it
-    fn resolve_if_ref(&self, primitive: &Primitive) -> Result<Primitive, Error> {
-        match primitive {
-            Primitive::Reference(reference) => self.resolve(reference),
-            _ => Ok(primitive.clone())
+    /// Resolve reference to object if it is a reference, otherwise simply return a copy to it
+    fn resolve_if_ref(&self, object: &RawObject) -> Result<RawObject, Error> {
+        match object {
+            RawObject::Reference(id, gen) => self.resolve(&PlainRef { id: *id, gen: *gen }),
+            _ => Ok(object.clone())
         }
     }
 
-    fn resolve(&self, plain_ref: &PlainRef) -> Result<Primitive, Error> {
-        self.resolver.as_ref().unwrap().resolve(*plain_ref).map_err(|err| Error::from(4, format!("Failed to resolve reference {} with: {}", plain_ref.format(), err)))
+    fn resolve(&self, plain_ref: &PlainRef) -> Result<RawObject, Error> {
+        self.file.resolve_ref(plain_ref.id, plain_ref.gen).map_err(|err| Error::from(4, format!("Failed to resolve reference {} with: {}", plain_ref.format(), err.message)))
     }
 
     /// Returns the /Root dictionary
-    fn get_root(&self) -> Result<Dictionary, Error> {
+    fn get_root(&self) -> Result<RawDictionary, Error> {
         let root = self.trailer_dict.get("Root").ok_or(Error::from(5, String::from("/Root not found!")))?;
         match self.resolve_if_ref(root)? {
-            Primitive::Dictionary(dict) => Ok(dict),
+            RawObject::Dictionary(dict) => Ok(dict),
             _ => Err(Error::from(6, String::from("Failed to resolve /Root into a dictionary")))
         }
     }
 
-    /// Resolves a plain_ref into the content bytes of a PDF stream
-    fn resolve_stream(&self, plain_ref: &PlainRef) -> Result<Arc<[u8]>, Error> {
-        match self.resolve(&plain_ref)? {
-            Primitive::Stream(pdf_stream) => {
-                let file_stream = Stream::<EmbeddedFile>::from_stream(pdf_stream, self.resolver.as_ref().unwrap())
-                    .map_err(|err| Error::from(7, format!("Stream<EmbeddedFile> conversion failed for {} with: {}", plain_ref.format(), err)))?;
+    /// Returns the raw (still encoded) or decoded data of any stream depending on the stream mode
+    fn stream_data(&self, plain_ref: &PlainRef) -> Result<Arc<[u8]>, Error> {
+        let object = self.resolve(plain_ref)?;
+        match self.stream_mode {
+            StreamMode::Raw => self.file.decrypted_stream_data(&object).map(Arc::from),
+            StreamMode::Decoded => self.file.stream_data(&object).map(Arc::from),
+        }
+    }
+
+    /// Decodes the stream step by step and returns the length of the encoded data and the output of each filter
+    fn filter_stages(&self, plain_ref: &PlainRef) -> Result<(usize, Vec<FilterStage>), Error> {
+        let object = self.resolve(plain_ref)?;
+        let data = self.file.decrypted_stream_data(&object)?;
+        Ok((data.len(), decode_stages(&data, &self.file.filter_chain(&object)?)))
+    }
+
+
+    /// Evaluates the path expression and returns the resulting object together with the reference it has been resolved
+    /// from (None if the result is a direct object or the trailer)
+    fn query(&self, expression: &str) -> Result<(Option<PlainRef>, RawObject), Error> {
+        let path = ObjectPath::parse(expression)?;
+        match path.start {
+            Some(plain_ref) => self.walk(Some(plain_ref), self.resolve(&plain_ref)?, &path.segments, expression),
+            None => self.walk(None, RawObject::Dictionary(self.trailer_dict.clone()), &path.segments, expression),
+        }
+    }
+
+    /// Follows the path segments starting at the given object (which has been resolved from `reference`)
+    fn walk(&self, mut reference: Option<PlainRef>, mut current: RawObject, segments: &[PathSegment], expression: &str) -> Result<(Option<PlainRef>, RawObject), Error> {
+        for segment in segments {
+            let next = match (segment, &current) {
+                (PathSegment::Key(key), RawObject::Dictionary(dict) | RawObject::Stream(dict, _)) => dict.get(key.as_str()),
+                (PathSegment::Index(index), RawObject::Array(vec)) => vec.get(*index),
+                _ => None,
+            }.cloned().ok_or(Error::from(13, format!("Path '{}': {} not found", expression.trim(), segment)))?;
 
-                file_stream.data(self.resolver.as_ref().unwrap()).map_err(|err| Error::from(8, format!("Failed to resolve stream {} with: {}", plain_ref.format(), err)))
+            reference = PlainRef::of(&next);
+            current = self.resolve_if_ref(&next)?;
+        }
+
+        Ok((reference, current))
+    }
+
+    /// Converts the object into JSON for scripts. Names are written as "/Name" strings, strings as object with the decoded
+    /// text and the raw bytes (hex), references as {"id", "gen"} and streams as {"stream": dict, "length": ...} with the
+    /// base64 encoded decoded data if `data` is set (`reference` is the reference the stream has been resolved from).
+    /// References are resolved until `depth` references have been followed and the result is added as "object" to the
+    /// reference. Already visited references are marked with "visited": true instead.
+    fn to_json(&self, object: &RawObject, reference: Option<PlainRef>, depth: usize, data: bool, visited: &mut HashSet<PlainRef>) -> Value {
+        match object {
+            RawObject::Null => Value::Null,
+            RawObject::Integer(value) => Value::from(*value),
+            RawObject::Real(value) => Value::from(*value),
+            RawObject::Boolean(value) => Value::from(*value),
+            RawObject::Name(name) => Value::from(format!("/{}", name)),
+            RawObject::String(bytes) => json!({
+                "text": object.as_text(),
+                "bytes": bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>(),
+            }),
+            RawObject::Array(vec) => Value::Array(vec.iter().map(|element| self.to_json(element, None, depth, data, visited)).collect()),
+            RawObject::Dictionary(dict) => Value::Object(dict.iter().map(|(name, value)| (name.to_string(), self.to_json(value, None, depth, data, visited))).collect()),
+            RawObject::Stream(dict, range) => {
+                let mut stream = Map::new();
+                stream.insert(String::from("stream"), Value::Object(dict.iter().map(|(name, value)| (name.to_string(), self.to_json(value, None, depth, data, visited))).collect()));
+                stream.insert(String::from("length"), Value::from(range.len()));
+
+                if let Some(plain_ref) = reference.filter(|_| data) {
+                    match self.stream_data(&plain_ref) {
+                        Ok(bytes) => stream.insert(String::from("data"), Value::from(BASE64_STANDARD.encode(bytes.as_ref()))),
+                        Err(error) => stream.insert(String::from("error"), Value::from(error.message)),
+                    };
+                }
+                Value::Object(stream)
+            },
+            RawObject::Reference(id, gen) => {
+                let plain_ref = &PlainRef { id: *id, gen: *gen };
+                let mut result = ref_json(*plain_ref);
+                if visited.contains(plain_ref) {
+                    result.insert(String::from("visited"), Value::from(true));
+                } else if depth > 0 {
+                    visited.insert(*plain_ref);
+                    match self.resolve(plain_ref) {
+                        Ok(resolved) => result.insert(String::from("object"), self.to_json(&resolved, Some(*plain_ref), depth - 1, data, visited)),
+                        Err(error) => result.insert(String::from("error"), Value::from(error.message)),
+                    };
+                }
+                Value::Object(result)
             },
-            _ => { return Err(Error::from(9, format!("Reference {} is not a PDF data stream", plain_ref.format()))); }
+        }
+    }
+
+    /// Evaluates the path expression and returns the decoded content of the stream it points to
+    fn query_stream(&self, expression: &str) -> Result<(PlainRef, Arc<[u8]>), Error> {
+        match self.query(expression)? {
+            (Some(plain_ref), RawObject::Stream(..)) => Ok((plain_ref, self.stream_data(&plain_ref)?)),
+            _ => Err(Error::from(9, format!("Path '{}' does not point to a PDF data stream", expression.trim()))),
+        }
+    }
+
+
+    /// Recursively formats the object and resolves all references until `depth` references have been followed.
+    /// Objects which have already been printed are only marked as visited to break cycles (like /Parent) and keep
+    /// shared objects from being printed multiple times. Stream data is elided.
+    fn format_tree(&self, object: &RawObject, depth: usize, indent: usize, visited: &mut HashSet<PlainRef>) -> String {
+        let padding = "  ".repeat(indent);
+        match object {
+            RawObject::Reference(id, gen) => {
+                let plain_ref = &PlainRef { id: *id, gen: *gen };
+                if visited.contains(plain_ref) {
+                    format!("{} (visited)", plain_ref.format())
+                } else if depth == 0 {
+                    format!("{} ...", plain_ref.format())
+                } else {
+                    visited.insert(*plain_ref);
+                    match self.resolve(plain_ref) {
+                        Ok(resolved) => format!("{} {}", plain_ref.format(), self.format_tree(&resolved, depth - 1, indent, visited)),
+                        Err(error) => format!("{} <{}>", plain_ref.format(), error.message),
+                    }
+                }
+            },
+            RawObject::Dictionary(dict) => {
+                let mut result = String::from("<<\n");
+                for (name, value) in dict.iter() {
+                    result += &format!("{}  {} = {}\n", padding, name, self.format_tree(value, depth, indent + 1, visited));
+                }
+                result + &padding + ">>"
+            },
+            RawObject::Array(vec) if vec.iter().any(|element| matches!(element, RawObject::Reference(..) | RawObject::Dictionary(_) | RawObject::Array(_))) => {
+                let mut result = String::from("[\n");
+                for element in vec {
+                    result += &format!("{}  {}\n", padding, self.format_tree(element, depth, indent + 1, visited));
+                }
+                result + &padding + "]"
+            },
+            RawObject::Stream(dict, _) => {
+                format!("{} stream <data elided>", self.format_tree(&RawObject::Dictionary(dict.clone()), depth, indent, visited))
+            },
+            other => other.format(),
         }
     }
 
 
     fn main() -> Result<(), Error> {
         let args = Args::parse();
-        let mut i = Inspector::new(args.input_path().as_path())?;
-        let inspector = i.with_resolver();
+        let json = args.format == Format::Json;
+        let mut output = Map::new();
+
+        let file_data = FileData::open(&args.input_path()).map_err(|err| Error::from(1, format!("Failed to open {:?} with: {}", args.input_path(), err)))?;
+        let password = args.password()?;
+        if args.revisions {
+            let revisions = revisions(&file_data)?;
+            if json {
+                output.insert(String::from("revisions"), revisions_json(&file_data, &revisions));
+            } else {
+                print_revisions(&file_data, &revisions);
+            }
+        }
+
+        // Everything below looks at the document as of the selected revision
+        let data = match args.revision {
+            Some(number) => revision_data(&file_data, number)?,
+            None => &file_data,
+        };
+
+        // Files with broken cross-reference sections are looked at through the objects found by scanning the file
+        let recover = args.recover || needs_recovery(data);
+        if recover {
+            if json {
+                output.insert(String::from("recovered"), Value::Bool(true));
+            } else {
+                println!("The cross-reference table has been recovered by scanning the file for objects\n");
+            }
+        }
+
+        if args.signatures {
+            let trust_store = match &args.trust_store {
+                Some(path) => TrustStore::load(path)?,
+                None => TrustStore::default(),
+            };
+            let file = open_raw(data, &password, recover)?;
+            let results: Vec<(Signature, SignatureVerification, Vec<UnsignedChange>)> = signatures(&file).into_iter().map(|signature| {
+                let verification = verify_signature(data, &signature, &trust_store);
+                let changes = unsigned_attachment_changes(&file, &signature).unwrap_or_default();
+                (signature, verification, changes)
+            }).collect();
+
+            if json {
+                output.insert(String::from("signatures"), signatures_json(&results));
+            } else {
+                print_signatures(&results, data.len());
+            }
+        }
+
+        if args.encryption {
+            let file = open_raw(data, &password, recover)?;
+            if json {
+                output.insert(String::from("encryption"), encryption_json(&file));
+            } else {
+                print_encryption(&file);
+            }
+        }
+
+        if args.attachments {
+            let file = open_raw(data, &password, recover)?;
+            let (attachments, dates) = (attachments(&file), document_dates(&file));
+            if json {
+                output.insert(String::from("attachments"), attachments_json(&attachments));
+                output.insert(String::from("dates"), dates_json(&dates));
+            } else {
+                print_attachments(&attachments, &dates);
+            }
+        }
+
+        // The xref listing doesn't need the password, so it also works for encrypted files we cannot decrypt below
+        if args.xref {
+            let file = if recover { RawFile::recover(data, Limits::default())? } else { RawFile::open(data)? };
+            if json {
+                output.insert(String::from("xref"), xref_json(&file));
+            } else {
+                print_xref(&file);
+            }
+        }
+
+        let inspector = Inspector::new(open_raw(data, &password, recover)?, args.stream_mode)?;
 
         let root = inspector.get_root()?;
-        println!("Catalog:");
-        println!("{}\n", root.format());
+        if let Some(number) = args.revision.filter(|_| !json) {
+            println!("Showing the document as of revision {}\n", number);
+        }
+
+        if json {
+            output.insert(String::from("catalog"), inspector.to_json(&RawObject::Dictionary(root), None, 0, args.data, &mut HashSet::new()));
+        } else {
+            println!("Catalog:");
+            println!("{}\n", root.format());
+        }
 
         if let Some(refs) = &args.references {
+            let mut results = Map::new();
             for ref_str in refs.split(',') {
-                let ref_id = ref_str.parse::<u64>().map_err(|_err| Error::from(6, format!("Failed to parse '{}' as integer id", ref_str)))?;
-                let plain_ref = PlainRef { id: ref_id, gen: 0 };
+                let plain_ref = match ObjectPath::parse(ref_str)? {
+                    ObjectPath { start: Some(plain_ref), segments } if segments.is_empty() => plain_ref,
+                    _ => return Err(Error::from(6, format!("Failed to parse '{}' as object id or reference", ref_str))),
+                };
                 let resolved = inspector.resolve(&plain_ref)?;
-                println!("{}:\n{}\n", plain_ref.format(), resolved.format());
+                if json {
+                    results.insert(plain_ref.format(), inspector.to_json(&resolved, Some(plain_ref), 0, args.data, &mut HashSet::new()));
+                } else {
+                    println!("{}:\n{}\n", plain_ref.format(), resolved.format());
+                }
             }
+            output.insert(String::from("references"), Value::Object(results));
         }
 
+        if let Some(paths) = &args.query {
+            let mut results = Map::new();
+            for path in paths.split(',') {
+                let (plain_ref, resolved) = inspector.query(path)?;
+                if json {
+                    results.insert(String::from(path.trim()), json!({
+                        "reference": plain_ref.map(ref_json),
+                        "object": inspector.to_json(&resolved, plain_ref, 0, args.data, &mut HashSet::new()),
+                    }));
+                } else {
+                    println!("{}:\n{}\n", label(path, plain_ref), resolved.format());
+                }
+            }
+            output.insert(String::from("queries"), Value::Object(results));
+        }
 
-        if let Some(refs) = &args.print {
-            for ref_str in refs.split(',') {
-                let ref_id = ref_str.parse::<u64>().map_err(|_err| Error::from(6, format!("Failed to parse '{}' as integer id", ref_str)))?;
-                let plain_ref = PlainRef { id: ref_id, gen: 0 };
-                let bytes = inspector.resolve_stream(&plain_ref)?;
-                println!("{}:\n{}\n", plain_ref.format(), String::from_utf8_lossy(bytes.as_ref()));
+
+        if let Some(start) = &args.tree {
+            let start = match inspector.query(start)? {
+                (Some(plain_ref), _) => RawObject::from(plain_ref),
+                (None, object) => object,
+            };
+
+            let depth = args.depth.unwrap_or(usize::MAX);
+            if json {
+                output.insert(String::from("tree"), inspector.to_json(&start, None, depth, args.data, &mut HashSet::new()));
+            } else {
+                let tree = inspector.format_tree(&start, depth, 0, &mut HashSet::new());
+                println!("Tree:\n{}\n", tree);
             }
         }
 
-        if let Some(refs) = &args.export {
-            for ref_str in refs.split(',') {
-                let ref_id = ref_str.parse::<u64>().map_err(|_err| Error::from(6, format!("Failed to parse '{}' as integer id", ref_str)))?;
-                let plain_ref = PlainRef { id: ref_id, gen: 0 };
-                let bytes = inspector.resolve_stream(&plain_ref)?;
 
-                let mut output_path = args.input_path().clone();
-                output_path.set_extension(format!("{}.ref", ref_str));
+        if let Some(paths) = &args.print {
+            let mut results = Map::new();
+            for path in paths.split(',') {
+                let (plain_ref, bytes) = inspector.query_stream(path)?;
+                if json {
+                    results.insert(String::from(path.trim()), json!({
+                        "reference": ref_json(plain_ref),
+                        "length": bytes.len(),
+                        "file_type": FileType::detect(bytes.as_ref()).description(),
+                        "text": std::str::from_utf8(bytes.as_ref()).ok(),
+                        "data": BASE64_STANDARD.encode(bytes.as_ref()),
+                    }));
+                } else {
+                    println!("{}:\n{}", label(path, Some(plain_ref)), format_content(bytes.as_ref(), args.view, args.range));
+                }
+            }
+            output.insert(String::from("streams"), Value::Object(results));
+        }
+
+        if let Some(paths) = &args.filters {
+            let mut results = Map::new();
+            for path in paths.split(',') {
+                let plain_ref = match inspector.query(path)? {
+                    (Some(plain_ref), RawObject::Stream(..)) => plain_ref,
+                    _ => return Err(Error::from(9, format!("Path '{}' does not point to a PDF data stream", path.trim()))),
+                };
+                let (length, stages) = inspector.filter_stages(&plain_ref)?;
+                if json {
+                    results.insert(String::from(path.trim()), filters_json(plain_ref, length, &stages));
+                } else {
+                    println!("{}:\n{}", label(path, Some(plain_ref)), format_filters(length, &stages));
+                }
+            }
+            output.insert(String::from("filters"), Value::Object(results));
+        }
+
+        if let Some(paths) = &args.export {
+            for path in paths.split(',') {
+                let (plain_ref, bytes) = inspector.query_stream(path)?;
+                write_export(&export_path(&args.input_path(), plain_ref), &bytes)?;
+            }
+        }
+
+        if json {
+            let text = serde_json::to_string_pretty(&Value::Object(output)).map_err(|err| Error::from(15, format!("Failed to serialize JSON output: {}", err)))?;
+            println!("{}", text);
+        }
+
+        if args.interactive {
+            inspector.interactive(&args)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens the file with our own raw object layer (through the recovered cross-reference table if the file is broken)
+fn open_raw<'a>(data: &'a [u8], password: &[u8], recover: bool) -> Result<RawFile<'a>, Error> {
+    let mut file = if recover { RawFile::recover(data, Limits::default())? } else { RawFile::open(data)? };
+    file.set_password(password)?;
+    Ok(file)
+}
+
+/// Returns the default export path for the stream with the given reference. Paths can't be used in file names, so the
+/// file is named after the object id (next to the input PDF).
+fn export_path(input_path: &Path, plain_ref: PlainRef) -> PathBuf {
+    let mut output_path = input_path.to_path_buf();
+    if plain_ref.gen == 0 {
+        output_path.set_extension(format!("{}.ref", plain_ref.id));
+    } else {
+        output_path.set_extension(format!("{}.{}.ref", plain_ref.id, plain_ref.gen));
+    }
+    output_path
+}
+
+fn write_export(output_path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let mut file = OpenOptions::new().write(true).truncate(true).create(true).open(output_path).map_err(|err| Error::from(10,format!("Failed to open {}: {}", &output_path.display().to_string(), err)))?;
+    file.write_all(bytes).map_err(|err| Error::from(11, format!("Failed to write {}: {}", &output_path.display().to_string(), err)))
+}
+
+
+/// A position inside the document for the interactive shell
+#[derive(Clone)]
+struct Location {
+    /// Path shown in the prompt
+    path: String,
+    /// Reference the object has been resolved from (None for direct objects and the trailer)
+    reference: Option<PlainRef>,
+    object: RawObject,
+}
+
+const SHELL_COMMANDS: [&str; 14] = ["cd", "ls", "cat", "hex", "tree", "export", "filters", "refs", "back", "pwd", "history", "help", "exit", "quit"];
+
+const SHELL_HELP: &str = "\
+Paths are relative to the current object (Names/EmbeddedFiles, AF[0]) unless they start with '/' (trailer)
+or an object id or reference (12, 12 1 R/Type). Keys can be completed with <Tab>.
+
+  cd [PATH]             change into the given object (the trailer if no path is given, the parent for '..')
+  back                  return to the previous object
+  pwd                   print the current path
+  ls [PATH]             list the entries of the current (or given) dictionary, array or stream dictionary
+  cat [PATH]            print the current (or given) object or the content of a stream (see --stream-mode, --view and --range)
+  hex [PATH]            print the content of the current (or given) stream as hexdump
+  tree [DEPTH]          print the object tree below the current object (see --tree)
+  filters [PATH]        show the filter chain of the current (or given) stream with the output size of each stage
+  refs                  list all references of the current object
+  export [PATH] [FILE]  write the content of the current (or given) stream to a file (see --stream-mode)
+  history               print the command history
+  exit, quit            leave the shell";
+
+
+impl<'a> Inspector<'a> {
+    /// The location of the trailer dictionary, where all absolute paths start
+    fn trailer_location(&self) -> Location {
+        Location { path: String::from("/"), reference: None, object: RawObject::Dictionary(self.trailer_dict.clone()) }
+    }
+
+    /// Resolves the path relative to the given location. Paths starting with '/' start at the trailer and paths
+    /// starting with an object id or reference (`12 1 R/Type`) at that object.
+    fn locate(&self, from: &Location, expression: &str) -> Result<Location, Error> {
+        let expression = expression.trim();
+        if expression.is_empty() {
+            Ok(from.clone())
+        } else if expression.starts_with('/') || expression.starts_with(|c: char| c.is_ascii_digit()) {
+            let (reference, object) = self.query(expression)?;
+            Ok(Location { path: String::from(expression), reference, object })
+        } else {
+            let relative = if expression.starts_with('[') { String::from(expression) } else { format!("/{}", expression) };
+            let path = ObjectPath::parse(&relative)?;
+            let (reference, object) = self.walk(from.reference, from.object.clone(), &path.segments, expression)?;
+            Ok(Location { path: format!("{}{}", from.path.trim_end_matches('/'), relative.trim_end_matches('/')), reference, object })
+        }
+    }
+
+    /// Returns the location of the parent path (the trailer if the path has no parent)
+    fn parent_location(&self, location: &Location) -> Result<Location, Error> {
+        match location.path.rfind(['/', '[']).filter(|index| *index > 0) {
+            Some(index) => self.locate(location, &location.path[..index]),
+            None => Ok(self.trailer_location()),
+        }
+    }
+
+
+    /// Runs the interactive shell, which keeps the loaded document for the whole session
+    fn interactive(&self, args: &Args) -> Result<(), Error> {
+        let current = Rc::new(RefCell::new(self.trailer_location()));
+        let mut previous = Vec::new();
+
+        let mut editor = Editor::<ShellHelper, DefaultHistory>::new().map_err(|err| Error::from(14, format!("Failed to start interactive shell: {}", err)))?;
+        editor.set_helper(Some(ShellHelper { inspector: self, current: current.clone() }));
+        println!("Interactive shell - type 'help' for a list of commands");
 
-                let mut file = OpenOptions::new().write(true).truncate(true).create(true).open(&output_path).map_err(|err| Error::from(10,format!("Failed to open {}: {}", &output_path.display().to_string(), err)))?;
-                file.write_all(&*bytes).map_err(|err| Error::from(11, format!("Failed to write {}: {}", &output_path.display().to_string(), err)))?;
+        loop {
+            let prompt = format!("{}> ", current.borrow().path);
+            let line = match editor.readline(&prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(Error::from(14, format!("Failed to read command: {}", err))),
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let _ = editor.add_history_entry(line);
+
+            let (command, argument) = line.split_once(char::is_whitespace).map_or((line, ""), |(command, argument)| (command, argument.trim()));
+            let result = match command {
+                "exit" | "quit" => break,
+                "history" => {
+                    for (index, entry) in editor.history().iter().enumerate() {
+                        println!("{:>4}  {}", index + 1, entry);
+                    }
+                    Ok(())
+                },
+                // A command which triggers a bug must not end the session
+                _ => Error::catch_panic(|| self.shell_command(command, argument, &current, &mut previous, args)),
+            };
+
+            if let Err(error) = result {
+                error.print();
             }
         }
 
         Ok(())
     }
+
+    fn shell_command(&self, command: &str, argument: &str, current: &Rc<RefCell<Location>>, previous: &mut Vec<Location>, args: &Args) -> Result<(), Error> {
+        let location = current.borrow().clone();
+        match command {
+            "cd" => {
+                let target = match argument {
+                    "" => self.trailer_location(),
+                    ".." => self.parent_location(&location)?,
+                    path => self.locate(&location, path)?,
+                };
+                if !matches!(target.object, RawObject::Dictionary(_) | RawObject::Array(_) | RawObject::Stream(..)) {
+                    return Err(Error::from(13, format!("{} is not a dictionary, array or stream", target.path)));
+                }
+                previous.push(current.replace(target));
+            },
+            "back" => match previous.pop() {
+                Some(target) => { current.replace(target); },
+                None => println!("No previous location"),
+            },
+            "pwd" => match location.reference {
+                Some(plain_ref) => println!("{} ({})", location.path, plain_ref.format()),
+                None => println!("{}", location.path),
+            },
+            "ls" => {
+                let target = self.locate(&location, argument)?;
+                let entries: Vec<(String, &RawObject)> = match &target.object {
+                    RawObject::Dictionary(dict) => dict.iter().map(|(name, value)| (name.to_string(), value)).collect(),
+                    RawObject::Stream(dict, _) => dict.iter().map(|(name, value)| (name.to_string(), value)).collect(),
+                    RawObject::Array(vec) => vec.iter().enumerate().map(|(index, value)| (format!("[{}]", index), value)).collect(),
+                    other => vec![(String::new(), other)],
+                };
+                for (name, value) in entries {
+                    println!("  {:<24} {}", name, summary(value));
+                }
+            },
+            "cat" | "hex" => {
+                let target = self.locate(&location, argument)?;
+                let view = if command == "hex" { View::Hex } else { args.view };
+                match (target.reference, &target.object) {
+                    (Some(plain_ref), RawObject::Stream(..)) => print!("{}", format_content(self.stream_data(&plain_ref)?.as_ref(), view, args.range)),
+                    (_, object) => println!("{}", object.format()),
+                }
+            },
+            "tree" => {
+                let depth = match argument {
+                    "" => usize::MAX,
+                    depth => depth.parse::<usize>().map_err(|_err| Error::from(6, format!("Failed to parse '{}' as depth", depth)))?,
+                };
+                let start = location.reference.map_or(location.object.clone(), RawObject::from);
+                println!("{}", self.format_tree(&start, depth, 0, &mut HashSet::new()));
+            },
+            "filters" => {
+                let target = self.locate(&location, argument)?;
+                match (target.reference, &target.object) {
+                    (Some(plain_ref), RawObject::Stream(..)) => {
+                        let (length, stages) = self.filter_stages(&plain_ref)?;
+                        print!("{}", format_filters(length, &stages));
+                    },
+                    _ => return Err(Error::from(9, format!("{} is not a PDF data stream", target.path))),
+                }
+            },
+            "refs" => {
+                let mut references = Vec::new();
+                collect_references(&location.object, String::new(), &mut references);
+                for (path, plain_ref) in references {
+                    println!("  {:<24} {}", path, plain_ref.format());
+                }
+            },
+            "export" => {
+                // The last argument is the output file if there are multiple
+                let (path, output) = match argument.rsplit_once(char::is_whitespace) {
+                    Some((path, output)) if !output.ends_with('R') => (path, Some(PathBuf::from(output))),
+                    _ => (argument, None),
+                };
+                let target = self.locate(&location, path)?;
+                let plain_ref = match (target.reference, &target.object) {
+                    (Some(plain_ref), RawObject::Stream(..)) => plain_ref,
+                    _ => return Err(Error::from(9, format!("{} is not a PDF data stream", target.path))),
+                };
+
+                let output_path = output.map_or_else(|| export_path(&args.input_path(), plain_ref), |output| Args::resolve_path(&output));
+                write_export(&output_path, &self.stream_data(&plain_ref)?)?;
+                println!("Exported {} to {}", plain_ref.format(), output_path.display());
+            },
+            "help" => println!("{}", SHELL_HELP),
+            other => return Err(Error::from(12, format!("Unknown command '{}' - type 'help' for a list of commands", other))),
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Keys of a dictionary or stream dictionary (empty for all other objects)
+fn keys(object: &RawObject) -> Vec<String> {
+    match object {
+        RawObject::Dictionary(dict) => dict.iter().map(|(name, _)| name.to_string()).collect(),
+        RawObject::Stream(dict, _) => dict.iter().map(|(name, _)| name.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Short single line description of a object for the `ls` command
+fn summary(object: &RawObject) -> String {
+    match object {
+        RawObject::Dictionary(dict) => format!("<< {} entries >>", dict.len()),
+        RawObject::Stream(dict, _) => format!("stream << {} entries >>", dict.len()),
+        RawObject::Array(vec) if vec.len() > 8 => format!("[ {} elements ]", vec.len()),
+        other => {
+            let text = other.format();
+            if text.chars().count() > 80 { text.chars().take(77).collect::<String>() + "..." } else { text }
+        }
+    }
+}
+
+/// Collects all references inside the object (without following them) together with their relative path
+fn collect_references(object: &RawObject, path: String, references: &mut Vec<(String, PlainRef)>) {
+    match object {
+        RawObject::Reference(id, gen) => references.push((path, PlainRef { id: *id, gen: *gen })),
+        RawObject::Dictionary(dict) => dict.iter().for_each(|(name, value)| collect_references(value, format!("{}/{}", path, name), references)),
+        RawObject::Stream(dict, _) => dict.iter().for_each(|(name, value)| collect_references(value, format!("{}/{}", path, name), references)),
+        RawObject::Array(vec) => vec.iter().enumerate().for_each(|(index, value)| collect_references(value, format!("{}[{}]", path, index), references)),
+        _ => {},
+    }
+}
+
+
+/// Completes commands and dictionary keys in the interactive shell
+struct ShellHelper<'i, 'a> {
+    inspector: &'i Inspector<'a>,
+    current: Rc<RefCell<Location>>,
+}
+
+impl Completer for ShellHelper<'_, '_> {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let word = &line[start..pos];
+        if line[..start].trim().is_empty() {
+            return Ok((start, SHELL_COMMANDS.iter().filter(|command| command.starts_with(word)).map(|command| String::from(*command)).collect()));
+        }
+
+        // The last key of the path is completed with the keys of the object the rest of the path points to
+        let (base, prefix, offset) = match word.rfind('/') {
+            Some(index) => (&word[..index], &word[index + 1..], start + index + 1),
+            None => ("", word, start),
+        };
+        let current = self.current.borrow();
+        let location = match base {
+            "" if word.starts_with('/') => Ok(self.inspector.trailer_location()),
+            "" => Ok(current.clone()),
+            base => self.inspector.locate(&current, base),
+        };
+
+        let candidates = location.map(|location| keys(&location.object)).unwrap_or_default();
+        Ok((offset, candidates.into_iter().filter(|key| key.starts_with(prefix)).collect()))
+    }
+}
+
+impl Hinter for ShellHelper<'_, '_> {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper<'_, '_> {}
+
+impl Validator for ShellHelper<'_, '_> {}
+
+impl Helper for ShellHelper<'_, '_> {}
+
+
+/// Returns the label to print above a query result, which shows the reference the path has been resolved to
+fn label(path: &str, plain_ref: Option<PlainRef>) -> String {
+    match plain_ref {
+        Some(plain_ref) if path.trim().parse::<u64>().is_ok() || path.trim() == plain_ref.format() => plain_ref.format(),
+        Some(plain_ref) => format!("{} ({})", path.trim(), plain_ref.format()),
+        None => String::from(path.trim()),
+    }
+}
+
+/// Prints the cross-reference sections and an inventory of all objects listed in them
+fn print_xref(file: &RawFile) {
+    println!("Cross-reference sections (newest first):");
+    for (index, section) in file.xref.sections.iter().enumerate() {
+        let kind = match section.kind {
+            SectionKind::Table => format!("xref table at offset {}", section.offset),
+            SectionKind::Stream(id) => format!("xref stream {} 0 R at offset {}", id, section.offset),
+            SectionKind::Recovered => String::from("xref table recovered by scanning the file"),
+        };
+        println!("  #{} {} with {} entries", index, kind, section.entries.len());
+    }
+
+    println!("\n{:>8} {:>5}  {:<30} {:<32} {:>10}  section", "id", "gen", "location", "type", "length");
+    for (id, (entry, section)) in file.entries() {
+        let location = match entry {
+            XRefEntry::Free { next, .. } => format!("free (next {})", next),
+            XRefEntry::InFile { offset, .. } => format!("offset {}", offset),
+            XRefEntry::InStream { stream_id, index } => format!("object stream {} [{}]", stream_id, index),
+        };
+
+        let (object_type, length) = describe_object(file, *id, entry);
+        println!("{:>8} {:>5}  {:<30} {:<32} {:>10}  #{}", id, entry.gen(), location, object_type, length.map(|length| length.to_string()).unwrap_or_default(), section);
+    }
+    println!();
+}
+
+/// The cross-reference sections and object inventory as JSON
+fn xref_json(file: &RawFile) -> Value {
+    let sections: Vec<Value> = file.xref.sections.iter().map(|section| json!({
+        "offset": section.offset,
+        "stream": match section.kind { SectionKind::Stream(id) => Some(id), _ => None },
+        "recovered": section.kind == SectionKind::Recovered,
+        "entries": section.entries.len(),
+    })).collect();
+
+    let objects: Vec<Value> = file.entries().iter().map(|(id, (entry, section))| {
+        let (object_type, length) = describe_object(file, *id, entry);
+        let mut object = json!({ "id": id, "gen": entry.gen(), "section": section, "type": object_type, "length": length });
+        match entry {
+            XRefEntry::Free { next, .. } => object["free"] = json!({ "next": next }),
+            XRefEntry::InFile { offset, .. } => object["offset"] = json!(offset),
+            XRefEntry::InStream { stream_id, index } => object["object_stream"] = json!({ "id": stream_id, "index": index }),
+        }
+        object
+    }).collect();
+
+    json!({ "sections": sections, "objects": objects })
+}
+
+/// Returns the type description and (for streams) the encoded length of the object with the given xref entry
+fn describe_object(file: &RawFile, id: u64, entry: &XRefEntry) -> (String, Option<usize>) {
+    match entry {
+        XRefEntry::Free { .. } => (String::new(), None),
+        _ => match file.resolve(id) {
+            Ok(RawObject::Stream(dict, range)) => (describe_type(&dict, "stream"), Some(range.len())),
+            Ok(RawObject::Dictionary(dict)) => (describe_type(&dict, "dictionary"), None),
+            Ok(RawObject::Array(array)) => (format!("array [{}]", array.len()), None),
+            Ok(object) => (format!("{:?}", object).chars().take(32).collect(), None),
+            Err(error) => (format!("<{}>", error.message), None),
+        }
+    }
+}
+
+/// Formats the stream content with a header showing the detected file type. Text is printed as is and binary data as
+/// hexdump (unless the view forces one of them).
+fn format_content(data: &[u8], view: View, range: Option<ByteRange>) -> String {
+    let file_type = FileType::detect(data);
+    let end = range.and_then(|range| range.end).unwrap_or(data.len()).min(data.len());
+    let start = range.map_or(0, |range| range.start).min(end);
+
+    let mut result = format!("[{}, {} bytes", file_type, data.len());
+    if start > 0 || end < data.len() {
+        result += &format!(", showing {}..{}", start, end);
+    }
+    result += "]\n";
+
+    let text = match view {
+        View::Auto => file_type.is_text(),
+        View::Text => true,
+        View::Hex => false,
+    };
+    if text {
+        result += &String::from_utf8_lossy(&data[start..end]);
+        result += "\n";
+    } else {
+        result += &hexdump(&data[start..end], start);
+    }
+    result
 }
 
+/// Classic hexdump with offsets, hex bytes and the ASCII column (16 bytes per line)
+fn hexdump(data: &[u8], offset: usize) -> String {
+    let mut result = String::new();
+    for (index, line) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let (left, right) = hex.split_at(hex.len().min(8));
+        let ascii: String = line.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect();
+        result += &format!("{:08x}  {:<23}  {:<23}  |{}|\n", offset + index * 16, left.join(" "), right.join(" "), ascii);
+    }
+    result
+}
+
+
+/// Lists the encoded size and the output size (or error) of every filter stage
+fn format_filters(length: usize, stages: &[FilterStage]) -> String {
+    let mut result = format!("  {:<50} {:>10} bytes\n", "encoded data", length);
+    if stages.is_empty() {
+        result += "  (no filters)\n";
+    }
+
+    for (index, stage) in stages.iter().enumerate() {
+        let filter = match &stage.params {
+            Some(params) => format!("{}. /{} {}", index + 1, stage.filter, params),
+            None => format!("{}. /{}", index + 1, stage.filter),
+        };
+        result += &match &stage.output {
+            Ok(output) => format!("  {:<50} {:>10} bytes\n", filter, output.len()),
+            Err(error) => format!("  {:<50} error: {}\n", filter, error.message),
+        };
+    }
+    result
+}
+
+fn filters_json(plain_ref: PlainRef, length: usize, stages: &[FilterStage]) -> Value {
+    let stages: Vec<Value> = stages.iter().map(|stage| {
+        let mut result = json!({ "filter": format!("/{}", stage.filter), "params": stage.params.as_ref().map(|params| params.to_string()) });
+        match &stage.output {
+            Ok(output) => result["length"] = json!(output.len()),
+            Err(error) => result["error"] = json!(error.message),
+        }
+        result
+    }).collect();
+
+    json!({ "reference": ref_json(plain_ref), "encoded_length": length, "stages": stages })
+}
+
+/// Returns the objects of every revision with the kind of change ("added", "changed", "deleted" or "free") and their type
+fn revision_changes(data: &[u8], revisions: &[Revision]) -> Vec<Vec<(u64, u16, &'static str, String)>> {
+    let mut known = HashSet::new();
+    revisions.iter().map(|revision| {
+        let file = RawFile::open(&data[..revision.end]).ok();
+        revision.objects.iter().filter(|(id, _)| *id != 0).map(|(id, entry)| {
+            let change = match entry {
+                XRefEntry::Free { .. } if known.contains(id) => "deleted",
+                XRefEntry::Free { .. } => "free",
+                _ if !known.insert(*id) => "changed",
+                _ => "added",
+            };
+            let object_type = file.as_ref().map(|file| describe_object(file, *id, entry).0).unwrap_or_default();
+            (*id, entry.gen(), change, object_type)
+        }).collect()
+    }).collect()
+}
+
+/// Prints all revisions with the objects added, changed or deleted by each of them
+fn print_revisions(data: &[u8], revisions: &[Revision]) {
+    println!("Revisions (oldest first):");
+    for (revision, changes) in revisions.iter().zip(revision_changes(data, revisions)) {
+        let kind = match revision.kind {
+            SectionKind::Table | SectionKind::Recovered => String::from("xref table"),
+            SectionKind::Stream(id) => format!("xref stream {} 0 R", id),
+        };
+        println!("  #{} {} at offset {}, ends at {} with {} object(s)", revision.number, kind, revision.startxref, revision.end, changes.len());
+        for (id, gen, change, object_type) in changes {
+            println!("    {:>12}  {:<8} {}", format!("{} {} R", id, gen), change, object_type);
+        }
+    }
+
+    if data.len() > revisions.last().map_or(0, |revision| revision.end) {
+        println!("  {} byte(s) after the last revision", data.len() - revisions.last().map_or(0, |revision| revision.end));
+    }
+    println!();
+}
+
+fn revisions_json(data: &[u8], revisions: &[Revision]) -> Value {
+    let revisions: Vec<Value> = revisions.iter().zip(revision_changes(data, revisions)).map(|(revision, changes)| json!({
+        "number": revision.number,
+        "startxref": revision.startxref,
+        "stream": match revision.kind { SectionKind::Stream(id) => Some(id), _ => None },
+        "end": revision.end,
+        "objects": changes.into_iter().map(|(id, gen, change, object_type)| json!({ "id": id, "gen": gen, "change": change, "type": object_type })).collect::<Vec<Value>>(),
+    })).collect();
+    Value::Array(revisions)
+}
+
+/// Prints the signatures with their verification result and the attachments modified after signing
+fn print_signatures(results: &[(Signature, SignatureVerification, Vec<UnsignedChange>)], length: usize) {
+    println!("Signatures:");
+    if results.is_empty() {
+        println!("  none");
+    }
+
+    for (index, (signature, verification, changes)) in results.iter().enumerate() {
+        println!("  #{} {} 0 R{} {}", index + 1, signature.id, signature.field.as_ref().map(|field| format!(" field '{}'", field)).unwrap_or_default(), signature.sub_filter.as_deref().unwrap_or("(no /SubFilter)"));
+        println!("    signer:       {}", verification.signer.as_deref().or(signature.name.as_deref()).unwrap_or("unknown"));
+        println!("    signing time: {}", verification.signing_time.as_deref().unwrap_or("unknown"));
+        println!("    integrity:    {}", verification.integrity);
+        println!("    trust:        {}", verification.trust);
+        if verification.covers_whole_file {
+            println!("    coverage:     whole file");
+        } else {
+            println!("    coverage:     up to byte {} of {}", signature.signed_end(), length);
+        }
+        for change in changes {
+            println!("    modified after signing: {}", change);
+        }
+    }
+    println!();
+}
+
+fn signatures_json(results: &[(Signature, SignatureVerification, Vec<UnsignedChange>)]) -> Value {
+    let signatures: Vec<Value> = results.iter().map(|(signature, verification, changes)| json!({
+        "id": signature.id,
+        "field": signature.field,
+        "sub_filter": signature.sub_filter,
+        "byte_range": signature.byte_range.iter().flat_map(|(offset, length)| [*offset, *length]).collect::<Vec<usize>>(),
+        "name": signature.name,
+        "signer": verification.signer,
+        "signing_time": verification.signing_time,
+        "integrity": verification.integrity.to_string(),
+        "valid": verification.is_valid(),
+        "trust": verification.trust.to_string(),
+        "covers_whole_file": verification.covers_whole_file,
+        "modified_after_signing": changes.iter().map(|change| json!({ "id": change.id, "revision": change.revision, "type": change.description, "deleted": change.deleted })).collect::<Vec<Value>>(),
+    })).collect();
+    Value::Array(signatures)
+}
+
+fn print_encryption(file: &RawFile) {
+    println!("Encryption:");
+    match file.security_handler() {
+        Some(handler) => {
+            println!("  security handler: Standard (/V {}, /R {})", handler.version, handler.revision);
+            println!("  key length:       {} bits", handler.key_length);
+            println!("  strings:          {}", handler.string_method);
+            println!("  streams:          {}", handler.stream_method);
+            println!("  embedded files:   {}", handler.file_method);
+            println!("  metadata:         {}", if handler.encrypt_metadata { "encrypted" } else { "not encrypted" });
+            println!("  permissions:      {} (/P {})", handler.permission_names().join(", "), handler.permissions);
+            println!("  opened with:      {} password", handler.authenticated);
+        },
+        None => println!("  none"),
+    }
+    println!();
+}
+
+fn encryption_json(file: &RawFile) -> Value {
+    match file.security_handler() {
+        Some(handler) => json!({
+            "filter": "Standard",
+            "version": handler.version,
+            "revision": handler.revision,
+            "key_length": handler.key_length,
+            "string_method": handler.string_method.to_string(),
+            "stream_method": handler.stream_method.to_string(),
+            "file_method": handler.file_method.to_string(),
+            "encrypt_metadata": handler.encrypt_metadata,
+            "permissions": handler.permissions,
+            "permission_names": handler.permission_names(),
+            "password": handler.authenticated.to_string(),
+        }),
+        None => Value::Null,
+    }
+}
+
+/// An embedded file of the --attachments listing
+struct Attachment {
+    name: String,
+    file_spec: FileSpecification,
+    /// Where the file specification is listed (/AF, /EmbeddedFiles or both)
+    sources: Vec<&'static str>,
+    params: Option<EmbeddedFileParams>,
+}
+
+/// Collects the file specifications of /AF and /EmbeddedFiles (file specifications listed in both appear only once)
+fn attachments(file: &RawFile) -> Vec<Attachment> {
+    let af = associated_files(file).unwrap_or_default().into_iter().map(|file_spec| (None, file_spec, "/AF"));
+    let embedded = embedded_files(file).unwrap_or_default().into_iter().map(|(name, file_spec)| (Some(name), file_spec, "/EmbeddedFiles"));
+
+    let mut attachments: Vec<Attachment> = Vec::new();
+    for (tree_name, file_spec, source) in af.chain(embedded) {
+        if let Some(attachment) = attachments.iter_mut().find(|attachment| file_spec.id.is_some() && attachment.file_spec.id == file_spec.id) {
+            attachment.sources.push(source);
+            continue;
+        }
+
+        let name = file_spec.names().next().map(String::from).or(tree_name).unwrap_or_default();
+        let params = file_spec.params(file).unwrap_or_default();
+        attachments.push(Attachment { name, file_spec, sources: vec![source], params });
+    }
+    attachments
+}
+
+/// Returns the dates of the document as (source, date as written in the file, parsed date): /CreationDate and /ModDate
+/// of the /Info dictionary and the dates of the XMP metadata
+fn document_dates(file: &RawFile) -> Vec<(String, String, Option<PdfDate>)> {
+    let mut dates = Vec::new();
+    let info = file.xref.trailer().and_then(|trailer| trailer.get("Info")).and_then(|info| file.resolve_if_ref(info).ok());
+    if let Some(info) = info.as_ref().and_then(RawObject::as_dictionary) {
+        for key in ["CreationDate", "ModDate"] {
+            if let Some(text) = info.get(key).and_then(|date| file.resolve_if_ref(date).ok()).and_then(|date| date.as_text()) {
+                dates.push((format!("/Info /{}", key), text.clone(), PdfDate::parse(&text)));
+            }
+        }
+    }
+
+    let catalog = file.catalog().ok();
+    let metadata = catalog.as_ref().and_then(RawObject::as_dictionary).and_then(|catalog| catalog.get("Metadata"));
+    if let Some(metadata) = metadata.and_then(|metadata| file.resolve_if_ref(metadata).ok()).and_then(|metadata| file.stream_data(&metadata).ok()) {
+        for (property, text) in xmp_dates(&String::from_utf8_lossy(&metadata)) {
+            let date = PdfDate::parse_xmp(&text);
+            dates.push((property, text, date));
+        }
+    }
+    dates
+}
+
+/// The parsed date followed by the date as written in the file if it is non-conformant
+fn format_date(text: &str, date: Option<PdfDate>) -> String {
+    match date {
+        Some(date) if date.non_conformant => format!("{} (non-conformant: {:?})", date, text),
+        Some(date) => date.to_string(),
+        None => format!("invalid date {:?}", text),
+    }
+}
+
+fn print_attachments(attachments: &[Attachment], dates: &[(String, String, Option<PdfDate>)]) {
+    println!("Attachments:");
+    if attachments.is_empty() {
+        println!("  none");
+    }
+
+    for (index, attachment) in attachments.iter().enumerate() {
+        let reference = attachment.file_spec.id.map(|id| format!(" ({} 0 R)", id)).unwrap_or_default();
+        println!("  #{} {}{} in {}", index + 1, attachment.name, reference, attachment.sources.join(", "));
+        println!("    relationship: {}", attachment.file_spec.relationship.as_deref().unwrap_or("unknown"));
+        let params = attachment.params.clone().unwrap_or_default();
+        println!("    type:         {}", params.subtype.as_deref().unwrap_or("unknown"));
+        println!("    size:         {}", params.size.map_or(String::from("unknown"), |size| format!("{} bytes", size)));
+        for (label, text) in [("created: ", &params.creation_date), ("modified:", &params.modification_date)] {
+            if let Some(text) = text {
+                println!("    {}     {}", label, format_date(text, PdfDate::parse(text)));
+            }
+        }
+    }
+
+    println!("\nDocument dates:");
+    if dates.is_empty() {
+        println!("  none");
+    }
+    for (source, text, date) in dates {
+        println!("  {:<20} {}", source, format_date(text, *date));
+    }
+    println!();
+}
+
+/// A date as JSON object with the text as written in the file, the parsed date (ISO 8601), its UNIX timestamp and whether
+/// the text is conformant
+fn date_json(text: &str, date: Option<PdfDate>) -> Value {
+    json!({
+        "text": text,
+        "date": date.map(|date| date.to_string()),
+        "timestamp": date.map(|date| date.timestamp()),
+        "conformant": date.is_some_and(|date| !date.non_conformant),
+    })
+}
+
+fn attachments_json(attachments: &[Attachment]) -> Value {
+    let attachments: Vec<Value> = attachments.iter().map(|attachment| {
+        let params = attachment.params.clone().unwrap_or_default();
+        json!({
+            "name": attachment.name,
+            "id": attachment.file_spec.id,
+            "f": attachment.file_spec.f,
+            "uf": attachment.file_spec.uf,
+            "sources": attachment.sources,
+            "relationship": attachment.file_spec.relationship,
+            "subtype": params.subtype,
+            "size": params.size,
+            "creation_date": params.creation_date.as_deref().map(|text| date_json(text, PdfDate::parse(text))),
+            "modification_date": params.modification_date.as_deref().map(|text| date_json(text, PdfDate::parse(text))),
+        })
+    }).collect();
+    Value::Array(attachments)
+}
+
+fn dates_json(dates: &[(String, String, Option<PdfDate>)]) -> Value {
+    Value::Object(dates.iter().map(|(source, text, date)| (source.clone(), date_json(text, *date))).collect())
+}
+
+/// A reference as JSON object {"id", "gen"}
+fn ref_json(plain_ref: PlainRef) -> Map<String, Value> {
+    let mut result = Map::new();
+    result.insert(String::from("id"), Value::from(plain_ref.id));
+    result.insert(String::from("gen"), Value::from(plain_ref.gen));
+    result
+}
+
+/// Returns "/Type /Subtype" of the dictionary or the fallback if it has no /Type
+fn describe_type(dict: &RawDictionary, fallback: &str) -> String {
+    let names: Vec<String> = ["Type", "Subtype", "S"].iter()
+        .filter_map(|key| dict.get(key).and_then(RawObject::as_name))
+        .map(|name| format!("/{}", name))
+        .collect();
+
+    if names.is_empty() { String::from(fallback) } else { names.join(" ") }
+}
+
+
 /// This is a custom string formatting trait to adjust the display of certain PDF primitives when printed to the console and
 /// make them look closer to what they actually look like inside the PDF
 trait Print {
@@ -173,42 +1405,37 @@ impl Print for PlainRef {
     }
 }
 
-impl Print for Dictionary {
+impl Print for RawDictionary {
     fn format(&self) -> String {
-        self.iter().map(|(name, primitive)| { format!("{} = {}", name, primitive.format()) })
+        self.iter().map(|(name, object)| { format!("{} = {}", name, object.format()) })
             .reduce(|a, b| a + "\n" + b.as_str()).unwrap_or(String::from(""))
     }
 }
 
 
-impl Print for Primitive {
+impl Print for RawObject {
     fn format(&self) -> String {
         match self {
-            Primitive::Null => String::from("null"),
-            Primitive::Integer(i32) => format!("{}", i32),
-            Primitive::Number(f32) => format!("{}", f32),
-            Primitive::Boolean(bool) => format!("{:?}", bool),
-            Primitive::String(pdf_string) => format!("{:?}", pdf_string),
-            Primitive::Stream(pdf_stream) => format!("{}\nStream raw data <...>\n", pdf_stream.info.format()),
-            Primitive::Dictionary(dict) => format!("<<\n{}\n>>", dict.format()),
-            Primitive::Array(vec) => vec.format(),
-            Primitive::Reference(plain_ref) => plain_ref.format(),
-            Primitive::Name(small_string) => format!("/{}", small_string)
+            RawObject::String(_) => format!("({})", self.as_text().unwrap_or_default()),
+            RawObject::Stream(dict, range) => format!("{}\nStream raw data <{} bytes>\n", dict.format(), range.len()),
+            RawObject::Dictionary(dict) => format!("<<\n{}\n>>", dict.format()),
+            RawObject::Array(vec) => vec.format(),
+            other => other.to_string(),
         }
     }
 }
 
 
-impl Print for Vec<Primitive> {
+impl Print for Vec<RawObject> {
     fn format(&self) -> String {
-        let elements = self.iter().map(|primitive| primitive.format()).reduce(|a,b| a + " " + b.as_str()).unwrap_or(String::from(""));
+        let elements = self.iter().map(|object| object.format()).reduce(|a,b| a + " " + b.as_str()).unwrap_or(String::from(""));
         format!("[ {} ]", elements)
     }
 }
 
 
 fn main() -> ExitCode {
-    if let Err(error) = Inspector::main() {
+    if let Err(error) = Error::catch_panic(Inspector::main) {
         error.print();
         error.exit_code
     } else {
@@ -0,0 +1,139 @@
+use zugferd::{Error, Font, Invoice, RenderOptions};
+
+use std::fs;
+use std::process::ExitCode;
+
+use clap::Parser;
+
+
+fn main() -> ExitCode {
+    let cli = Render::parse();
+
+    match cli.render() {
+        Err(error) => {
+            error.print();
+            error.exit_code
+        },
+
+        Ok(_) => ExitCode::SUCCESS
+    }
+}
+
+
+// Error codes:
+//  1-9 : Basic File IO Errors
+// 60-69: Invoice XML problem
+// 140-149: Font problem (not 70-79, which extract uses for e-mail and ZIP inputs)
+
+
+/// Fonts we try to embed if no --font is passed
+const DEFAULT_FONTS: [&str; 4] = [
+    "C:\\Windows\\Fonts\\arial.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/Library/Fonts/Arial.ttf",
+];
+
+
+// Command line args
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+pub struct Render {
+    /// XML invoice input file (CII or UBL)
+    xml_input: std::path::PathBuf,
+
+    /// PDF output path (default = xmlInput + ".pdf")
+    pdf_output: Option<std::path::PathBuf>,
+
+    /// TrueType font to embed (default: Arial or DejaVu Sans if installed)
+    #[arg(short, long)]
+    font: Option<std::path::PathBuf>,
+
+    /// ICC profile to embed as PDF/A output intent
+    #[arg(short, long)]
+    icc_profile: Option<std::path::PathBuf>,
+
+    /// Name of the embedded XML attachment (default: "factur-x.xml" for CII and "xrechnung.xml" for UBL)
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Print additional info to the console
+    #[arg(short, long, default_value_t=false)]
+    verbose: bool,
+}
+
+impl Render {
+    fn input_path(&self) -> std::path::PathBuf {
+        Self::resolve_path(&self.xml_input)
+    }
+
+    fn output_path(&self) -> std::path::PathBuf {
+        let path = match self.pdf_output.as_ref() {
+            Some(path) => path.clone(),
+            None => {
+                // Add .pdf extension to input path
+                let mut output = self.xml_input.clone();
+                output.set_extension("xml.pdf");
+                output
+            }
+        };
+
+        Self::resolve_path(&path)
+    }
+
+    fn resolve_path(path: &std::path::PathBuf) -> std::path::PathBuf {
+        // Resolve to absolute path if necessary
+        if path.is_relative() {
+            std::env::current_dir().unwrap().join(path)
+        } else {
+            path.clone()
+        }
+    }
+
+    fn verbose_log(&self, message: String) {
+        if self.verbose {
+            println!("{}", message);
+        }
+    }
+
+    /// Loads the passed font or the first installed default font
+    fn load_font(&self) -> Result<Font, Error> {
+        if let Some(path) = self.font.as_ref() {
+            return Font::load(&Self::resolve_path(path));
+        }
+
+        let path = DEFAULT_FONTS.iter().map(std::path::Path::new).find(|path| path.exists())
+            .ok_or(Error::from(142, String::from("No default font found, please pass a TrueType font with --font")))?;
+        self.verbose_log(format!("Using font: {}", path.display()));
+        Font::load(path)
+    }
+
+
+    /// The render main function
+    fn render(&self) -> Result<(), Error> {
+        let input_path = self.input_path();
+        let output_path = self.output_path();
+
+        self.verbose_log(format!("Reading: {}", input_path.display()));
+        let xml = fs::read(&input_path).map_err(|err| Error::from(1, format!("Failed to open {}: {}", input_path.display(), err)))?;
+        let xml_string = String::from_utf8(xml.clone()).map_err(|utf8_err| Error::from(60, format!("Failed to decode {} as utf8: {}", input_path.display(), utf8_err)))?;
+
+        let invoice = Invoice::parse(&xml_string)?;
+        self.verbose_log(format!("Parsed invoice {} with {} lines", invoice.number, invoice.lines.len()));
+
+        let font = self.load_font()?;
+        let icc_profile = match self.icc_profile.as_ref() {
+            Some(path) => Some(fs::read(path).map_err(|err| Error::from(4, format!("Failed to read ICC profile {}: {}", path.display(), err)))?),
+            None => None
+        };
+
+        let options = RenderOptions {
+            attachment_name: self.name.as_deref(),
+            icc_profile: icc_profile.as_deref(),
+        };
+        let pdf = zugferd::render_hybrid(&invoice, &xml, &font, &options);
+
+        self.verbose_log(format!("Writing: {}", output_path.display()));
+        fs::write(&output_path, pdf).map_err(|err| Error::from(2, format!("Failed to write {}: {}", output_path.display(), err)))
+    }
+}
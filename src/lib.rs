@@ -1,3 +1,24 @@
 mod zugferd;
 
 pub use zugferd::{Error, FileMatcher};
+pub use zugferd::{Invoice, InvoiceLine, Party, VatBreakdown, Totals, Decimal, Syntax};
+pub use zugferd::{Font, render_hybrid, RenderOptions};
+pub use zugferd::{INVOICE_JSON_SCHEMA, INVOICE_JSON_VERSION};
+pub use zugferd::{CsvOptions, CsvTable, CsvWriter};
+pub use zugferd::{DatevMapping, DatevOptions, DatevRule, DatevWriter};
+pub use zugferd::{RawObject, RawDictionary, RawParser, XRef, XRefEntry, XRefSection, SectionKind, RawFile};
+pub use zugferd::{decode_stages, FilterStage};
+pub use zugferd::{FileType, is_binary};
+pub use zugferd::{Revision, revisions, revision_data};
+pub use zugferd::{recover_xref, needs_recovery, repair};
+pub use zugferd::{CarvedInvoice, carve_invoices};
+pub use zugferd::{Limits, check_embedded_files};
+pub use zugferd::{FileData, FileSpecification, EmbeddedFileParams, associated_files, embedded_files};
+pub use zugferd::{PdfDate, xmp_dates};
+pub use zugferd::{MailMessage, MailAttachment, split_mbox};
+pub use zugferd::{Archive, ArchiveEntry};
+pub use zugferd::{Signature, UnsignedChange, signatures, unsigned_attachment_changes};
+pub use zugferd::{TrustStore, Integrity, Trust, SignatureVerification, verify_signature};
+pub use zugferd::{SecurityHandler, CryptMethod, PasswordKind};
+#[cfg(feature = "testing")]
+pub use zugferd::testing;
@@ -1,5 +1,52 @@
 mod filematcher;
 mod error;
+mod invoice;
+mod font;
+mod writer;
+mod render;
+mod json;
+mod csv;
+mod datev;
+mod raw;
+mod filter;
+mod xref;
+mod filetype;
+mod revision;
+mod recovery;
+mod carve;
+mod limits;
+mod signature;
+mod verify;
+mod crypt;
+mod filedata;
+mod embedded;
+mod date;
+mod mail;
+mod archive;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use filematcher::FileMatcher;
 pub use error::Error;
+pub use invoice::{Invoice, InvoiceLine, Party, VatBreakdown, Totals, Decimal, Syntax};
+pub use font::Font;
+pub use render::{render_hybrid, RenderOptions};
+pub use json::{INVOICE_JSON_SCHEMA, INVOICE_JSON_VERSION};
+pub use csv::{CsvOptions, CsvTable, CsvWriter};
+pub use datev::{DatevMapping, DatevOptions, DatevRule, DatevWriter};
+pub use raw::{RawObject, RawDictionary, RawParser};
+pub use filter::{decode_stages, FilterStage};
+pub use xref::{XRef, XRefEntry, XRefSection, SectionKind, RawFile};
+pub use filetype::{FileType, is_binary};
+pub use revision::{Revision, revisions, revision_data};
+pub use recovery::{recover_xref, needs_recovery, repair};
+pub use carve::{CarvedInvoice, carve_invoices};
+pub use limits::{Limits, check_embedded_files};
+pub use filedata::FileData;
+pub use embedded::{FileSpecification, EmbeddedFileParams, associated_files, embedded_files};
+pub use date::{PdfDate, xmp_dates};
+pub use mail::{MailMessage, MailAttachment, split_mbox};
+pub use archive::{Archive, ArchiveEntry};
+pub use signature::{Signature, UnsignedChange, signatures, unsigned_attachment_changes};
+pub use verify::{TrustStore, Integrity, Trust, SignatureVerification, verify_signature};
+pub use crypt::{SecurityHandler, CryptMethod, PasswordKind};
@@ -0,0 +1,71 @@
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use zip::ZipArchive;
+
+use super::limits::Limits;
+use super::Error;
+
+
+/// A file of a ZIP archive
+#[derive(Clone, Debug)]
+pub struct ArchiveEntry {
+    /// Relative path of the entry inside the archive (never absolute and without `..` components)
+    pub path: PathBuf,
+    /// The decompressed content
+    pub data: Vec<u8>,
+}
+
+/// A ZIP archive (like the monthly invoice bundle of a supplier portal) whose entries are decompressed one at a time
+/// within the resource limits: an entry may neither decompress to more than `max_stream_size` bytes nor exceed
+/// `max_compression_ratio`, no matter which sizes the archive claims. Entries whose path would leave the directory
+/// they are extracted to are rejected.
+pub struct Archive<'a> {
+    archive: ZipArchive<Cursor<&'a [u8]>>,
+    limits: Limits,
+}
+
+impl<'a> Archive<'a> {
+    /// Reads the central directory of the archive. Fails with 73 if the data is not a ZIP archive.
+    pub fn open(data: &'a [u8], limits: Limits) -> Result<Archive<'a>, Error> {
+        let archive = ZipArchive::new(Cursor::new(data)).map_err(|error| Error::from(73, format!("Failed to read the ZIP archive: {}", error)))?;
+        Ok(Archive { archive, limits })
+    }
+
+    /// Number of entries (including directories)
+    pub fn len(&self) -> usize {
+        self.archive.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.archive.is_empty()
+    }
+
+    /// Name of the entry as stored in the archive (for reports, it may be an unsafe path)
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.archive.name_for_index(index)
+    }
+
+    /// Decompresses the entry with the given index. Returns None for directories and symbolic links, fails with 74 for
+    /// unsafe paths (absolute or leaving the archive with `..`) and with 75 if the entry cannot be decompressed (like
+    /// encrypted entries, unsupported compression methods or a wrong checksum).
+    pub fn entry(&mut self, index: usize) -> Result<Option<ArchiveEntry>, Error> {
+        let limits = self.limits;
+        let mut file = self.archive.by_index(index).map_err(|error| Error::from(75, format!("Failed to read ZIP entry {}: {}", index, error)))?;
+        if file.is_dir() || file.is_symlink() {
+            return Ok(None);
+        }
+
+        let name = file.name().to_string();
+        let path = file.enclosed_name().ok_or(Error::from(74, format!("ZIP entry {} has an unsafe path", name)))?;
+
+        // The sizes in the archive are not trusted, reading stops right after the limit
+        let compressed_size = usize::try_from(file.compressed_size()).unwrap_or(usize::MAX);
+        let max_size = limits.max_decoded_size(compressed_size);
+        let mut data = Vec::new();
+        (&mut file).take(max_size as u64 + 1).read_to_end(&mut data).map_err(|error| Error::from(75, format!("Failed to decompress ZIP entry {}: {}", name, error)))?;
+        limits.check_decoded_size(compressed_size, data.len())?;
+
+        Ok(Some(ArchiveEntry { path, data }))
+    }
+}
@@ -0,0 +1,89 @@
+use regex::bytes::Regex;
+use roxmltree::Document;
+
+use super::invoice::{Invoice, Syntax};
+use super::raw::{self, RawObject};
+use super::xref::RawFile;
+
+
+const CII_NAMESPACE: &str = "urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100";
+const UBL_INVOICE_NAMESPACE: &str = "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2";
+const UBL_CREDIT_NOTE_NAMESPACE: &str = "urn:oasis:names:specification:ubl:schema:xsd:CreditNote-2";
+
+
+/// An invoice XML found inside a stream of the file without following the file specifications.
+/// Such an invoice is never a conformant e-invoice, as the XML is not attached properly.
+#[derive(Clone, Debug)]
+pub struct CarvedInvoice {
+    /// Object id of the stream containing the XML
+    pub id: u64,
+    /// Local name of the root element (CrossIndustryInvoice, Invoice or CreditNote)
+    pub root: String,
+    pub syntax: Syntax,
+    /// How likely this is the invoice of the document (0-100): the root element uses the namespace of its syntax (30),
+    /// the XML is well-formed (20), it can be parsed as invoice with an invoice number (20) and it is stored
+    /// in an /EmbeddedFile stream (10). Finding the root element alone gives 20.
+    pub confidence: u8,
+    /// The XML starting at its XML declaration (if any) up to the end tag of the root element (or the end of the stream)
+    pub xml: Vec<u8>,
+}
+
+
+/// Last resort for documents with missing or broken file specifications: decodes every stream of the file and looks for
+/// the root element of a CII or UBL invoice. Returns the candidates ordered by confidence (highest first).
+pub fn carve_invoices(file: &RawFile) -> Vec<CarvedInvoice> {
+    let root = Regex::new(r"<(?:([A-Za-z_][\w.-]*):)?(CrossIndustryInvoice|Invoice|CreditNote)[\x00\t\n\x0C\r />]").unwrap();
+    let mut candidates = Vec::new();
+
+    for id in file.entries().keys() {
+        let Ok(object) = file.resolve(*id) else { continue; };
+        let RawObject::Stream(dict, _) = &object else { continue; };
+        if matches!(dict.type_name(), Some("XRef" | "ObjStm")) {
+            continue;
+        }
+        let Ok(data) = file.stream_data(&object) else { continue; };
+        let Some(captures) = root.captures(&data) else { continue; };
+
+        let start_tag = captures.get(0).unwrap();
+        let name = String::from_utf8_lossy(&captures[2]).to_string();
+        let prefix = captures.get(1).map(|prefix| format!("{}:", String::from_utf8_lossy(prefix.as_bytes()))).unwrap_or_default();
+
+        // Start at the XML declaration before the root element and end after its end tag
+        let start = raw::rfind(&data[..start_tag.start()], b"<?xml").unwrap_or(start_tag.start());
+        let end_tag = format!("</{}{}>", prefix, name);
+        let end = raw::find(&data, end_tag.as_bytes(), start_tag.start()).map_or(data.len(), |end| end + end_tag.len());
+        let xml = data[start..end].to_vec();
+
+        let syntax = if name == "CrossIndustryInvoice" { Syntax::Cii } else { Syntax::Ubl };
+        let namespace = match name.as_str() {
+            "CrossIndustryInvoice" => CII_NAMESPACE,
+            "Invoice" => UBL_INVOICE_NAMESPACE,
+            _ => UBL_CREDIT_NOTE_NAMESPACE,
+        };
+
+        let mut confidence = 20;
+        let text = std::str::from_utf8(&xml).ok();
+        match text.and_then(|text| Document::parse(text).ok()) {
+            Some(document) => {
+                confidence += 20;
+                if document.root_element().tag_name().namespace() == Some(namespace) {
+                    confidence += 30;
+                }
+            },
+            // Broken XML only gets the namespace bonus if the namespace is declared at all
+            None if raw::find(&xml, namespace.as_bytes(), 0).is_some() => confidence += 30,
+            None => {},
+        }
+        if text.is_some_and(|text| Invoice::parse(text).is_ok()) {
+            confidence += 20;
+        }
+        if dict.type_name() == Some("EmbeddedFile") {
+            confidence += 10;
+        }
+
+        candidates.push(CarvedInvoice { id: *id, root: name, syntax, confidence, xml });
+    }
+
+    candidates.sort_by(|a, b| b.confidence.cmp(&a.confidence).then(b.xml.len().cmp(&a.xml.len())));
+    candidates
+}
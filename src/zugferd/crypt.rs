@@ -0,0 +1,355 @@
+use aes::cipher::block_padding::{NoPadding, Pkcs7};
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::{Aes128, Aes256};
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use super::raw::{RawDictionary, RawObject};
+use super::Error;
+
+
+/// Padding string of the standard security handler for passwords shorter than 32 bytes
+const PASSWORD_PADDING: [u8; 32] = [
+    0x28, 0xbf, 0x4e, 0x5e, 0x4e, 0x75, 0x8a, 0x41, 0x64, 0x00, 0x4e, 0x56, 0xff, 0xfa, 0x01, 0x08,
+    0x2e, 0x2e, 0x00, 0xb6, 0xd0, 0x68, 0x3e, 0x80, 0x2f, 0x0c, 0xa9, 0xfe, 0x64, 0x53, 0x69, 0x7a,
+];
+
+
+/// Encryption algorithm of strings, streams or embedded files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CryptMethod {
+    /// Not encrypted
+    Identity,
+    Rc4,
+    Aes128,
+    Aes256,
+}
+
+impl std::fmt::Display for CryptMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CryptMethod::Identity => write!(f, "none"),
+            CryptMethod::Rc4 => write!(f, "RC4"),
+            CryptMethod::Aes128 => write!(f, "AES-128"),
+            CryptMethod::Aes256 => write!(f, "AES-256"),
+        }
+    }
+}
+
+/// Which of the two passwords of the document has been used to decrypt it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordKind {
+    User,
+    Owner,
+}
+
+impl std::fmt::Display for PasswordKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordKind::User => write!(f, "user"),
+            PasswordKind::Owner => write!(f, "owner"),
+        }
+    }
+}
+
+
+/// The standard security handler (/Filter /Standard) of an encrypted PDF with the file key
+/// derived from the user or owner password. Supports RC4 (40-128 bit), AES-128 and AES-256 (revisions 2 to 6).
+#[derive(Clone, Debug)]
+pub struct SecurityHandler {
+    /// /V of the encryption dictionary (algorithm)
+    pub version: i64,
+    /// /R of the encryption dictionary (revision of the standard security handler)
+    pub revision: i64,
+    /// Length of the file key in bits
+    pub key_length: usize,
+    /// The access permissions (/P) granted to users who opened the document with the user password
+    pub permissions: i32,
+    pub encrypt_metadata: bool,
+    pub string_method: CryptMethod,
+    pub stream_method: CryptMethod,
+    /// Method of the embedded file streams (/EFF, defaults to the stream method)
+    pub file_method: CryptMethod,
+    pub authenticated: PasswordKind,
+    key: Vec<u8>,
+}
+
+impl SecurityHandler {
+    /// Derives the file key from the given user or owner password. `id` is the first element of the /ID array of the trailer.
+    pub fn new(encrypt: &RawDictionary, id: &[u8], password: &[u8]) -> Result<SecurityHandler, Error> {
+        let filter = encrypt.get("Filter").and_then(RawObject::as_name).unwrap_or_default();
+        if filter != "Standard" {
+            return Err(Error::from(110, format!("Unsupported security handler /{}", filter)));
+        }
+
+        let integer = |key: &str| encrypt.get(key).and_then(RawObject::as_integer);
+        let bytes = |key: &str| match encrypt.get(key) {
+            Some(RawObject::String(bytes)) => bytes.clone(),
+            _ => Vec::new(),
+        };
+
+        let version = integer("V").unwrap_or(0);
+        let revision = integer("R").unwrap_or(0);
+        let permissions = integer("P").unwrap_or(0) as i32;
+        let encrypt_metadata = !matches!(encrypt.get("EncryptMetadata"), Some(RawObject::Boolean(false)));
+        let (owner, user) = (bytes("O"), bytes("U"));
+
+        let (string_method, stream_method, file_method) = match version {
+            1 | 2 => (CryptMethod::Rc4, CryptMethod::Rc4, CryptMethod::Rc4),
+            4 | 5 => {
+                let string_method = crypt_filter(encrypt, "StrF")?;
+                let stream_method = crypt_filter(encrypt, "StmF")?;
+                let file_method = if encrypt.get("EFF").is_some() { crypt_filter(encrypt, "EFF")? } else { stream_method };
+                (string_method, stream_method, file_method)
+            },
+            _ => return Err(Error::from(111, format!("Unsupported encryption algorithm /V {}", version))),
+        };
+
+        let key_length = match version {
+            1 => 40,
+            2 => integer("Length").unwrap_or(40).clamp(40, 128) as usize,
+            4 => 128,
+            _ => 256,
+        };
+
+        let mut handler = SecurityHandler {
+            version, revision, key_length, permissions, encrypt_metadata, string_method, stream_method, file_method,
+            authenticated: PasswordKind::User,
+            key: Vec::new(),
+        };
+
+        match revision {
+            2..=4 if owner.len() >= 32 && user.len() >= 32 => {
+                // Try the password as user password first and otherwise recover the user password from /O
+                let key = handler.compute_key(password, &owner, id);
+                if handler.is_user_key(&key, &user, id) {
+                    handler.key = key;
+                } else {
+                    let user_password = handler.user_password_from_owner(password, &owner);
+                    let key = handler.compute_key(&user_password, &owner, id);
+                    if !handler.is_user_key(&key, &user, id) {
+                        return Err(wrong_password());
+                    }
+                    handler.key = key;
+                    handler.authenticated = PasswordKind::Owner;
+                }
+            },
+            5 | 6 if owner.len() >= 48 && user.len() >= 48 => {
+                let password = &password[..password.len().min(127)];
+                let hash = |salt: &[u8], user_data: &[u8]| if revision == 5 {
+                    Sha256::new().chain_update(password).chain_update(salt).chain_update(user_data).finalize().to_vec()
+                } else {
+                    hash_r6(password, salt, user_data)
+                };
+
+                let (intermediate, encrypted_key) = if hash(&user[32..40], &[]) == user[..32] {
+                    (hash(&user[40..48], &[]), bytes("UE"))
+                } else if hash(&owner[32..40], &user[..48]) == owner[..32] {
+                    handler.authenticated = PasswordKind::Owner;
+                    (hash(&owner[40..48], &user[..48]), bytes("OE"))
+                } else {
+                    return Err(wrong_password());
+                };
+
+                handler.key = cbc::Decryptor::<Aes256>::new_from_slices(&intermediate, &[0; 16])
+                    .ok()
+                    .and_then(|cipher| cipher.decrypt_padded_vec_mut::<NoPadding>(&encrypted_key).ok())
+                    .filter(|key| key.len() == 32)
+                    .ok_or(Error::from(113, String::from("Failed to decrypt the file key (/UE or /OE)")))?;
+            },
+            _ => return Err(Error::from(111, format!("Unsupported revision /R {} of the standard security handler", revision))),
+        }
+
+        Ok(handler)
+    }
+
+    /// Decrypts a string or stream of the object with the given id and generation
+    pub fn decrypt(&self, method: CryptMethod, id: u64, gen: u16, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match method {
+            CryptMethod::Identity => Ok(data.to_vec()),
+            CryptMethod::Rc4 => Ok(rc4(&self.object_key(id, gen, false), data)),
+            CryptMethod::Aes128 => aes_decrypt(&self.object_key(id, gen, true), data),
+            CryptMethod::Aes256 => aes_decrypt(&self.key, data),
+        }
+    }
+
+    /// Names of the permissions granted by /P
+    pub fn permission_names(&self) -> Vec<&'static str> {
+        const PERMISSIONS: [(u32, &str); 8] = [
+            (3, "print"), (4, "modify"), (5, "copy"), (6, "annotate"),
+            (9, "fill forms"), (10, "extract for accessibility"), (11, "assemble"), (12, "print high quality"),
+        ];
+        PERMISSIONS.iter().filter(|(bit, _)| self.permissions & (1 << (bit - 1)) != 0).map(|(_, name)| *name).collect()
+    }
+
+    /// Length of the file key of revisions 2 to 4 in bytes, which is at most the length of an MD5 hash
+    /// (a /V 5 dictionary with such a revision would ask for a 256 bit key)
+    fn md5_key_length(&self) -> usize {
+        if self.revision == 2 { 5 } else { (self.key_length / 8).min(16) }
+    }
+
+    /// Algorithm 2: The file key of revisions 2 to 4
+    fn compute_key(&self, password: &[u8], owner: &[u8], id: &[u8]) -> Vec<u8> {
+        let length = self.md5_key_length();
+        let mut hasher = Md5::new();
+        hasher.update(pad_password(password));
+        hasher.update(&owner[..32]);
+        hasher.update(self.permissions.to_le_bytes());
+        hasher.update(id);
+        if self.revision >= 4 && !self.encrypt_metadata {
+            hasher.update([0xff; 4]);
+        }
+
+        let mut hash = hasher.finalize().to_vec();
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                hash = Md5::digest(&hash[..length]).to_vec();
+            }
+        }
+        hash.truncate(length);
+        hash
+    }
+
+    /// Algorithms 4 and 5: The /U entry is the padding (or its hash with the /ID) encrypted with the file key
+    fn is_user_key(&self, key: &[u8], user: &[u8], id: &[u8]) -> bool {
+        if self.revision == 2 {
+            return rc4(key, &PASSWORD_PADDING) == user[..32];
+        }
+
+        let hash = Md5::new().chain_update(PASSWORD_PADDING).chain_update(id).finalize();
+        let encrypted = (1..=19).fold(rc4(key, &hash), |data, round| rc4(&xor_key(key, round), &data));
+        encrypted[..16] == user[..16]
+    }
+
+    /// Algorithm 7: The /O entry is the padded user password encrypted with a key derived from the owner password
+    fn user_password_from_owner(&self, password: &[u8], owner: &[u8]) -> Vec<u8> {
+        let length = self.md5_key_length();
+        let mut hash = Md5::digest(pad_password(password)).to_vec();
+        if self.revision >= 3 {
+            for _ in 0..50 {
+                hash = Md5::digest(&hash).to_vec();
+            }
+        }
+        let key = &hash[..length];
+
+        if self.revision == 2 {
+            rc4(key, &owner[..32])
+        } else {
+            (0..=19).rev().fold(owner[..32].to_vec(), |data, round| rc4(&xor_key(key, round), &data))
+        }
+    }
+
+    /// Algorithm 1: The key of a single object for RC4 and AES-128
+    fn object_key(&self, id: u64, gen: u16, aes: bool) -> Vec<u8> {
+        let mut hasher = Md5::new();
+        hasher.update(&self.key);
+        hasher.update(&id.to_le_bytes()[..3]);
+        hasher.update(gen.to_le_bytes());
+        if aes {
+            hasher.update(b"sAlT");
+        }
+        let mut key = hasher.finalize().to_vec();
+        key.truncate((self.key.len() + 5).min(16));
+        key
+    }
+}
+
+
+/// Returns the method of the crypt filter referenced by /StmF, /StrF or /EFF
+fn crypt_filter(encrypt: &RawDictionary, key: &str) -> Result<CryptMethod, Error> {
+    let name = encrypt.get(key).and_then(RawObject::as_name).unwrap_or("Identity");
+    if name == "Identity" {
+        return Ok(CryptMethod::Identity);
+    }
+
+    let filter = encrypt.get("CF").and_then(RawObject::as_dictionary).and_then(|filters| filters.get(name)).and_then(RawObject::as_dictionary)
+        .ok_or(Error::from(111, format!("Crypt filter /{} not found in /CF", name)))?;
+    match filter.get("CFM").and_then(RawObject::as_name).unwrap_or("None") {
+        "None" => Ok(CryptMethod::Identity),
+        "V2" => Ok(CryptMethod::Rc4),
+        "AESV2" => Ok(CryptMethod::Aes128),
+        "AESV3" => Ok(CryptMethod::Aes256),
+        other => Err(Error::from(111, format!("Unsupported crypt filter method /{}", other))),
+    }
+}
+
+fn wrong_password() -> Error {
+    Error::from(112, String::from("The document is encrypted and the password is wrong (pass it with --password or --password-file)"))
+}
+
+fn pad_password(password: &[u8]) -> [u8; 32] {
+    let mut padded = PASSWORD_PADDING;
+    let length = password.len().min(32);
+    padded[..length].copy_from_slice(&password[..length]);
+    padded[length..].copy_from_slice(&PASSWORD_PADDING[..32 - length]);
+    padded
+}
+
+fn xor_key(key: &[u8], value: u8) -> Vec<u8> {
+    key.iter().map(|byte| byte ^ value).collect()
+}
+
+/// Algorithm 2.B: The hash of revision 6, which mixes SHA-256/384/512 and AES-128 for at least 64 rounds
+fn hash_r6(password: &[u8], salt: &[u8], user_data: &[u8]) -> Vec<u8> {
+    let mut hash = Sha256::new().chain_update(password).chain_update(salt).chain_update(user_data).finalize().to_vec();
+    let mut round = 0;
+    loop {
+        let block = [password, &hash, user_data].concat().repeat(64);
+        let Ok(cipher) = cbc::Encryptor::<Aes128>::new_from_slices(&hash[..16], &hash[16..32]) else { break; };
+        let encrypted = cipher.encrypt_padded_vec_mut::<NoPadding>(&block);
+
+        // The first 16 bytes as big integer modulo 3 (which is the same as the sum of the bytes modulo 3)
+        hash = match encrypted[..16].iter().map(|byte| *byte as u32).sum::<u32>() % 3 {
+            0 => Sha256::digest(&encrypted).to_vec(),
+            1 => Sha384::digest(&encrypted).to_vec(),
+            _ => Sha512::digest(&encrypted).to_vec(),
+        };
+
+        round += 1;
+        if round >= 64 && encrypted.last().map_or(0, |last| *last as usize) + 32 <= round {
+            break;
+        }
+    }
+    hash.truncate(32);
+    hash
+}
+
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    if key.is_empty() {
+        return data.to_vec();
+    }
+
+    let mut state: Vec<u8> = (0..=255).collect();
+    let mut j: u8 = 0;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+
+    let (mut i, mut j) = (0u8, 0u8);
+    data.iter().map(|byte| {
+        i = i.wrapping_add(1);
+        j = j.wrapping_add(state[i as usize]);
+        state.swap(i as usize, j as usize);
+        byte ^ state[state[i as usize].wrapping_add(state[j as usize]) as usize]
+    }).collect()
+}
+
+/// AES in CBC mode with the initialization vector in the first 16 bytes and PKCS#7 padding (the key length selects AES-128 or AES-256)
+fn aes_decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < 16 {
+        return Err(Error::from(113, format!("Encrypted data of {} bytes is too short for AES", data.len())));
+    }
+
+    let (iv, encrypted) = data.split_at(16);
+    let decrypted = match key.len() {
+        16 => cbc::Decryptor::<Aes128>::new_from_slices(key, iv).map(|cipher| cipher.decrypt_padded_vec_mut::<Pkcs7>(encrypted)),
+        _ => cbc::Decryptor::<Aes256>::new_from_slices(key, iv).map(|cipher| cipher.decrypt_padded_vec_mut::<Pkcs7>(encrypted)),
+    };
+
+    match decrypted {
+        Ok(Ok(data)) => Ok(data),
+        Ok(Err(_)) => Err(Error::from(113, String::from("Failed to decrypt AES data (wrong length or padding)"))),
+        Err(err) => Err(Error::from(113, format!("Invalid AES key: {}", err))),
+    }
+}
@@ -0,0 +1,127 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use super::{Decimal, Error, Invoice};
+
+
+/// Formatting options for the CSV export. German Excel expects ';' as delimiter and ',' as decimal separator
+/// and only detects UTF-8 files correctly if they start with a byte order mark.
+#[derive(Clone, Copy)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub decimal_separator: char,
+    pub bom: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions { delimiter: ',', decimal_separator: '.', bom: false }
+    }
+}
+
+
+/// The tables which can be exported as CSV
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CsvTable {
+    /// One row per invoice line
+    Lines,
+    /// One row per entry of the VAT breakdown
+    VatBreakdown,
+}
+
+impl CsvTable {
+    pub fn header(&self) -> &'static [&'static str] {
+        match self {
+            CsvTable::Lines => &["source", "invoice_number", "issue_date", "currency", "position", "seller_item_id", "buyer_item_id", "global_id",
+                                 "description", "quantity", "unit_code", "net_price", "line_total", "vat_category", "vat_rate"],
+            CsvTable::VatBreakdown => &["source", "invoice_number", "issue_date", "currency", "vat_category", "vat_rate", "basis", "vat_amount", "exemption_reason"],
+        }
+    }
+
+    /// Returns the rows of this table for the given invoice. The source (e.g. the PDF file name) is written
+    /// into the first column to tell the invoices apart when aggregating several invoices in one file.
+    pub fn rows(&self, invoice: &Invoice, source: &str, options: &CsvOptions) -> Vec<Vec<String>> {
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        let decimal = |value: &Option<Decimal>| value.map(|value| value.format(options.decimal_separator)).unwrap_or_default();
+        let document = [String::from(source), invoice.number.clone(), text(&invoice.issue_date), text(&invoice.currency)];
+
+        match self {
+            CsvTable::Lines => invoice.lines.iter().map(|line| document.iter().cloned().chain([
+                line.id.clone(),
+                text(&line.seller_item_id),
+                text(&line.buyer_item_id),
+                text(&line.global_id),
+                line.description.as_ref().map_or(line.name.clone(), |description| format!("{} {}", line.name, description).trim().to_string()),
+                decimal(&line.quantity),
+                text(&line.unit_code),
+                decimal(&line.net_price),
+                decimal(&line.line_total),
+                text(&line.vat_category),
+                decimal(&line.vat_rate),
+            ]).collect()).collect(),
+
+            CsvTable::VatBreakdown => invoice.vat_breakdown.iter().map(|vat| document.iter().cloned().chain([
+                vat.category.clone(),
+                decimal(&vat.rate),
+                decimal(&vat.basis),
+                decimal(&vat.amount),
+                text(&vat.exemption_reason),
+            ]).collect()).collect(),
+        }
+    }
+}
+
+
+/// Writes one CSV table into a file. Existing files are appended to (without repeating the header),
+/// so repeated runs over a batch of invoices aggregate all rows into a single file.
+pub struct CsvWriter {
+    file: File,
+    table: CsvTable,
+    options: CsvOptions,
+}
+
+impl CsvWriter {
+    pub fn append(path: &Path, table: CsvTable, options: CsvOptions) -> Result<CsvWriter, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(|err| Error::from(80, format!("Failed to open {}: {}", path.display(), err)))?;
+        let is_new = file.metadata().map_or(true, |metadata| metadata.len() == 0);
+
+        let mut writer = CsvWriter { file, table, options };
+        if is_new {
+            if options.bom {
+                writer.write_bytes("\u{FEFF}".as_bytes())?;
+            }
+            let header: Vec<String> = table.header().iter().map(|column| String::from(*column)).collect();
+            writer.write_row(&header)?;
+        }
+
+        Ok(writer)
+    }
+
+    pub fn write_invoice(&mut self, invoice: &Invoice, source: &str) -> Result<(), Error> {
+        for row in self.table.rows(invoice, source, &self.options) {
+            self.write_row(&row)?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &[String]) -> Result<(), Error> {
+        let delimiter = self.options.delimiter.to_string();
+        let line = row.iter().map(|value| quote(value, self.options.delimiter)).collect::<Vec<_>>().join(&delimiter) + "\r\n";
+        self.write_bytes(line.as_bytes())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.file.write_all(bytes).map_err(|err| Error::from(81, format!("Failed to write CSV file: {}", err)))
+    }
+}
+
+
+/// Quotes the value if it contains the delimiter, quotes or line breaks
+fn quote(value: &str, delimiter: char) -> String {
+    if value.contains(delimiter) || value.contains('"') || value.contains('\n') || value.contains('\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
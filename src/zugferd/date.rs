@@ -0,0 +1,275 @@
+use std::fmt::Display;
+
+use regex::Regex;
+
+
+/// A date of a PDF date string (`D:YYYYMMDDHHmmSSOHH'mm'`) or an XMP date (ISO 8601). Both parsers are lenient as many
+/// producers write slightly broken dates (like `’` instead of `'` in the time zone). Such dates are still parsed, but
+/// marked as non-conformant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PdfDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Offset from UTC in minutes (None if the date has no time zone, so it is an unknown local time)
+    pub offset: Option<i16>,
+    /// Whether the date deviates from the syntax of the standard and has only been parsed by a fallback
+    pub non_conformant: bool,
+}
+
+impl PdfDate {
+    /// Parses a PDF date string (like /CreationDate or /ModDate). Missing parts (everything after the year) default to
+    /// the start of the period. Wrong apostrophes, a missing `D:` prefix and ISO 8601 dates are accepted as non-conformant.
+    pub fn parse(text: &str) -> Option<PdfDate> {
+        parse_pdf_syntax(text).or_else(|| parse_iso_syntax(text).map(PdfDate::non_conformant))
+    }
+
+    /// Parses an XMP date (ISO 8601 like `2024-05-01T10:00:00+02:00`, all parts after the year are optional).
+    /// A time zone without colon, a space instead of the `T` and PDF date strings are accepted as non-conformant.
+    pub fn parse_xmp(text: &str) -> Option<PdfDate> {
+        parse_iso_syntax(text).or_else(|| parse_pdf_syntax(text).map(PdfDate::non_conformant))
+    }
+
+    /// Seconds since 1970-01-01T00:00:00Z (dates without time zone are treated as UTC)
+    pub fn timestamp(&self) -> i64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        let seconds = days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        seconds - self.offset.unwrap_or(0) as i64 * 60
+    }
+
+    fn non_conformant(self) -> PdfDate {
+        PdfDate { non_conformant: true, ..self }
+    }
+
+    /// Checks the ranges of all components
+    fn validate(self) -> Option<PdfDate> {
+        let valid = (1..=12).contains(&self.month)
+            && self.day >= 1 && self.day <= days_in_month(self.year, self.month)
+            && self.hour <= 23 && self.minute <= 59 && self.second <= 59
+            && self.offset.unwrap_or(0).abs() < 24 * 60;
+        valid.then_some(self)
+    }
+}
+
+/// ISO 8601 with the time zone if it is known (`2024-05-01T10:00:00+02:00`)
+impl Display for PdfDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)?;
+        match self.offset {
+            Some(0) => write!(f, "Z"),
+            Some(offset) => write!(f, "{}{:02}:{:02}", if offset < 0 { '-' } else { '+' }, offset.abs() / 60, offset.abs() % 60),
+            None => Ok(()),
+        }
+    }
+}
+
+
+/// Returns the dates of the XMP metadata (xmp:CreateDate, xmp:ModifyDate and xmp:MetadataDate) as they are written in
+/// the XMP packet. Like the file name of the invoice they can be written as element or as attribute of the <rdf:Description>.
+pub fn xmp_dates(xmp: &str) -> Vec<(String, String)> {
+    let date_regex = Regex::new(r#"xmp:(?<property>CreateDate|ModifyDate|MetadataDate)((>(?<value1>[^<]*)</xmp:)|(="(?<value2>[^"]*)"))"#).unwrap();
+    date_regex.captures_iter(xmp).map(|captures| {
+        let value = captures.name("value1").or_else(|| captures.name("value2")).map_or("", |value| value.as_str());
+        (format!("xmp:{}", &captures["property"]), String::from(value.trim()))
+    }).collect()
+}
+
+
+/// Cursor over the remaining text of a date
+struct Scanner<'a> {
+    rest: &'a str,
+}
+
+impl Scanner<'_> {
+    /// Consumes exactly `count` ASCII digits (nothing if there are less)
+    fn digits(&mut self, count: usize) -> Option<u16> {
+        let digits = self.rest.get(..count).filter(|digits| digits.bytes().all(|byte| byte.is_ascii_digit()))?;
+        self.rest = &self.rest[count..];
+        digits.parse().ok()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.rest = &self.rest[next.len_utf8()..];
+        Some(next)
+    }
+
+    /// Consumes the given character if it is next
+    fn accept(&mut self, expected: char) -> bool {
+        let accepted = self.peek() == Some(expected);
+        if accepted {
+            self.next();
+        }
+        accepted
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+}
+
+
+/// Removes surrounding whitespace (and NUL bytes, which some producers append), which makes a date non-conformant
+fn trim(text: &str) -> (&str, bool) {
+    let trimmed = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    (trimmed, trimmed.len() != text.len())
+}
+
+fn parse_pdf_syntax(text: &str) -> Option<PdfDate> {
+    let (text, mut non_conformant) = trim(text);
+    let rest = text.strip_prefix("D:").unwrap_or_else(|| {
+        non_conformant = true;
+        text
+    });
+
+    let mut scanner = Scanner { rest };
+    let year = scanner.digits(4)?;
+    let mut fields = [1, 1, 0, 0, 0];
+    for field in fields.iter_mut() {
+        match scanner.digits(2) {
+            Some(value) => *field = value as u8,
+            None => break,
+        }
+    }
+
+    let offset = match scanner.next() {
+        None => None,
+        Some('Z') => {
+            // Old producers write Z00'00'
+            if !scanner.is_empty() && pdf_time_zone(&mut scanner, &mut non_conformant)? != 0 {
+                non_conformant = true;
+            }
+            Some(0)
+        },
+        Some(sign @ ('+' | '-')) => {
+            let offset = pdf_time_zone(&mut scanner, &mut non_conformant)?;
+            Some(if sign == '-' { -offset } else { offset })
+        },
+        Some(_) => return None,
+    };
+
+    if !scanner.is_empty() {
+        return None;
+    }
+
+    let [month, day, hour, minute, second] = fields;
+    PdfDate { year, month, day, hour, minute, second, offset, non_conformant }.validate()
+}
+
+/// Parses the `HH'mm'` after the sign of the time zone. The apostrophes are optional, but anything else than an apostrophe
+/// in their place (`’`, `′`, `"`, `:` or no separator between the hours and minutes at all) makes the date non-conformant.
+fn pdf_time_zone(scanner: &mut Scanner, non_conformant: &mut bool) -> Option<i16> {
+    let hours = scanner.digits(2)?;
+    let minutes = match scanner.peek() {
+        None => 0,
+        Some('\'') => {
+            scanner.next();
+            if scanner.is_empty() { 0 } else { scanner.digits(2)? }
+        },
+        Some(c) if c.is_ascii_digit() => {
+            *non_conformant = true;
+            scanner.digits(2)?
+        },
+        Some(c) if !c.is_alphanumeric() => {
+            *non_conformant = true;
+            scanner.next();
+            if scanner.is_empty() { 0 } else { scanner.digits(2)? }
+        },
+        Some(_) => return None,
+    };
+
+    // The trailing apostrophe of PDF 1.x
+    if let Some(c) = scanner.peek().filter(|c| !c.is_alphanumeric()) {
+        *non_conformant |= c != '\'';
+        scanner.next();
+    }
+
+    Some((hours * 60 + minutes) as i16)
+}
+
+fn parse_iso_syntax(text: &str) -> Option<PdfDate> {
+    let (text, mut non_conformant) = trim(text);
+    let mut scanner = Scanner { rest: text };
+
+    let year = scanner.digits(4)?;
+    let mut date = PdfDate { year, month: 1, day: 1, hour: 0, minute: 0, second: 0, offset: None, non_conformant: false };
+    if scanner.accept('-') {
+        date.month = scanner.digits(2)? as u8;
+        if scanner.accept('-') {
+            date.day = scanner.digits(2)? as u8;
+            if let Some(separator) = scanner.peek().filter(|c| *c == 'T' || *c == ' ') {
+                scanner.next();
+                non_conformant |= separator == ' ';
+                date.hour = scanner.digits(2)? as u8;
+                if !scanner.accept(':') {
+                    return None;
+                }
+                date.minute = scanner.digits(2)? as u8;
+                if scanner.accept(':') {
+                    date.second = scanner.digits(2)? as u8;
+                    // Fractions of a second are dropped
+                    if scanner.accept('.') {
+                        while scanner.peek().is_some_and(|c| c.is_ascii_digit()) {
+                            scanner.next();
+                        }
+                    }
+                }
+                date.offset = iso_time_zone(&mut scanner, &mut non_conformant)?;
+            }
+        }
+    }
+
+    if !scanner.is_empty() {
+        return None;
+    }
+    PdfDate { non_conformant, ..date }.validate()
+}
+
+/// Parses the optional time zone designator (`Z`, `+hh:mm`). `+hhmm` and `+hh` are accepted as non-conformant.
+fn iso_time_zone(scanner: &mut Scanner, non_conformant: &mut bool) -> Option<Option<i16>> {
+    let sign = match scanner.peek() {
+        None => return Some(None),
+        Some('Z') => {
+            scanner.next();
+            return Some(Some(0));
+        },
+        Some('+') => 1,
+        Some('-') => -1,
+        Some(_) => return None,
+    };
+    scanner.next();
+
+    let hours = scanner.digits(2)?;
+    let minutes = if scanner.accept(':') {
+        scanner.digits(2)?
+    } else {
+        *non_conformant = true;
+        if scanner.is_empty() { 0 } else { scanner.digits(2)? }
+    };
+    Some(Some(sign * (hours * 60 + minutes) as i16))
+}
+
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let (year, month) = (year as i64, month as i64);
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    (days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)) as u8
+}
+
+/// Converts (year, month, day) of the proleptic gregorian calendar into days since 1970-01-01
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Algorithm from Howard Hinnant's "chrono-Compatible Low-Level Date Algorithms" (inverse of writer::civil_from_days)
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
@@ -0,0 +1,298 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::font::encode_win_ansi;
+use super::writer::utc_components;
+use super::{Decimal, Error, Invoice};
+
+
+/// Columns we fill of the DATEV Buchungsstapel (format version 700). The remaining columns are optional and omitted.
+const COLUMNS: [&str; 14] = [
+    "Umsatz (ohne Soll/Haben-Kz)", "Soll/Haben-Kennzeichen", "WKZ Umsatz", "Kurs", "Basis-Umsatz", "WKZ Basis-Umsatz", "Konto",
+    "Gegenkonto (ohne BU-Schlüssel)", "BU-Schlüssel", "Belegdatum", "Belegfeld 1", "Belegfeld 2", "Skonto", "Buchungstext",
+];
+
+
+/// Accounts to book an invoice (or the part of an invoice with a certain tax rate) on
+#[derive(Clone, Debug)]
+pub struct DatevRule {
+    /// Supplier VAT ID this rule applies to (None = any supplier)
+    pub vat_id: Option<String>,
+    /// Tax rate this rule applies to (None = any rate)
+    pub rate: Option<Decimal>,
+    pub account: String,
+    pub contra_account: String,
+    pub bu_key: Option<String>,
+}
+
+/// Maps supplier VAT ID and tax rate to DATEV accounts. The mapping file contains one rule per line in the form
+///
+/// ```text
+/// vat_id;rate;account;contra_account[;bu_key]
+/// ```
+///
+/// where `*` matches any VAT ID or rate. Empty lines and lines starting with '#' are ignored.
+/// Rules with a specific VAT ID take precedence over rules with a specific rate, which take precedence over catch-all rules.
+pub struct DatevMapping {
+    rules: Vec<DatevRule>,
+}
+
+impl DatevMapping {
+    pub fn load(path: &Path) -> Result<DatevMapping, Error> {
+        let content = std::fs::read_to_string(path).map_err(|err| Error::from(82, format!("Failed to read DATEV mapping {}: {}", path.display(), err)))?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<DatevMapping, Error> {
+        let mut rules = Vec::new();
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(';').map(str::trim).collect();
+            if fields.len() < 4 || fields.len() > 5 {
+                return Err(Error::from(83, format!("Invalid DATEV mapping line {}: expected vat_id;rate;account;contra_account[;bu_key]", index + 1)));
+            }
+
+            let rate = match fields[1] {
+                "*" => None,
+                rate => Some(Decimal::parse(&rate.replace(',', ".")).ok_or(Error::from(83, format!("Invalid tax rate '{}' in DATEV mapping line {}", rate, index + 1)))?),
+            };
+
+            rules.push(DatevRule {
+                vat_id: Some(fields[0]).filter(|vat_id| *vat_id != "*").map(normalize_vat_id),
+                rate,
+                account: String::from(fields[2]),
+                contra_account: String::from(fields[3]),
+                bu_key: fields.get(4).filter(|key| !key.is_empty()).map(|key| String::from(*key)),
+            });
+        }
+
+        Ok(DatevMapping { rules })
+    }
+
+    /// Returns the most specific rule for the supplier VAT ID and tax rate
+    pub fn find(&self, vat_id: Option<&str>, rate: Option<Decimal>) -> Option<&DatevRule> {
+        let vat_id = vat_id.map(normalize_vat_id);
+        let matches_vat_id = |rule: &&DatevRule| rule.vat_id.is_some() && rule.vat_id == vat_id;
+        let matches_rate = |rule: &&DatevRule| rule.rate.is_some() && rule.rate == rate;
+
+        self.rules.iter().find(|rule| matches_vat_id(rule) && matches_rate(rule))
+            .or_else(|| self.rules.iter().find(|rule| matches_vat_id(rule) && rule.rate.is_none()))
+            .or_else(|| self.rules.iter().find(|rule| rule.vat_id.is_none() && matches_rate(rule)))
+            .or_else(|| self.rules.iter().find(|rule| rule.vat_id.is_none() && rule.rate.is_none()))
+    }
+}
+
+fn normalize_vat_id(vat_id: &str) -> String {
+    vat_id.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase()
+}
+
+
+/// Values of the DATEV header line
+pub struct DatevOptions {
+    /// Beraternummer
+    pub consultant: u32,
+    /// Mandantennummer
+    pub client: u32,
+    /// Start of the fiscal year as YYYYMMDD (default: January 1st of the invoice year)
+    pub fiscal_year_start: Option<String>,
+    /// Length of the general ledger account numbers (Sachkontenlänge). Shorter accounts of the mapping are padded with zeros.
+    pub account_length: u8,
+}
+
+
+/// Writes invoices as bookings into a DATEV Buchungsstapel CSV file (Windows-1252 encoded as required by DATEV).
+/// Like the CSV export, existing files are appended to, so one file can collect the invoices of a batch run.
+/// A Buchungsstapel covers a single fiscal year (the Belegdatum has no year), so invoices of other years are rejected.
+pub struct DatevWriter {
+    file: File,
+    /// First and last day of the fiscal year of the file as YYYYMMDD ("Datum vom" and "Datum bis" of the header)
+    fiscal_year: (u32, u32),
+    /// Sachkontenlänge of the header
+    account_length: usize,
+}
+
+impl DatevWriter {
+    /// Opens the file for appending. The header lines are only written for new files and use the issue date
+    /// of the given invoice to derive the default fiscal year, existing files keep the fiscal year and Sachkontenlänge of their header.
+    pub fn append(path: &Path, options: &DatevOptions, invoice: &Invoice) -> Result<DatevWriter, Error> {
+        let file = OpenOptions::new().create(true).read(true).append(true).open(path).map_err(|err| Error::from(86, format!("Failed to open {}: {}", path.display(), err)))?;
+        let is_new = file.metadata().map_or(true, |metadata| metadata.len() == 0);
+        if !is_new {
+            let (fiscal_year, account_length) = read_header(&file)
+                .ok_or(Error::from(86, format!("{} is no DATEV Buchungsstapel (the header has no valid fiscal year or Sachkontenlänge)", path.display())))?;
+            if let Some(start) = options.fiscal_year_start.as_ref().filter(|start| start.parse::<u32>() != Ok(fiscal_year.0)) {
+                return Err(Error::from(85, format!("The fiscal year start {} differs from the fiscal year {} to {} of {}, please use a new file for each fiscal year",
+                    start, fiscal_year.0, fiscal_year.1, path.display())));
+            }
+            return Ok(DatevWriter { file, fiscal_year, account_length });
+        }
+
+        let fiscal_year_start = match options.fiscal_year_start.as_ref() {
+            Some(start) => start.clone(),
+            None => format!("{:04}0101", invoice_date(invoice)?.0),
+        };
+        let (year, month, day) = parse_compact_date(&fiscal_year_start).filter(|(year, _, _)| *year < 9999)
+            .ok_or(Error::from(85, format!("Invalid fiscal year start '{}', expected YYYYMMDD", fiscal_year_start)))?;
+        let fiscal_year_end = last_day_before(year + 1, month, day);
+
+        let [year, month, day, hour, minute, second] = utc_components(SystemTime::now());
+        let created = format!("{:04}{:02}{:02}{:02}{:02}{:02}000", year, month, day, hour, minute, second);
+
+        let fiscal_year = (fiscal_year_start.parse().unwrap_or(0), fiscal_year_end.parse().unwrap_or(0));
+        let mut writer = DatevWriter { file, fiscal_year, account_length: options.account_length.into() };
+        let header = format!("\"EXTF\";700;21;\"Buchungsstapel\";13;{};;\"RE\";\"\";\"\";{};{};{};{};{};{};\"E-Rechnungen\";\"\";1;0;0;\"EUR\";;\"\";;;\"\";;;\"\";\"\"",
+            created, options.consultant, options.client, fiscal_year_start, options.account_length, fiscal_year_start, fiscal_year_end);
+        writer.write_line(&header)?;
+        writer.write_line(&COLUMNS.join(";"))?;
+        Ok(writer)
+    }
+
+    /// Writes one booking per VAT breakdown entry (gross amount of that tax rate) of the invoice.
+    /// Fails with 85 if the invoice was issued outside the fiscal year of the file, with 88 if an amount has more than
+    /// 2 decimals (other than zeros) and with 89 if the invoice is not in EUR (as there is no exchange rate for the Kurs).
+    pub fn write_invoice(&mut self, invoice: &Invoice, mapping: &DatevMapping) -> Result<(), Error> {
+        let (year, month, day) = invoice_date(invoice)?;
+        let (start, end) = self.fiscal_year;
+        if !(start..=end).contains(&(year * 10000 + month * 100 + day)) {
+            return Err(Error::from(85, format!("Invoice {} of {} is outside the fiscal year {} to {} of the DATEV file, please use a new file for each fiscal year",
+                invoice.number, invoice.issue_date.as_deref().unwrap_or_default(), start, end)));
+        }
+
+        let currency = invoice.currency.as_deref().unwrap_or("EUR");
+        if currency != "EUR" {
+            return Err(Error::from(89, format!("Invoice {} is in {}, but the DATEV export only supports invoices in EUR", invoice.number, currency)));
+        }
+
+        let vat_id = invoice.seller.vat_id.as_deref();
+        let is_credit_note = invoice.type_code.as_deref() == Some("381");
+
+        // Without breakdown we book the grand total with the rule for an unknown rate
+        let amounts: Vec<(Option<Decimal>, Decimal)> = if invoice.vat_breakdown.is_empty() {
+            vec![(None, invoice.totals.grand_total.unwrap_or_default())]
+        } else {
+            invoice.vat_breakdown.iter().map(|vat| {
+                let gross = vat.basis.unwrap_or_default().checked_add(vat.amount.unwrap_or_default())
+                    .ok_or(Error::from(88, format!("Gross amount of the tax rate {} of invoice {} is too large", vat.rate.map_or(String::from("<none>"), |rate| rate.to_string()), invoice.number)))?;
+                Ok((vat.rate, gross))
+            }).collect::<Result<_, Error>>()?
+        };
+
+        for (rate, gross) in amounts {
+            let rule = mapping.find(vat_id, rate).ok_or(Error::from(84, format!("No DATEV mapping for supplier VAT ID {} and tax rate {}",
+                vat_id.unwrap_or("<none>"), rate.map_or(String::from("<none>"), |rate| rate.to_string()))))?;
+
+            // DATEV only accepts amounts with 2 decimals, so only zeros are dropped and not a cent is rounded away
+            let amount = gross.abs().with_scale(2)
+                .ok_or(Error::from(88, format!("Gross amount {} of invoice {} has more than 2 decimals", gross, invoice.number)))?;
+
+            // Incoming invoices are booked as debit on the account, credit notes and negative amounts reverse the booking
+            let debit = gross.is_negative() == is_credit_note;
+            let row = [
+                amount.format(','),
+                quote(if debit { "S" } else { "H" }),
+                quote(currency),
+                String::new(),
+                String::new(),
+                String::new(),
+                pad_account(&rule.account, self.account_length),
+                pad_account(&rule.contra_account, self.account_length),
+                quote(rule.bu_key.as_deref().unwrap_or("")),
+                format!("{:02}{:02}", day, month),
+                quote(&document_field(&invoice.number)),
+                quote(&invoice.due_date.as_deref().and_then(due_date_field).unwrap_or_default()),
+                String::new(),
+                quote(&invoice.seller.name.chars().take(60).collect::<String>()),
+            ];
+            self.write_line(&row.join(";"))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        let mut bytes = encode_win_ansi(line);
+        bytes.extend_from_slice(b"\r\n");
+        self.file.write_all(&bytes).map_err(|err| Error::from(87, format!("Failed to write DATEV file: {}", err)))
+    }
+}
+
+
+/// Returns (year, month, day) of the invoice issue date
+fn invoice_date(invoice: &Invoice) -> Result<(u32, u32, u32), Error> {
+    invoice.issue_date.as_deref().and_then(parse_iso_date)
+        .ok_or(Error::from(85, format!("Invoice {} has no valid issue date, which is required for the DATEV export", invoice.number)))
+}
+
+fn parse_iso_date(date: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = date.get(0..10)?.split('-').map(|part| part.parse::<u32>().ok());
+    Some((parts.next()??, parts.next()??, parts.next()??))
+}
+
+/// Parses a YYYYMMDD date (None if it is no valid calendar date)
+fn parse_compact_date(date: &str) -> Option<(u32, u32, u32)> {
+    if date.len() != 8 || !date.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (year, month, day) = (date[0..4].parse().ok()?, date[4..6].parse().ok()?, date[6..8].parse().ok()?);
+    ((1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day)).then_some((year, month, day))
+}
+
+/// Reads the fiscal year ("Datum vom" and "Datum bis") and the Sachkontenlänge from the header line of an existing Buchungsstapel
+fn read_header(file: &File) -> Option<((u32, u32), usize)> {
+    let mut header = Vec::new();
+    BufReader::new(file).read_until(b'\n', &mut header).ok()?;
+    let header = String::from_utf8_lossy(&header);
+    let fields: Vec<&str> = header.trim_end().split(';').collect();
+    let account_length = fields.get(13)?.parse().ok()?;
+    let start = fields.get(14).copied().filter(|date| parse_compact_date(date).is_some())?;
+    let end = fields.get(15).copied().filter(|date| parse_compact_date(date).is_some())?;
+    Some(((start.parse().ok()?, end.parse().ok()?), account_length))
+}
+
+/// General ledger accounts of a chart with shorter account numbers (like 3400 of the SKR03 with a Sachkontenlänge of 5) are padded
+/// with zeros like DATEV does when the Sachkontenlänge is increased. Longer accounts are personal accounts and kept as they are.
+fn pad_account(account: &str, account_length: usize) -> String {
+    format!("{:0<width$}", account, width = account_length)
+}
+
+/// Returns the day before the given date as YYYYMMDD (the end of the fiscal year)
+fn last_day_before(year: u32, month: u32, day: u32) -> String {
+    if day > 1 {
+        return format!("{:04}{:02}{:02}", year, month, day - 1);
+    }
+
+    let (year, month) = if month == 1 { (year.saturating_sub(1), 12) } else { (year, month - 1) };
+    format!("{:04}{:02}{:02}", year, month, days_in_month(year, month))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    // Leap years by the remainders of year / 4, 100 and 400 (is_multiple_of needs a newer Rust than the Windows 7 build)
+    let leap_year = matches!((year % 4, year % 100, year % 400), (0, 1.., _) | (_, _, 0));
+    match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Belegfeld 1 only allows 36 characters out of a restricted character set
+fn document_field(number: &str) -> String {
+    number.chars().filter(|c| c.is_ascii_alphanumeric() || "$&%*+-/".contains(*c)).take(36).collect()
+}
+
+/// Belegfeld 2 is commonly used for the due date (DDMMYY) for the open item management
+fn due_date_field(date: &str) -> Option<String> {
+    parse_iso_date(date).map(|(year, month, day)| format!("{:02}{:02}{:02}", day, month, year % 100))
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
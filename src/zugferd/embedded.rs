@@ -0,0 +1,138 @@
+use super::limits;
+use super::raw::{RawDictionary, RawObject};
+use super::xref::RawFile;
+use super::Error;
+
+
+/// A file specification of an embedded file as listed in the /AF array or the /EmbeddedFiles name tree. It is resolved
+/// lazily from the raw file, so finding an attachment only parses the catalog, the name tree and the file specifications.
+#[derive(Clone, Debug)]
+pub struct FileSpecification {
+    /// Object id of the file specification (None if it is a direct dictionary)
+    pub id: Option<u64>,
+    /// The file name (/F)
+    pub f: Option<String>,
+    /// The unicode file name (/UF)
+    pub uf: Option<String>,
+    /// The relationship of the file to the document (/AFRelationship, like Alternative or Data)
+    pub relationship: Option<String>,
+    /// The /EF dictionary with the references to the embedded file streams
+    pub ef: Option<RawDictionary>,
+}
+
+impl FileSpecification {
+    /// Resolves a file specification dictionary (None for file specification strings and other objects)
+    pub fn resolve(file: &RawFile, object: &RawObject) -> Result<Option<FileSpecification>, Error> {
+        let id = object.as_reference().map(|(id, _)| id);
+        let object = file.resolve_if_ref(object)?;
+        let Some(dict) = object.as_dictionary() else { return Ok(None); };
+
+        let text = |key: &str| -> Result<Option<String>, Error> {
+            Ok(dict.get(key).map(|value| file.resolve_if_ref(value)).transpose()?.and_then(|value| value.as_text()))
+        };
+        let ef = match dict.get("EF") {
+            Some(ef) => file.resolve_if_ref(ef)?.as_dictionary().cloned(),
+            None => None,
+        };
+
+        Ok(Some(FileSpecification {
+            id,
+            f: text("F")?,
+            uf: text("UF")?,
+            relationship: dict.get("AFRelationship").and_then(RawObject::as_name).map(String::from),
+            ef,
+        }))
+    }
+
+    /// The names of the file (/F first, then /UF)
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.f.iter().chain(self.uf.iter()).map(String::as_str)
+    }
+
+    /// Object id of the embedded file stream (/EF /F or /UF if /F is missing)
+    pub fn stream_id(&self) -> Option<u64> {
+        let ef = self.ef.as_ref()?;
+        ef.get("F").and_then(RawObject::as_reference).or_else(|| ef.get("UF").and_then(RawObject::as_reference)).map(|(id, _)| id)
+    }
+
+    /// Reads the /Subtype and the /Params of the embedded file stream without decoding its data
+    /// (None if there is no embedded file stream)
+    pub fn params(&self, file: &RawFile) -> Result<Option<EmbeddedFileParams>, Error> {
+        let Some(id) = self.stream_id() else { return Ok(None); };
+        let stream = file.resolve(id)?;
+        let Some(dict) = stream.as_dictionary() else { return Ok(None); };
+
+        let params = match dict.get("Params") {
+            Some(params) => file.resolve_if_ref(params)?.as_dictionary().cloned().unwrap_or_default(),
+            None => RawDictionary::default(),
+        };
+        let value = |key: &str| -> Result<Option<RawObject>, Error> { params.get(key).map(|value| file.resolve_if_ref(value)).transpose() };
+
+        Ok(Some(EmbeddedFileParams {
+            subtype: dict.get("Subtype").and_then(RawObject::as_name).map(String::from),
+            size: value("Size")?.and_then(|size| size.as_integer()),
+            creation_date: value("CreationDate")?.and_then(|date| date.as_text()),
+            modification_date: value("ModDate")?.and_then(|date| date.as_text()),
+        }))
+    }
+}
+
+
+/// The /Subtype and the /Params of an embedded file stream. The dates are kept as written in the file,
+/// see PdfDate::parse.
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddedFileParams {
+    /// MIME type of the file (like text/xml)
+    pub subtype: Option<String>,
+    /// Size of the decoded file in bytes (/Size)
+    pub size: Option<i64>,
+    /// /CreationDate
+    pub creation_date: Option<String>,
+    /// /ModDate
+    pub modification_date: Option<String>,
+}
+
+
+/// Returns the file specifications of the /AF array of the catalog (entries which are no file specification dictionary are skipped)
+pub fn associated_files(file: &RawFile) -> Result<Vec<FileSpecification>, Error> {
+    let catalog = file.catalog()?;
+    let af = catalog.as_dictionary().and_then(|catalog| catalog.get("AF")).ok_or(Error::from(2, String::from("No /AF Array found!")))?;
+    let af = file.resolve_if_ref(af)?;
+    let af = af.as_array().ok_or(Error::from(2, String::from("/AF is not an array")))?;
+
+    let mut file_specs = Vec::with_capacity(af.len());
+    for object in af {
+        file_specs.extend(FileSpecification::resolve(file, object)?);
+    }
+    Ok(file_specs)
+}
+
+/// Returns the file specifications of the /EmbeddedFiles name tree of the catalog with their names in the tree.
+/// The tree is walked within the limits of the file (see check_embedded_files).
+pub fn embedded_files(file: &RawFile) -> Result<Vec<(String, FileSpecification)>, Error> {
+    let catalog = file.catalog()?;
+    let names = catalog.as_dictionary().and_then(|catalog| catalog.get("Names")).ok_or(Error::from(31, String::from("names dictionary not found while looking for /EmbeddedFiles")))?;
+    let names = file.resolve_if_ref(names)?;
+    let tree = names.as_dictionary().and_then(|names| names.get("EmbeddedFiles")).ok_or(Error::from(32, String::from("No /EmbeddedFiles found")))?;
+
+    walk_embedded_files(file, tree).map_err(|error| {
+        if error.is_limit_exceeded() {
+            error
+        } else {
+            Error::from(33, format!("Iteration over /EmbeddedFiles failed with: {}", error.message))
+        }
+    })
+}
+
+fn walk_embedded_files(file: &RawFile, tree: &RawObject) -> Result<Vec<(String, FileSpecification)>, Error> {
+    let mut entries = Vec::new();
+    limits::walk_name_tree(file, tree, &mut |name, value| entries.push((name.as_text().unwrap_or_default(), value.clone())))?;
+
+    let mut file_specs = Vec::with_capacity(entries.len());
+    for (name, value) in entries {
+        if let Some(file_spec) = FileSpecification::resolve(file, &value)? {
+            file_specs.push((name, file_spec));
+        }
+    }
+    Ok(file_specs)
+}
@@ -1,3 +1,4 @@
+use std::panic::{self, AssertUnwindSafe};
 use std::process::ExitCode;
 
 /// zugferd error containing the error text and the exit code
@@ -15,6 +16,22 @@ impl Error {
         }
     }
 
+    /// Whether the error was caused by exceeding one of the resource limits (see Limits)
+    pub fn is_limit_exceeded(&self) -> bool {
+        (120..=124).any(|code| self.exit_code == ExitCode::from(code))
+    }
+
+    /// Runs the given function and turns a panic into an error with exit code 130, so that a bug triggered by a single
+    /// file cannot take down the processing of all other files
+    pub fn catch_panic<T>(function: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        panic::catch_unwind(AssertUnwindSafe(function)).unwrap_or_else(|payload| {
+            let message = payload.downcast_ref::<&str>().copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            Err(Error::from(130, format!("Internal error: {}", message)))
+        })
+    }
+
     pub fn print(&self) {
         eprintln!("{}", self.message);
     }
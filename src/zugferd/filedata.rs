@@ -0,0 +1,48 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+
+/// The content of an input file. Files are memory mapped, so only the pages which are actually parsed (the trailer,
+/// the cross-reference sections and the objects on the way to the attachment) are read from disk, no matter how large
+/// the scanned pages of the document are. Files which cannot be mapped (like pipes or empty files) are read into memory.
+pub enum FileData {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl FileData {
+    pub fn open(path: &Path) -> io::Result<FileData> {
+        let mut file = File::open(path)?;
+
+        // SAFETY: The mapping is only read. If another process truncates the file while it is mapped, the process is
+        // terminated by SIGBUS, which we accept for input files just like any other tool mapping its input.
+        match unsafe { Mmap::map(&file) } {
+            Ok(mapping) if !mapping.is_empty() => Ok(FileData::Mapped(mapping)),
+            _ => {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                Ok(FileData::Read(data))
+            },
+        }
+    }
+
+    /// Whether the file is memory mapped instead of read into memory
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, FileData::Mapped(_))
+    }
+}
+
+impl Deref for FileData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileData::Mapped(mapping) => mapping,
+            FileData::Read(data) => data,
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use std::fmt::Display;
 
-use pdf::{object::FileSpec, primitive::PdfString};
+use regex::Regex;
+
+use super::embedded::FileSpecification;
+use super::Error;
 
 
 /// Struct for matching the embedded xml files by their name
@@ -29,32 +32,39 @@ impl FileMatcher {
         }
     }
 
+    /// Creates the matcher for the file name given as fx:DocumentFileName in the Factur-X/ZUGFeRD extension schema of the
+    /// XMP /Metadata stream
+    pub fn from_metadata(xmp: &str) -> Result<FileMatcher, Error> {
+        // We could also add an XML parser, but regex chould be the easier solution at the moment
+        // First we find the matching <rdf:Description> tag, which can either be an immediately closed tag with attributes or
+        // an open tag with sub elements
+        let description_regex = Regex::new(r#"(?ms)<rdf:Description [^>]*xmlns:fx="urn:factur-x:pdfa:CrossIndustryDocument:invoice[^/>]+(/>|>.*?</rdf:Description>)"#).unwrap();
+        let filename_regex = Regex::new(r#"fx:DocumentFileName((>(?<name1>.*?)</fx:DocumentFileName>)|(="(?<name2>.*?)"))"#).unwrap();
 
-    /// Returns true if the given pdf_str matches any of the names in this FileMatcher
-    pub fn matches(&self, pdf_str: &PdfString) -> bool {
-        self.names.iter().any(|name| FileMatcher::matches_str(pdf_str, name))
-    }
+        let description_match = description_regex.find(xmp).ok_or(Error::from(14, String::from("Missing <rdf:Description> element in /Metadata stream")))?;
+        let filename_match = filename_regex.captures(description_match.as_str()).ok_or(Error::from(15, String::from("Failed to locate fx:DocumentFileName in /Metadata stream")))?;
 
-    /// Returns true if either /F or /UF of the filespec matches the requested filename
-    pub fn matching_name<'a>(&self, file_spec: &'a FileSpec) -> Option<&'a PdfString> {
-        vec![&file_spec.f, &file_spec.uf].iter()
-            .map(|name_option| name_option.as_ref().and_then(|name| { if self.matches(&name) { Some(name) } else { None }}))
-            .fold(None, |a,b| a.or(b))
+        let name = filename_match.name("name1").or_else(|| filename_match.name("name2")).map_or("", |name| name.as_str());
+        if name.is_empty() {
+            return Err(Error::from(16, String::from("Empty fx:DocumentFileName in /Metadata stream")));
+        }
+        Ok(Self::from_name(name))
     }
 
-    /// Returns true if either /F or /UF end with the given suffix string 
-    pub fn matching_suffix<'a>(file_spec: &'a FileSpec, suffix: &str) -> Option<&'a PdfString> {
-        vec![&file_spec.f, &file_spec.uf].iter()
-            .map(|name_option| name_option.as_ref().and_then(|name| { if Self::matches_suffix(&name, suffix) { Some(name) } else { None }}))
-            .fold(None, |a,b| a.or(b))
+
+    /// Returns true if the given name matches any of the names in this FileMatcher
+    pub fn matches(&self, name: &str) -> bool {
+        self.names.iter().any(|matcher_name| matcher_name == name)
     }
-    
-    fn matches_str(pdf_str: &PdfString, str: &String) -> bool {
-        pdf_str.to_string().ok().map_or(false, |decoded| decoded == *str)
+
+    /// Returns the name (/F or /UF) of the filespec, which matches the requested filename
+    pub fn matching_name<'a>(&self, file_spec: &'a FileSpecification) -> Option<&'a str> {
+        file_spec.names().find(|name| self.matches(name))
     }
 
-    fn matches_suffix(pdf_str: &PdfString, suffix: &str) -> bool {
-        pdf_str.to_string().ok().map_or(false, |decoded| decoded.ends_with(suffix))
+    /// Returns the name (/F or /UF) of the filespec, which ends with the given suffix string
+    pub fn matching_suffix<'a>(file_spec: &'a FileSpecification, suffix: &str) -> Option<&'a str> {
+        file_spec.names().find(|name| name.ends_with(suffix))
     }
 }
 
@@ -0,0 +1,115 @@
+/// File types we recognize by their magic bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Xml,
+    Pdf,
+    Zip,
+    Gzip,
+    /// Zlib stream as used by /FlateDecode (only found in raw stream data)
+    Zlib,
+    Png,
+    Jpeg,
+    Jpeg2000,
+    Gif,
+    Tiff,
+    IccProfile,
+    Font,
+    /// Any other data which looks like text
+    Text,
+    Binary,
+}
+
+impl FileType {
+    /// Detects the file type from the first bytes of the data
+    pub fn detect(data: &[u8]) -> FileType {
+        const SIGNATURES: [(&[u8], FileType); 14] = [
+            (b"%PDF-", FileType::Pdf),
+            (b"PK\x03\x04", FileType::Zip),
+            (b"PK\x05\x06", FileType::Zip),
+            (b"\x1f\x8b", FileType::Gzip),
+            (b"\x89PNG\r\n\x1a\n", FileType::Png),
+            (b"\xff\xd8\xff", FileType::Jpeg),
+            (b"\x00\x00\x00\x0cjP  ", FileType::Jpeg2000),
+            (b"\xff\x4f\xff\x51", FileType::Jpeg2000),
+            (b"GIF87a", FileType::Gif),
+            (b"GIF89a", FileType::Gif),
+            (b"II*\x00", FileType::Tiff),
+            (b"MM\x00*", FileType::Tiff),
+            (b"\x00\x01\x00\x00", FileType::Font),
+            (b"OTTO", FileType::Font),
+        ];
+
+        if let Some((_, file_type)) = SIGNATURES.iter().find(|(signature, _)| data.starts_with(signature)) {
+            *file_type
+        } else if data.get(36..40) == Some(b"acsp") {
+            FileType::IccProfile
+        } else if is_xml(data) {
+            FileType::Xml
+        } else if data.len() >= 2 && data[0] & 0x0f == 8 && data[0] >> 4 <= 7 && data[1] & 0x20 == 0 && u16::from_be_bytes([data[0], data[1]]).rem_euclid(31) == 0 {
+            // zlib header: deflate with a valid window size, no preset dictionary and a valid header checksum
+            FileType::Zlib
+        } else if is_binary(data) {
+            FileType::Binary
+        } else {
+            FileType::Text
+        }
+    }
+
+    /// Whether the content should be shown as text
+    pub fn is_text(&self) -> bool {
+        matches!(self, FileType::Xml | FileType::Text)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FileType::Xml => "XML document",
+            FileType::Pdf => "PDF document",
+            FileType::Zip => "ZIP archive",
+            FileType::Gzip => "gzip compressed data",
+            FileType::Zlib => "zlib compressed data",
+            FileType::Png => "PNG image",
+            FileType::Jpeg => "JPEG image",
+            FileType::Jpeg2000 => "JPEG 2000 image",
+            FileType::Gif => "GIF image",
+            FileType::Tiff => "TIFF image",
+            FileType::IccProfile => "ICC color profile",
+            FileType::Font => "TrueType/OpenType font",
+            FileType::Text => "text",
+            FileType::Binary => "binary data",
+        }
+    }
+}
+
+impl std::fmt::Display for FileType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+
+/// XML starts with a root element or the XML declaration after an optional BOM and whitespace (UTF-16 is detected by its BOM)
+fn is_xml(data: &[u8]) -> bool {
+    if data.starts_with(b"\xff\xfe<\x00") || data.starts_with(b"\xfe\xff\x00<") {
+        return true;
+    }
+
+    let data = data.strip_prefix(b"\xef\xbb\xbf").unwrap_or(data);
+    let start = data.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(data.len());
+    match &data[start..] {
+        [b'<', b'?', b'x', b'm', b'l', ..] => true,
+        [b'<', next, ..] => next.is_ascii_alphabetic() || *next == b'!',
+        _ => false,
+    }
+}
+
+/// Checks the first 8 KiB of the data for control characters and invalid UTF-8
+pub fn is_binary(data: &[u8]) -> bool {
+    let sample = &data[..data.len().min(8192)];
+    let text = match std::str::from_utf8(sample) {
+        Ok(text) => text,
+        // The sample may end in the middle of a character
+        Err(error) if error.error_len().is_none() && data.len() > sample.len() => std::str::from_utf8(&sample[..error.valid_up_to()]).unwrap_or_default(),
+        Err(_) => return true,
+    };
+    text.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c'))
+}
@@ -0,0 +1,196 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use super::limits::Limits;
+use super::raw::{RawDictionary, RawObject};
+use super::Error;
+
+
+/// Returns the /Filter chain of the stream dictionary together with the matching /DecodeParms
+pub fn filter_chain(dict: &RawDictionary) -> Vec<(String, Option<RawDictionary>)> {
+    let names: Vec<String> = match dict.get("Filter") {
+        Some(RawObject::Name(name)) => vec![name.clone()],
+        Some(RawObject::Array(names)) => names.iter().filter_map(|name| name.as_name().map(String::from)).collect(),
+        _ => Vec::new(),
+    };
+
+    let params: Vec<Option<RawDictionary>> = match dict.get("DecodeParms").or_else(|| dict.get("DP")) {
+        Some(RawObject::Dictionary(params)) => vec![Some(params.clone())],
+        Some(RawObject::Array(params)) => params.iter().map(|param| param.as_dictionary().cloned()).collect(),
+        _ => Vec::new(),
+    };
+
+    names.into_iter().enumerate().map(|(index, name)| (name, params.get(index).cloned().flatten())).collect()
+}
+
+/// Decodes the stream data by applying all filters of the stream dictionary
+pub fn decode(data: &[u8], dict: &RawDictionary, limits: &Limits) -> Result<Vec<u8>, Error> {
+    decode_chain(data, &filter_chain(dict), limits)
+}
+
+/// Decodes the data by applying the given filter chain. The compression ratio is checked against the encoded data of the stream.
+pub fn decode_chain(data: &[u8], chain: &[(String, Option<RawDictionary>)], limits: &Limits) -> Result<Vec<u8>, Error> {
+    let decoded = chain.iter().try_fold(data.to_vec(), |data, (name, params)| decode_filter(name, params.as_ref(), &data, limits))?;
+    limits.check_decoded_size(data.len(), decoded.len())?;
+    Ok(decoded)
+}
+
+/// One step of the filter chain with its output (or the error which stopped the decoding)
+pub struct FilterStage {
+    pub filter: String,
+    pub params: Option<RawDictionary>,
+    pub output: Result<Vec<u8>, Error>,
+}
+
+/// Applies the filter chain step by step and returns the output of every stage. Decoding stops at the first failing filter.
+/// The stages are decoded with the default limits.
+pub fn decode_stages(data: &[u8], chain: &[(String, Option<RawDictionary>)]) -> Vec<FilterStage> {
+    let limits = Limits::default();
+    let mut stages: Vec<FilterStage> = Vec::with_capacity(chain.len());
+    for (filter, params) in chain {
+        let input = match stages.last().map(|stage| &stage.output) {
+            None => data,
+            Some(Ok(output)) => output.as_slice(),
+            Some(Err(_)) => break,
+        };
+        let output = decode_filter(filter, params.as_ref(), input, &limits);
+        stages.push(FilterStage { filter: filter.clone(), params: params.clone(), output });
+    }
+    stages
+}
+
+/// Applies a single filter. Image filters (DCT, JPX, CCITT, JBIG2) are passed through as they
+/// represent the final data format.
+pub fn decode_filter(name: &str, params: Option<&RawDictionary>, data: &[u8], limits: &Limits) -> Result<Vec<u8>, Error> {
+    match name {
+        "FlateDecode" | "Fl" => {
+            let mut decoded = Vec::new();
+            // Stop right after the limit instead of inflating a decompression bomb completely
+            let limit = limits.max_decoded_size(data.len());
+            // Many writers produce truncated or slightly broken zlib streams, so we keep what we could decode
+            if let Err(err) = ZlibDecoder::new(data).take(limit as u64 + 1).read_to_end(&mut decoded) {
+                if decoded.is_empty() {
+                    return Err(Error::from(91, format!("FlateDecode failed: {}", err)));
+                }
+            }
+            limits.check_decoded_size(data.len(), decoded.len())?;
+            apply_predictor(decoded, params, limits)
+        },
+        "ASCIIHexDecode" | "AHx" => Ok(decode_ascii_hex(data)),
+        "ASCII85Decode" | "A85" => decode_ascii85(data),
+        "DCTDecode" | "DCT" | "JPXDecode" | "CCITTFaxDecode" | "CCF" | "JBIG2Decode" => Ok(data.to_vec()),
+        // Encrypted streams are already decrypted by the RawFile
+        "Crypt" => Ok(data.to_vec()),
+        other => Err(Error::from(92, format!("Unsupported stream filter /{}", other))),
+    }
+}
+
+
+/// Reverts the PNG (10-15) or TIFF (2) predictor of Flate encoded data
+fn apply_predictor(data: Vec<u8>, params: Option<&RawDictionary>, limits: &Limits) -> Result<Vec<u8>, Error> {
+    let param = |key: &str, default: i64| params.and_then(|params| params.get(key)).and_then(RawObject::as_integer).unwrap_or(default);
+    let predictor = param("Predictor", 1);
+    if predictor < 2 {
+        return Ok(data);
+    }
+
+    let colors = param("Colors", 1).max(1) as usize;
+    let bits = param("BitsPerComponent", 8).max(1) as usize;
+    let columns = param("Columns", 1).max(1) as usize;
+    let row_bits = colors.saturating_mul(bits).saturating_mul(columns);
+    if row_bits / 8 > limits.max_stream_size {
+        return Err(Error::from(120, format!("Predictor rows of {} bits exceed the maximum stream size of {} bytes", row_bits, limits.max_stream_size)));
+    }
+    let bytes_per_pixel = (colors * bits).div_ceil(8);
+    let row_length = row_bits.div_ceil(8);
+
+    if predictor == 2 {
+        // TIFF predictor (only 8 bit components)
+        let mut data = data;
+        for row in data.chunks_mut(row_length) {
+            for index in bytes_per_pixel..row.len() {
+                row[index] = row[index].wrapping_add(row[index - bytes_per_pixel]);
+            }
+        }
+        return Ok(data);
+    }
+
+    // PNG predictors: every row starts with its filter type byte
+    let mut result = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; row_length];
+    for chunk in data.chunks(row_length + 1) {
+        let (filter_type, row) = (chunk[0], &chunk[1..]);
+        let mut current = row.to_vec();
+        current.resize(row_length, 0);
+
+        for index in 0..row_length {
+            let left = if index >= bytes_per_pixel { current[index - bytes_per_pixel] } else { 0 };
+            let up = previous[index];
+            let up_left = if index >= bytes_per_pixel { previous[index - bytes_per_pixel] } else { 0 };
+            current[index] = current[index].wrapping_add(match filter_type {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                other => return Err(Error::from(91, format!("Invalid PNG predictor row type {}", other))),
+            });
+        }
+
+        result.extend_from_slice(&current[..row.len().min(row_length)]);
+        previous = current;
+    }
+
+    Ok(result)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (distance_left, distance_up, distance_up_left) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(), (estimate - up_left as i16).abs());
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn decode_ascii_hex(data: &[u8]) -> Vec<u8> {
+    let mut digits: Vec<u8> = data.iter().take_while(|byte| **byte != b'>').filter(|byte| byte.is_ascii_hexdigit()).copied().collect();
+    if digits.len() % 2 == 1 {
+        digits.push(b'0');
+    }
+    digits.chunks(2).filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()).collect()
+}
+
+fn decode_ascii85(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut result = Vec::new();
+    let mut group = Vec::with_capacity(5);
+
+    for byte in data.iter().copied().filter(|byte| !byte.is_ascii_whitespace()) {
+        match byte {
+            b'~' => break,
+            b'z' if group.is_empty() => result.extend_from_slice(&[0, 0, 0, 0]),
+            b'!'..=b'u' => {
+                group.push(byte - b'!');
+                if group.len() == 5 {
+                    let value = group.iter().fold(0u64, |value, digit| value * 85 + *digit as u64);
+                    result.extend_from_slice(&(value as u32).to_be_bytes());
+                    group.clear();
+                }
+            },
+            other => return Err(Error::from(91, format!("Invalid character 0x{:02X} in ASCII85 data", other))),
+        }
+    }
+
+    if group.len() > 1 {
+        let count = group.len() - 1;
+        group.resize(5, 84);
+        let value = group.iter().fold(0u64, |value, digit| value * 85 + *digit as u64);
+        result.extend_from_slice(&(value as u32).to_be_bytes()[..count]);
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use super::Error;
+
+
+/// Unicode characters of the WinAnsiEncoding code points 0x80 - 0x9F (the rest matches Latin-1)
+const WIN_ANSI_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// First and last character code for which we write glyph widths
+pub const FIRST_CHAR: u8 = 32;
+pub const LAST_CHAR: u8 = 255;
+
+
+/// A TrueType font, which gets embedded completely (no subsetting) as a simple font with WinAnsiEncoding.
+/// This limits the renderable characters to Western European text, which is sufficient for German invoices.
+pub struct Font {
+    /// PostScript name of the font, used as /BaseFont
+    pub name: String,
+    /// The raw font file
+    pub data: Vec<u8>,
+    /// Metrics scaled to 1000 units per em as required by the PDF font dictionaries
+    pub ascent: i32,
+    pub descent: i32,
+    pub cap_height: i32,
+    pub bbox: [i32; 4],
+    pub italic_angle: f32,
+    /// Glyph widths for the character codes FIRST_CHAR..=LAST_CHAR
+    pub widths: Vec<i32>,
+}
+
+
+impl Font {
+    pub fn load(path: &Path) -> Result<Font, Error> {
+        let data = std::fs::read(path).map_err(|err| Error::from(140, format!("Failed to read font {}: {}", path.display(), err)))?;
+        let fallback_name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or(String::from("EmbeddedFont"));
+        Self::from_bytes(data, &fallback_name)
+    }
+
+    pub fn from_bytes(data: Vec<u8>, fallback_name: &str) -> Result<Font, Error> {
+        let face = ttf_parser::Face::parse(&data, 0).map_err(|err| Error::from(141, format!("Failed to parse TrueType font: {}", err)))?;
+        let units_per_em = face.units_per_em() as i32;
+        let scale = |value: i32| value * 1000 / units_per_em;
+
+        let name = face.names().into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .unwrap_or(String::from(fallback_name))
+            .chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect::<String>();
+
+        let missing_width = face.glyph_hor_advance(ttf_parser::GlyphId(0)).map_or(0, |width| scale(width as i32));
+        let widths = (FIRST_CHAR..=LAST_CHAR)
+            .map(|code| face.glyph_index(decode_win_ansi(code))
+                .and_then(|glyph| face.glyph_hor_advance(glyph))
+                .map_or(missing_width, |width| scale(width as i32)))
+            .collect();
+
+        let bbox = face.global_bounding_box();
+        let font = Font {
+            name,
+            ascent: scale(face.ascender() as i32),
+            descent: scale(face.descender() as i32),
+            cap_height: scale(face.capital_height().unwrap_or(face.ascender()) as i32),
+            bbox: [scale(bbox.x_min as i32), scale(bbox.y_min as i32), scale(bbox.x_max as i32), scale(bbox.y_max as i32)],
+            italic_angle: face.italic_angle().unwrap_or(0.0),
+            widths,
+            data: Vec::new(),
+        };
+
+        Ok(Font { data, ..font })
+    }
+
+    /// Returns the width of the given text in points when rendered with the given font size
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let units: i32 = encode_win_ansi(text).iter()
+            .map(|code| if *code >= FIRST_CHAR { self.widths[(*code - FIRST_CHAR) as usize] } else { 0 })
+            .sum();
+        units as f32 * size / 1000.0
+    }
+
+    /// Shortens the text with an ellipsis until it fits into the given width
+    pub fn fit_text(&self, text: &str, size: f32, max_width: f32) -> String {
+        if self.text_width(text, size) <= max_width {
+            return String::from(text);
+        }
+
+        let mut shortened: String = text.chars().collect();
+        while !shortened.is_empty() && self.text_width(&format!("{}…", shortened), size) > max_width {
+            shortened.pop();
+        }
+        format!("{}…", shortened.trim_end())
+    }
+}
+
+
+/// Converts a WinAnsiEncoding character code into its unicode character
+pub fn decode_win_ansi(code: u8) -> char {
+    match code {
+        0x80..=0x9F => WIN_ANSI_HIGH[(code - 0x80) as usize],
+        _ => code as char,
+    }
+}
+
+/// Converts the text into WinAnsiEncoding, replacing all unsupported characters with '?'
+pub fn encode_win_ansi(text: &str) -> Vec<u8> {
+    text.chars().map(|c| match c as u32 {
+        0x20..=0x7E | 0xA0..=0xFF => c as u8,
+        _ => WIN_ANSI_HIGH.iter().position(|high| *high == c && (*high as u32) > 0x9F).map_or(b'?', |index| 0x80 + index as u8),
+    }).collect()
+}
@@ -0,0 +1,450 @@
+use std::fmt::Display;
+
+use roxmltree::{Document, Node};
+use serde::{Serialize, Serializer};
+
+use super::Error;
+
+
+/// The XML syntax an invoice was written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Syntax {
+    /// UN/CEFACT Cross Industry Invoice (ZUGFeRD, Factur-X, XRechnung CII)
+    Cii,
+    /// OASIS UBL Invoice or CreditNote (XRechnung UBL)
+    Ubl,
+}
+
+
+/// Largest number of fractional digits of a decimal
+const MAX_SCALE: u32 = 18;
+
+/// Largest absolute value of a decimal scaled to MAX_SCALE digits (the sum of two still fits into an i128)
+const MAX_SCALED_VALUE: i128 = 10i128.pow(37);
+
+/// Exact decimal number as it appears in the invoice XML (amounts, quantities and rates).
+/// We don't use floats here as we only ever copy, add and reformat these values. There is no `+` operator, as a sum
+/// can overflow: use checked_add.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Decimal {
+    value: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Parses a plain decimal string like "-1234.50" (no exponent, no thousands separators)
+    pub fn parse(text: &str) -> Option<Decimal> {
+        let text = text.trim();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        if !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) || fraction.len() > MAX_SCALE as usize {
+            return None;
+        }
+
+        // Values too large to be scaled to MAX_SCALE digits and added count as unparsable like malformed ones
+        let value = format!("{}{}", integer, fraction).parse::<i128>().ok()?;
+        let decimal = Decimal { value: if negative { -value } else { value }, scale: fraction.len() as u32 };
+        decimal.with_min_scale(MAX_SCALE).filter(|scaled| scaled.value.abs() <= MAX_SCALED_VALUE)?;
+        Some(decimal)
+    }
+
+    pub fn zero() -> Decimal {
+        Decimal::default()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.value < 0
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal { value: self.value.abs(), scale: self.scale }
+    }
+
+    /// Returns this value with at least `scale` fractional digits (never drops digits) or None if the value would overflow.
+    /// Parsed values can always be scaled up to 18 digits.
+    pub fn with_min_scale(&self, scale: u32) -> Option<Decimal> {
+        if scale <= self.scale {
+            Some(*self)
+        } else {
+            let factor = 10i128.checked_pow(scale - self.scale)?;
+            Some(Decimal { value: self.value.checked_mul(factor)?, scale })
+        }
+    }
+
+    /// Returns this value with exactly `scale` fractional digits or None if this would drop non-zero digits (or overflow)
+    pub fn with_scale(&self, scale: u32) -> Option<Decimal> {
+        if scale >= self.scale {
+            return self.with_min_scale(scale);
+        }
+
+        let factor = 10i128.checked_pow(self.scale - scale)?;
+        (self.value % factor == 0).then_some(Decimal { value: self.value / factor, scale })
+    }
+
+    /// Adds both values or returns None if the sum would overflow
+    pub fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let value = self.with_min_scale(scale)?.value.checked_add(other.with_min_scale(scale)?.value)?;
+        Some(Decimal { value, scale })
+    }
+
+    /// Formats the value using the given decimal separator (e.g. ',' for German spreadsheets)
+    pub fn format(&self, separator: char) -> String {
+        let digits = self.value.abs().to_string();
+        let sign = if self.value < 0 { "-" } else { "" };
+        if self.scale == 0 {
+            return format!("{}{}", sign, digits);
+        }
+
+        let digits = format!("{:0>width$}", digits, width = self.scale as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        format!("{}{}{}{}", sign, integer, separator, fraction)
+    }
+}
+
+/// Decimals compare by value, so 19 equals 19.00. A value which overflows when scaled to the scale of the other one
+/// is larger than any value of that scale.
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        let scale = self.scale.max(other.scale);
+        match (self.with_min_scale(scale), other.with_min_scale(scale)) {
+            (Some(this), Some(other)) => this.value == other.value,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Decimal {}
+
+/// Decimals are serialized as JSON strings to keep the exact value and scale
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format('.'))
+    }
+}
+
+
+/// Seller or buyer of an invoice
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Party {
+    pub name: String,
+    pub vat_id: Option<String>,
+    pub street: Option<String>,
+    pub postcode: Option<String>,
+    pub city: Option<String>,
+    pub country_code: Option<String>,
+}
+
+/// A single invoice line (BG-25)
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct InvoiceLine {
+    pub id: String,
+    pub seller_item_id: Option<String>,
+    pub buyer_item_id: Option<String>,
+    pub global_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub quantity: Option<Decimal>,
+    pub unit_code: Option<String>,
+    pub net_price: Option<Decimal>,
+    pub line_total: Option<Decimal>,
+    pub vat_category: Option<String>,
+    pub vat_rate: Option<Decimal>,
+}
+
+/// One entry of the VAT breakdown (BG-23)
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VatBreakdown {
+    pub category: String,
+    pub rate: Option<Decimal>,
+    pub basis: Option<Decimal>,
+    pub amount: Option<Decimal>,
+    pub exemption_reason: Option<String>,
+}
+
+/// Document totals (BG-22)
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Totals {
+    pub line_total: Option<Decimal>,
+    pub tax_basis: Option<Decimal>,
+    pub tax_total: Option<Decimal>,
+    pub grand_total: Option<Decimal>,
+    pub due_payable: Option<Decimal>,
+}
+
+/// The subset of the EN 16931 semantic model we need to render and export invoices.
+/// Dates are normalized to ISO 8601 (YYYY-MM-DD) regardless of the source syntax.
+#[derive(Clone, Debug, Serialize)]
+pub struct Invoice {
+    pub syntax: Syntax,
+    /// Specification identifier (BT-24), e.g. "urn:cen.eu:en16931:2017#compliant#urn:xeinkauf.de:kosit:xrechnung_3.0"
+    pub guideline: Option<String>,
+    pub number: String,
+    pub type_code: Option<String>,
+    pub issue_date: Option<String>,
+    pub due_date: Option<String>,
+    pub currency: Option<String>,
+    pub buyer_reference: Option<String>,
+    pub seller: Party,
+    pub buyer: Party,
+    pub lines: Vec<InvoiceLine>,
+    pub vat_breakdown: Vec<VatBreakdown>,
+    pub totals: Totals,
+}
+
+
+impl Invoice {
+    /// Parses a CII or UBL invoice XML into the invoice model
+    pub fn parse(xml: &str) -> Result<Invoice, Error> {
+        let document = Document::parse(xml).map_err(|xml_err| Error::from(60, format!("Failed to parse invoice XML: {}", xml_err)))?;
+        let root = document.root_element();
+
+        let invoice = match root.tag_name().name() {
+            "CrossIndustryInvoice" => Self::parse_cii(root),
+            "Invoice" | "CreditNote" => Self::parse_ubl(root),
+            other => return Err(Error::from(61, format!("Unsupported invoice root element <{}>", other)))
+        };
+
+        if invoice.number.is_empty() {
+            return Err(Error::from(62, String::from("Invoice XML contains no invoice number")));
+        }
+
+        Ok(invoice)
+    }
+
+    /// Returns the name the XML attachment should have inside a hybrid PDF
+    pub fn attachment_name(&self) -> &'static str {
+        match self.syntax {
+            Syntax::Cii => "factur-x.xml",
+            Syntax::Ubl => "xrechnung.xml",
+        }
+    }
+
+    /// Returns the Factur-X/ZUGFeRD conformance level matching the guideline of this invoice
+    pub fn conformance_level(&self) -> &'static str {
+        let guideline = self.guideline.as_deref().unwrap_or("").to_lowercase();
+        if guideline.contains("xrechnung") {
+            "XRECHNUNG"
+        } else if guideline.contains("extended") {
+            "EXTENDED"
+        } else if guideline.contains("basicwl") {
+            "BASIC WL"
+        } else if guideline.contains("basic") {
+            "BASIC"
+        } else if guideline.contains("minimum") {
+            "MINIMUM"
+        } else {
+            "EN 16931"
+        }
+    }
+
+
+    fn parse_cii(root: Node) -> Invoice {
+        let transaction = child(root, "SupplyChainTradeTransaction");
+        let agreement = transaction.and_then(|t| child(t, "ApplicableHeaderTradeAgreement"));
+        let settlement = transaction.and_then(|t| child(t, "ApplicableHeaderTradeSettlement"));
+        let summation = settlement.and_then(|s| child(s, "SpecifiedTradeSettlementHeaderMonetarySummation"));
+
+        let lines = transaction.map(|t| children(t, "IncludedSupplyChainTradeLineItem").map(Self::parse_cii_line).collect()).unwrap_or_default();
+
+        let vat_breakdown = settlement.map(|s| children(s, "ApplicableTradeTax").map(|tax| VatBreakdown {
+            category: text(tax, &["CategoryCode"]).unwrap_or_default(),
+            rate: decimal(tax, &["RateApplicablePercent"]),
+            basis: decimal(tax, &["BasisAmount"]),
+            amount: decimal(tax, &["CalculatedAmount"]),
+            exemption_reason: text(tax, &["ExemptionReason"]),
+        }).collect()).unwrap_or_default();
+
+        Invoice {
+            syntax: Syntax::Cii,
+            guideline: text(root, &["ExchangedDocumentContext", "GuidelineSpecifiedDocumentContextParameter", "ID"]),
+            number: text(root, &["ExchangedDocument", "ID"]).unwrap_or_default(),
+            type_code: text(root, &["ExchangedDocument", "TypeCode"]),
+            issue_date: text(root, &["ExchangedDocument", "IssueDateTime", "DateTimeString"]).map(|date| cii_date(&date)),
+            due_date: settlement.and_then(|s| text(s, &["SpecifiedTradePaymentTerms", "DueDateDateTime", "DateTimeString"])).map(|date| cii_date(&date)),
+            currency: settlement.and_then(|s| text(s, &["InvoiceCurrencyCode"])),
+            buyer_reference: agreement.and_then(|a| text(a, &["BuyerReference"])),
+            seller: agreement.and_then(|a| child(a, "SellerTradeParty")).map(Self::parse_cii_party).unwrap_or_default(),
+            buyer: agreement.and_then(|a| child(a, "BuyerTradeParty")).map(Self::parse_cii_party).unwrap_or_default(),
+            lines,
+            vat_breakdown,
+            totals: summation.map(|s| Totals {
+                line_total: decimal(s, &["LineTotalAmount"]),
+                tax_basis: decimal(s, &["TaxBasisTotalAmount"]),
+                tax_total: decimal(s, &["TaxTotalAmount"]),
+                grand_total: decimal(s, &["GrandTotalAmount"]),
+                due_payable: decimal(s, &["DuePayableAmount"]),
+            }).unwrap_or_default(),
+        }
+    }
+
+    fn parse_cii_party(party: Node) -> Party {
+        let vat_id = children(party, "SpecifiedTaxRegistration")
+            .filter_map(|registration| child(registration, "ID"))
+            .find(|id| id.attribute("schemeID") == Some("VA"))
+            .and_then(|id| node_text(id));
+
+        Party {
+            name: text(party, &["Name"]).unwrap_or_default(),
+            vat_id,
+            street: text(party, &["PostalTradeAddress", "LineOne"]),
+            postcode: text(party, &["PostalTradeAddress", "PostcodeCode"]),
+            city: text(party, &["PostalTradeAddress", "CityName"]),
+            country_code: text(party, &["PostalTradeAddress", "CountryID"]),
+        }
+    }
+
+    fn parse_cii_line(line: Node) -> InvoiceLine {
+        let product = child(line, "SpecifiedTradeProduct");
+        let settlement = child(line, "SpecifiedLineTradeSettlement");
+        let quantity = path(line, &["SpecifiedLineTradeDelivery", "BilledQuantity"]);
+
+        InvoiceLine {
+            id: text(line, &["AssociatedDocumentLineDocument", "LineID"]).unwrap_or_default(),
+            seller_item_id: product.and_then(|p| text(p, &["SellerAssignedID"])),
+            buyer_item_id: product.and_then(|p| text(p, &["BuyerAssignedID"])),
+            global_id: product.and_then(|p| text(p, &["GlobalID"])),
+            name: product.and_then(|p| text(p, &["Name"])).unwrap_or_default(),
+            description: product.and_then(|p| text(p, &["Description"])),
+            quantity: quantity.and_then(node_text).and_then(|q| Decimal::parse(&q)),
+            unit_code: quantity.and_then(|q| q.attribute("unitCode")).map(String::from),
+            net_price: decimal(line, &["SpecifiedLineTradeAgreement", "NetPriceProductTradePrice", "ChargeAmount"]),
+            line_total: settlement.and_then(|s| decimal(s, &["SpecifiedTradeSettlementLineMonetarySummation", "LineTotalAmount"])),
+            vat_category: settlement.and_then(|s| text(s, &["ApplicableTradeTax", "CategoryCode"])),
+            vat_rate: settlement.and_then(|s| decimal(s, &["ApplicableTradeTax", "RateApplicablePercent"])),
+        }
+    }
+
+
+    fn parse_ubl(root: Node) -> Invoice {
+        let is_credit_note = root.tag_name().name() == "CreditNote";
+        let line_name = if is_credit_note { "CreditNoteLine" } else { "InvoiceLine" };
+
+        let lines = children(root, line_name).map(Self::parse_ubl_line).collect();
+
+        let vat_breakdown = children(root, "TaxTotal")
+            .flat_map(|total| children(total, "TaxSubtotal"))
+            .map(|subtotal| VatBreakdown {
+                category: text(subtotal, &["TaxCategory", "ID"]).unwrap_or_default(),
+                rate: decimal(subtotal, &["TaxCategory", "Percent"]),
+                basis: decimal(subtotal, &["TaxableAmount"]),
+                amount: decimal(subtotal, &["TaxAmount"]),
+                exemption_reason: text(subtotal, &["TaxCategory", "TaxExemptionReason"]),
+            }).collect();
+
+        // There may be a second TaxTotal in accounting currency, which has no subtotals
+        let tax_total = children(root, "TaxTotal")
+            .find(|total| child(*total, "TaxSubtotal").is_some())
+            .or_else(|| child(root, "TaxTotal"))
+            .and_then(|total| decimal(total, &["TaxAmount"]));
+
+        Invoice {
+            syntax: Syntax::Ubl,
+            guideline: text(root, &["CustomizationID"]),
+            number: text(root, &["ID"]).unwrap_or_default(),
+            type_code: text(root, &[if is_credit_note { "CreditNoteTypeCode" } else { "InvoiceTypeCode" }]),
+            issue_date: text(root, &["IssueDate"]),
+            due_date: text(root, &["DueDate"]).or_else(|| text(root, &["PaymentMeans", "PaymentDueDate"])),
+            currency: text(root, &["DocumentCurrencyCode"]),
+            buyer_reference: text(root, &["BuyerReference"]),
+            seller: path(root, &["AccountingSupplierParty", "Party"]).map(Self::parse_ubl_party).unwrap_or_default(),
+            buyer: path(root, &["AccountingCustomerParty", "Party"]).map(Self::parse_ubl_party).unwrap_or_default(),
+            lines,
+            vat_breakdown,
+            totals: Totals {
+                line_total: decimal(root, &["LegalMonetaryTotal", "LineExtensionAmount"]),
+                tax_basis: decimal(root, &["LegalMonetaryTotal", "TaxExclusiveAmount"]),
+                tax_total,
+                grand_total: decimal(root, &["LegalMonetaryTotal", "TaxInclusiveAmount"]),
+                due_payable: decimal(root, &["LegalMonetaryTotal", "PayableAmount"]),
+            },
+        }
+    }
+
+    fn parse_ubl_party(party: Node) -> Party {
+        let vat_id = children(party, "PartyTaxScheme")
+            .find(|scheme| text(*scheme, &["TaxScheme", "ID"]).as_deref() == Some("VAT"))
+            .and_then(|scheme| text(scheme, &["CompanyID"]));
+
+        Party {
+            name: text(party, &["PartyLegalEntity", "RegistrationName"]).or_else(|| text(party, &["PartyName", "Name"])).unwrap_or_default(),
+            vat_id,
+            street: text(party, &["PostalAddress", "StreetName"]),
+            postcode: text(party, &["PostalAddress", "PostalZone"]),
+            city: text(party, &["PostalAddress", "CityName"]),
+            country_code: text(party, &["PostalAddress", "Country", "IdentificationCode"]),
+        }
+    }
+
+    fn parse_ubl_line(line: Node) -> InvoiceLine {
+        let quantity = child(line, "InvoicedQuantity").or_else(|| child(line, "CreditedQuantity"));
+        let item = child(line, "Item");
+
+        InvoiceLine {
+            id: text(line, &["ID"]).unwrap_or_default(),
+            seller_item_id: item.and_then(|i| text(i, &["SellersItemIdentification", "ID"])),
+            buyer_item_id: item.and_then(|i| text(i, &["BuyersItemIdentification", "ID"])),
+            global_id: item.and_then(|i| text(i, &["StandardItemIdentification", "ID"])),
+            name: item.and_then(|i| text(i, &["Name"])).unwrap_or_default(),
+            description: item.and_then(|i| text(i, &["Description"])),
+            quantity: quantity.and_then(node_text).and_then(|q| Decimal::parse(&q)),
+            unit_code: quantity.and_then(|q| q.attribute("unitCode")).map(String::from),
+            net_price: decimal(line, &["Price", "PriceAmount"]),
+            line_total: decimal(line, &["LineExtensionAmount"]),
+            vat_category: item.and_then(|i| text(i, &["ClassifiedTaxCategory", "ID"])),
+            vat_rate: item.and_then(|i| decimal(i, &["ClassifiedTaxCategory", "Percent"])),
+        }
+    }
+}
+
+
+// Namespace agnostic navigation helpers. CII and UBL documents use varying prefixes and namespace versions,
+// so we only compare the local element names.
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn path<'a, 'input>(node: Node<'a, 'input>, names: &[&str]) -> Option<Node<'a, 'input>> {
+    names.iter().try_fold(node, |current, name| child(current, name))
+}
+
+fn node_text(node: Node) -> Option<String> {
+    node.text().map(str::trim).filter(|t| !t.is_empty()).map(String::from)
+}
+
+fn text(node: Node, names: &[&str]) -> Option<String> {
+    path(node, names).and_then(node_text)
+}
+
+fn decimal(node: Node, names: &[&str]) -> Option<Decimal> {
+    text(node, names).and_then(|t| Decimal::parse(&t))
+}
+
+/// Converts a CII date in format 102 (YYYYMMDD) into YYYY-MM-DD, other formats are passed through
+fn cii_date(date: &str) -> String {
+    if date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8])
+    } else {
+        String::from(date)
+    }
+}
@@ -0,0 +1,27 @@
+use serde::Serialize;
+
+use super::{Error, Invoice};
+
+
+/// Version of the JSON representation. Must be incremented together with the `$id` of the schema on incompatible changes.
+pub const INVOICE_JSON_VERSION: u32 = 1;
+
+/// JSON Schema (draft 2020-12) describing the output of `Invoice::to_json()`
+pub const INVOICE_JSON_SCHEMA: &str = include_str!("../../schema/invoice.schema.json");
+
+
+#[derive(Serialize)]
+struct InvoiceJson<'a> {
+    format_version: u32,
+    #[serde(flatten)]
+    invoice: &'a Invoice,
+}
+
+
+impl Invoice {
+    /// Serializes the invoice model into its documented JSON representation (see INVOICE_JSON_SCHEMA)
+    pub fn to_json(&self) -> Result<String, Error> {
+        let json = InvoiceJson { format_version: INVOICE_JSON_VERSION, invoice: self };
+        serde_json::to_string_pretty(&json).map_err(|json_err| Error::from(63, format!("Failed to serialize invoice {} as JSON: {}", self.number, json_err)))
+    }
+}
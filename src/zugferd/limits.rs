@@ -0,0 +1,123 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use super::raw::RawObject;
+use super::xref::RawFile;
+use super::Error;
+
+
+/// Decoded streams up to this size are never rejected because of their compression ratio
+const RATIO_THRESHOLD: usize = 1024 * 1024;
+
+
+/// Resource limits for reading untrusted files. Exceeding a limit fails with a dedicated error code:
+/// 120 (stream size), 121 (compression ratio), 122 (nesting depth), 123 (name tree size) and 124 (processing time).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of a decoded stream in bytes
+    pub max_stream_size: usize,
+    /// Maximum ratio of decoded to encoded size of a stream (only checked for streams larger than 1 MiB)
+    pub max_compression_ratio: usize,
+    /// Maximum nesting depth of arrays and dictionaries (and of name tree nodes)
+    pub max_nesting_depth: usize,
+    /// Maximum number of entries and nodes of the /EmbeddedFiles name tree
+    pub max_name_tree_entries: usize,
+    /// Maximum time to spend on a file (None = unlimited)
+    pub max_time: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_stream_size: 256 * 1024 * 1024,
+            max_compression_ratio: 1000,
+            max_nesting_depth: 100,
+            max_name_tree_entries: 100_000,
+            max_time: None,
+        }
+    }
+}
+
+impl Limits {
+    /// Maximum decoded size of a stream with the given encoded size
+    pub fn max_decoded_size(&self, encoded: usize) -> usize {
+        encoded.saturating_mul(self.max_compression_ratio).max(RATIO_THRESHOLD).min(self.max_stream_size)
+    }
+
+    /// Fails if the decoded size exceeds the maximum stream size
+    pub fn check_stream_size(&self, decoded: usize) -> Result<(), Error> {
+        if decoded > self.max_stream_size {
+            return Err(Error::from(120, format!("Decoded stream exceeds the maximum stream size of {} bytes", self.max_stream_size)));
+        }
+        Ok(())
+    }
+
+    /// Fails if the decoded size exceeds the maximum stream size or the maximum compression ratio
+    pub fn check_decoded_size(&self, encoded: usize, decoded: usize) -> Result<(), Error> {
+        self.check_stream_size(decoded)?;
+        if decoded > self.max_decoded_size(encoded) {
+            return Err(Error::from(121, format!("Stream of {} bytes decodes to more than {} bytes, which exceeds the maximum compression ratio of {}", encoded, self.max_decoded_size(encoded), self.max_compression_ratio)));
+        }
+        Ok(())
+    }
+
+    pub fn nesting_error(&self, offset: usize) -> Error {
+        Error::from(122, format!("Objects are nested deeper than {} levels at offset {}", self.max_nesting_depth, offset))
+    }
+
+    pub fn time_error(&self) -> Error {
+        Error::from(124, format!("Processing the file took longer than {} seconds", self.max_time.unwrap_or_default().as_secs()))
+    }
+}
+
+
+/// Walks the /EmbeddedFiles name tree of the catalog (without following cycles) and returns the number of its entries.
+/// Fails if the tree has more entries and nodes than `max_name_tree_entries` or is nested deeper than `max_nesting_depth`.
+pub fn check_embedded_files(file: &RawFile) -> Result<usize, Error> {
+    let catalog = file.catalog()?;
+    let Some(names) = catalog.as_dictionary().and_then(|catalog| catalog.get("Names")) else { return Ok(0); };
+    let names = file.resolve_if_ref(names)?;
+    let Some(tree) = names.as_dictionary().and_then(|names| names.get("EmbeddedFiles")) else { return Ok(0); };
+
+    walk_name_tree(file, tree, &mut |_, _| {})
+}
+
+/// Walks the /EmbeddedFiles name tree (without following cycles), calls `visit` with every pair of name and value
+/// and returns the number of entries. The tree must stay within `max_name_tree_entries` and `max_nesting_depth`.
+pub fn walk_name_tree(file: &RawFile, tree: &RawObject, visit: &mut dyn FnMut(&RawObject, &RawObject)) -> Result<usize, Error> {
+    let mut count = 0;
+    walk_node(file, tree, 0, &mut count, &mut HashSet::new(), visit)?;
+    Ok(count)
+}
+
+fn walk_node(file: &RawFile, node: &RawObject, depth: usize, count: &mut usize, visited: &mut HashSet<u64>, visit: &mut dyn FnMut(&RawObject, &RawObject)) -> Result<(), Error> {
+    let limits = file.limits();
+    if depth > limits.max_nesting_depth {
+        return Err(Error::from(122, format!("The /EmbeddedFiles name tree is nested deeper than {} levels", limits.max_nesting_depth)));
+    }
+    if let Some((id, _)) = node.as_reference() {
+        if !visited.insert(id) {
+            return Ok(());
+        }
+    }
+
+    let node = file.resolve_if_ref(node)?;
+    let Some(dict) = node.as_dictionary() else { return Ok(()); };
+    let names = dict.get("Names").map(|names| file.resolve_if_ref(names)).transpose()?;
+    let kids = dict.get("Kids").map(|kids| file.resolve_if_ref(kids)).transpose()?;
+
+    // Pairs of name and file specification
+    let entries = names.as_ref().and_then(RawObject::as_array).map_or(&[][..], |names| names.as_slice());
+    *count += entries.len() / 2;
+    if *count + visited.len() > limits.max_name_tree_entries {
+        return Err(Error::from(123, format!("The /EmbeddedFiles name tree has more than {} entries", limits.max_name_tree_entries)));
+    }
+    for pair in entries.chunks_exact(2) {
+        visit(&pair[0], &pair[1]);
+    }
+
+    for kid in kids.as_ref().and_then(RawObject::as_array).into_iter().flatten() {
+        walk_node(file, kid, depth + 1, count, visited, visit)?;
+    }
+    Ok(())
+}
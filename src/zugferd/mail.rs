@@ -0,0 +1,436 @@
+use base64::alphabet::STANDARD;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+
+use super::limits::Limits;
+use super::Error;
+
+
+/// Decodes base64 without caring about the padding, as some mailers drop or repeat it
+const LENIENT_BASE64: GeneralPurpose = GeneralPurpose::new(&STANDARD, GeneralPurposeConfig::new()
+    .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+    .with_decode_allow_trailing_bits(true));
+
+
+/// A file carried by an e-mail: any part which is not a multipart container, an attached message or the text of the mail
+#[derive(Clone, Debug)]
+pub struct MailAttachment {
+    /// File name from the Content-Disposition (or the name parameter of the Content-Type) with RFC 2231 and RFC 2047 decoded
+    pub name: Option<String>,
+    /// Lowercase MIME type without parameters (like application/pdf)
+    pub content_type: String,
+    /// The content with the Content-Transfer-Encoding (base64 or quoted-printable) decoded
+    pub data: Vec<u8>,
+}
+
+/// An e-mail message (RFC 5322) with the attachments of all its MIME parts, including the attachments of attached messages
+#[derive(Clone, Debug)]
+pub struct MailMessage {
+    /// The Message-ID header as written (like <1234@example.com>)
+    pub message_id: Option<String>,
+    /// The From header with encoded words decoded (like Supplier GmbH <billing@example.com>)
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    pub attachments: Vec<MailAttachment>,
+}
+
+impl MailMessage {
+    /// Parses the message and decodes all of its attachments. MIME parts are nested at most `max_nesting_depth` levels
+    /// and no attachment may decode to more than `max_stream_size` bytes. Fails with 70 if the data does not start
+    /// with a header field.
+    pub fn parse(data: &[u8], limits: &Limits) -> Result<MailMessage, Error> {
+        // Single messages saved from a mailbox may still start with the From_ line of the mbox format
+        let data = if data.starts_with(b"From ") { &data[line_end(data, 0)..] } else { data };
+
+        let entity = Entity::parse(data);
+        if entity.headers.is_empty() {
+            return Err(Error::from(70, String::from("The file is not an e-mail message (it does not start with a header field)")));
+        }
+
+        let mut attachments = Vec::new();
+        collect_attachments(data, &entity, 0, limits, &mut attachments)?;
+        Ok(MailMessage {
+            message_id: entity.header("Message-ID").map(|id| id.trim().to_string()),
+            from: entity.header("From").map(decode_words),
+            subject: entity.header("Subject").map(decode_words),
+            attachments,
+        })
+    }
+
+    /// The message-id and the sender for reports (like message <1234@example.com> from billing@example.com)
+    pub fn description(&self) -> String {
+        format!("message {} from {}", self.message_id.as_deref().unwrap_or("without id"), self.from.as_deref().unwrap_or("unknown sender"))
+    }
+}
+
+
+/// Splits an mbox mailbox into its messages. Each message starts with a From_ line (`From sender date`) at the start of
+/// the file or after an empty line, which is not part of the message. Data before the first From_ line is treated as
+/// a message of its own unless it is empty, so a single message can be read as mailbox as well.
+pub fn split_mbox(data: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut message_start = 0;
+    let mut previous_empty = true;
+    let mut position = 0;
+
+    while position < data.len() {
+        let end = line_end(data, position);
+        let line = &data[position..end];
+        if previous_empty && line.starts_with(b"From ") {
+            let message = &data[message_start..position];
+            if position > 0 && !message.iter().all(u8::is_ascii_whitespace) {
+                messages.push(message);
+            }
+            message_start = end;
+        }
+        previous_empty = line == b"\n" || line == b"\r\n";
+        position = end;
+    }
+
+    let message = &data[message_start..];
+    if !message.iter().all(u8::is_ascii_whitespace) {
+        messages.push(message);
+    }
+    messages
+}
+
+
+/// A MIME entity: the header fields and the (still encoded) body
+struct Entity<'a> {
+    /// Unfolded header fields as (name, raw value)
+    headers: Vec<(String, String)>,
+    body: &'a [u8],
+}
+
+impl<'a> Entity<'a> {
+    /// Splits the header fields from the body at the first empty line. Parsing stops at the first line which is neither
+    /// a header field nor its continuation, which then starts the body.
+    fn parse(data: &'a [u8]) -> Entity<'a> {
+        let mut headers: Vec<(String, String)> = Vec::new();
+        let mut position = 0;
+
+        while position < data.len() {
+            let end = line_end(data, position);
+            let line = String::from_utf8_lossy(&data[position..end]);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                position = end;
+                break;
+            }
+
+            if line.starts_with([' ', '\t']) {
+                // Folded header field
+                match headers.last_mut() {
+                    Some((_, value)) => value.push_str(line),
+                    None => break,
+                }
+            } else {
+                match line.split_once(':') {
+                    Some((name, value)) if !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_graphic()) => {
+                        headers.push((name.to_string(), value.trim().to_string()));
+                    },
+                    _ => break,
+                }
+            }
+            position = end;
+        }
+
+        Entity { headers, body: &data[position.min(data.len())..] }
+    }
+
+    /// Value of the first header field with the given (case insensitive) name
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// The lowercase MIME type and the parameters of the Content-Type (text/plain if missing)
+    fn content_type(&self) -> (String, Vec<(String, String)>) {
+        let (content_type, parameters) = split_parameters(self.header("Content-Type").unwrap_or("text/plain"));
+        let content_type = if content_type.contains('/') { content_type.to_ascii_lowercase() } else { String::from("text/plain") };
+        (content_type, parameters)
+    }
+
+    /// The file name of the filename parameter of the Content-Disposition or the name parameter of the Content-Type
+    fn file_name(&self) -> Option<String> {
+        let disposition = self.header("Content-Disposition").map(|value| split_parameters(value).1).unwrap_or_default();
+        parameter(&disposition, "filename")
+            .or_else(|| parameter(&self.content_type().1, "name"))
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    fn is_attachment(&self) -> bool {
+        self.header("Content-Disposition").is_some_and(|value| split_parameters(value).0.eq_ignore_ascii_case("attachment"))
+    }
+
+    /// The body with the Content-Transfer-Encoding decoded (7bit, 8bit and binary bodies are returned as they are)
+    fn decoded_body(&self, limits: &Limits) -> Result<Vec<u8>, Error> {
+        let encoding = self.header("Content-Transfer-Encoding").unwrap_or("7bit").trim().to_ascii_lowercase();
+        let decoded = match encoding.as_str() {
+            "base64" => {
+                let mut encoded: Vec<u8> = self.body.iter().copied().filter(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/')).collect();
+                // A single character after the last complete group cannot encode a byte
+                if encoded.len() % 4 == 1 {
+                    encoded.pop();
+                }
+                limits.check_stream_size(encoded.len() / 4 * 3)?;
+                LENIENT_BASE64.decode(&encoded).map_err(|error| Error::from(71, format!("Failed to decode base64 encoded attachment: {}", error)))?
+            },
+            "quoted-printable" => decode_quoted_printable(self.body),
+            _ => self.body.to_vec(),
+        };
+
+        limits.check_stream_size(decoded.len())?;
+        Ok(decoded)
+    }
+}
+
+
+/// Walks the MIME tree of the entity and collects every part carrying a file. Text parts are skipped unless they have
+/// a file name or are marked as attachment, so the text of the mail is not mistaken for an attachment.
+fn collect_attachments(message: &[u8], entity: &Entity, depth: usize, limits: &Limits, attachments: &mut Vec<MailAttachment>) -> Result<(), Error> {
+    if depth > limits.max_nesting_depth {
+        return Err(limits.nesting_error(entity.body.as_ptr() as usize - message.as_ptr() as usize));
+    }
+
+    let (content_type, parameters) = entity.content_type();
+    if content_type.starts_with("multipart/") {
+        if let Some(boundary) = parameter(&parameters, "boundary") {
+            for part in split_multipart(entity.body, &boundary) {
+                collect_attachments(message, &Entity::parse(part), depth + 1, limits, attachments)?;
+            }
+            return Ok(());
+        }
+    }
+
+    let name = entity.file_name();
+    if content_type == "message/rfc822" && name.is_none() {
+        // Forwarded messages carry their attachments in their own MIME tree
+        let data = entity.decoded_body(limits)?;
+        let attached = Entity::parse(&data);
+        return collect_attachments(&data, &attached, depth + 1, limits, attachments);
+    }
+
+    if matches!(content_type.as_str(), "text/plain" | "text/html") && name.is_none() && !entity.is_attachment() {
+        return Ok(());
+    }
+
+    attachments.push(MailAttachment { name, content_type, data: entity.decoded_body(limits)? });
+    Ok(())
+}
+
+/// Splits a multipart body at the delimiter lines of the boundary. The preamble before the first and the epilogue after
+/// the closing delimiter are dropped. A missing closing delimiter ends the last part at the end of the body.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut part_start = None;
+    let mut position = 0;
+
+    while position < body.len() {
+        let end = line_end(body, position);
+        if let Some(rest) = body[position..end].strip_prefix(delimiter.as_bytes()) {
+            let closing = rest.starts_with(b"--");
+            let rest = if closing { &rest[2..] } else { rest };
+            if rest.iter().all(u8::is_ascii_whitespace) {
+                // The line break before the delimiter belongs to the delimiter
+                if let Some(start) = part_start {
+                    let part = &body[start..position];
+                    let part = part.strip_suffix(b"\n").unwrap_or(part);
+                    parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+                }
+                if closing {
+                    return parts;
+                }
+                part_start = Some(end);
+            }
+        }
+        position = end;
+    }
+
+    if let Some(start) = part_start {
+        parts.push(&body[start.min(body.len())..]);
+    }
+    parts
+}
+
+
+/// Splits a header value like `attachment; filename="invoice.pdf"` into the value before the first semicolon and the
+/// parameters with lowercase names and unquoted values
+fn split_parameters(value: &str) -> (String, Vec<(String, String)>) {
+    let mut chars = value.chars().peekable();
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => fields.last_mut().unwrap().extend(chars.next()),
+            ';' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+
+    let mut fields = fields.into_iter();
+    let value = fields.next().unwrap_or_default().trim().to_string();
+    let parameters = fields.filter_map(|field| {
+        let (name, value) = field.split_once('=')?;
+        Some((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+    }).collect();
+    (value, parameters)
+}
+
+/// Returns the value of the parameter with RFC 2231 continuations (`name*0=`, `name*1=`) and charsets (`name*=utf-8''`)
+/// decoded. Plain values may contain RFC 2047 encoded words, which many mailers write in file names.
+fn parameter(parameters: &[(String, String)], name: &str) -> Option<String> {
+    if let Some((_, value)) = parameters.iter().find(|(key, _)| *key == format!("{}*", name)) {
+        let (charset, value) = split_charset(value);
+        return Some(decode_charset(&percent_decode(value), &charset));
+    }
+
+    // Continuations are numbered from 0, only the encoded sections (with a trailing *) are percent encoded
+    let mut sections: Vec<(usize, bool, &str)> = parameters.iter().filter_map(|(key, value)| {
+        let section = key.strip_prefix(name)?.strip_prefix('*')?;
+        let (number, encoded) = match section.strip_suffix('*') {
+            Some(number) => (number, true),
+            None => (section, false),
+        };
+        Some((number.parse().ok()?, encoded, value.as_str()))
+    }).collect();
+
+    if sections.is_empty() {
+        return parameters.iter().find(|(key, _)| key == name).map(|(_, value)| decode_words(value));
+    }
+
+    sections.sort_by_key(|(number, _, _)| *number);
+    let mut charset = String::new();
+    let mut bytes = Vec::new();
+    for (number, encoded, value) in sections {
+        if !encoded {
+            bytes.extend_from_slice(value.as_bytes());
+        } else if number == 0 {
+            let (section_charset, value) = split_charset(value);
+            charset = section_charset;
+            bytes.extend(percent_decode(value));
+        } else {
+            bytes.extend(percent_decode(value));
+        }
+    }
+    Some(decode_charset(&bytes, &charset))
+}
+
+/// Splits `charset'language'value` of an RFC 2231 extended value
+fn split_charset(value: &str) -> (String, &str) {
+    let mut fields = value.splitn(3, '\'');
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(charset), Some(_), Some(value)) => (charset.to_string(), value),
+        _ => (String::new(), value),
+    }
+}
+
+fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3).and_then(hex_byte)) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    decoded
+}
+
+
+/// Decodes the RFC 2047 encoded words (`=?utf-8?B?...?=` and `=?iso-8859-1?Q?...?=`) of a header value. Whitespace
+/// between two encoded words is dropped, invalid encoded words are kept as they are.
+fn decode_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let word = rest[start + 2..].splitn(3, '?').collect::<Vec<_>>();
+        let text = match word.as_slice() {
+            [charset, encoding, text] => text.find("?=").and_then(|end| {
+                let bytes = match encoding.to_ascii_uppercase().as_str() {
+                    "B" => LENIENT_BASE64.decode(text[..end].trim_end_matches('=')).ok()?,
+                    "Q" => decode_quoted_printable(text[..end].replace('_', " ").as_bytes()),
+                    _ => return None,
+                };
+                // Skip "=?", the charset, the encoding, both question marks and the text with "?="
+                Some((decode_charset(&bytes, charset), start + 2 + charset.len() + encoding.len() + 2 + end + 2))
+            }),
+            _ => None,
+        };
+
+        match text {
+            Some((text, end)) => {
+                let between = &rest[..start];
+                if !(after_word && between.trim().is_empty()) {
+                    decoded.push_str(between);
+                }
+                decoded.push_str(&text);
+                rest = &rest[end..];
+                after_word = true;
+            },
+            None => {
+                decoded.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            },
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decodes text in the given charset. Besides UTF-8 and US-ASCII only ISO-8859-1 and Windows-1252 are common in mails
+/// of German suppliers, both are decoded as Latin-1. Text in any other charset is decoded as UTF-8 as far as possible.
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    let charset = charset.to_ascii_lowercase();
+    if matches!(charset.as_str(), "iso-8859-1" | "iso-8859-15" | "latin1" | "windows-1252" | "cp1252") {
+        bytes.iter().map(|byte| *byte as char).collect()
+    } else {
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+/// Decodes quoted-printable (RFC 2045): `=XX` is a byte, a `=` at the end of a line is a soft line break
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != b'=' {
+            decoded.push(data[i]);
+            i += 1;
+        } else if let Some(byte) = data.get(i + 1..i + 3).and_then(hex_byte) {
+            decoded.push(byte);
+            i += 3;
+        } else if data[i + 1..].starts_with(b"\r\n") {
+            i += 3;
+        } else if data[i + 1..].starts_with(b"\n") {
+            i += 2;
+        } else {
+            // A stray = is kept as it is
+            decoded.push(b'=');
+            i += 1;
+        }
+    }
+    decoded
+}
+
+fn hex_byte(digits: &[u8]) -> Option<u8> {
+    let digit = |byte: u8| (byte as char).to_digit(16);
+    Some((digit(*digits.first()?)? * 16 + digit(*digits.get(1)?)?) as u8)
+}
+
+/// Position after the next line feed at or after start (or the end of the data)
+fn line_end(data: &[u8], start: usize) -> usize {
+    data[start..].iter().position(|byte| *byte == b'\n').map_or(data.len(), |position| start + position + 1)
+}
@@ -0,0 +1,538 @@
+use std::ops::Range;
+
+use super::limits::Limits;
+use super::Error;
+
+
+/// A PDF object as parsed directly from the file bytes. Objects are resolved on demand without loading the whole
+/// document, which allows us to look at the file structure itself (xref sections, trailers, revisions) and to work
+/// on damaged files.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RawObject {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Real(f64),
+    String(Vec<u8>),
+    Name(String),
+    Array(Vec<RawObject>),
+    Dictionary(RawDictionary),
+    Reference(u64, u16),
+    /// Stream dictionary and the byte range of the (still encoded) stream data inside the file
+    Stream(RawDictionary, Range<usize>),
+}
+
+impl RawObject {
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            RawObject::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_name(&self) -> Option<&str> {
+        match self {
+            RawObject::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<RawObject>> {
+        match self {
+            RawObject::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    /// Returns the dictionary of dictionaries and streams
+    pub fn as_dictionary(&self) -> Option<&RawDictionary> {
+        match self {
+            RawObject::Dictionary(dict) | RawObject::Stream(dict, _) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_reference(&self) -> Option<(u64, u16)> {
+        match self {
+            RawObject::Reference(id, gen) => Some((*id, *gen)),
+            _ => None,
+        }
+    }
+
+    /// Decodes a text string (UTF-16BE or UTF-8 with BOM, PDFDocEncoding is treated as Latin-1 for simplicity)
+    pub fn as_text(&self) -> Option<String> {
+        match self {
+            RawObject::String(bytes) if bytes.starts_with(b"\xfe\xff") => {
+                let units: Vec<u16> = bytes[2..].chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                Some(String::from_utf16_lossy(&units))
+            },
+            RawObject::String(bytes) if bytes.starts_with(b"\xef\xbb\xbf") => Some(String::from_utf8_lossy(&bytes[3..]).into_owned()),
+            RawObject::String(bytes) => Some(bytes.iter().map(|byte| *byte as char).collect()),
+            _ => None,
+        }
+    }
+
+    /// Writes the object as valid PDF syntax (in contrast to Display, strings are written as hex strings and
+    /// names are escaped). Streams are written as their dictionary only.
+    pub fn write_to(&self, output: &mut Vec<u8>) {
+        match self {
+            RawObject::String(bytes) => {
+                output.push(b'<');
+                output.extend(bytes.iter().flat_map(|byte| format!("{:02x}", byte).into_bytes()));
+                output.push(b'>');
+            },
+            RawObject::Name(name) => write_name(name, output),
+            RawObject::Array(array) => {
+                output.push(b'[');
+                for element in array {
+                    output.push(b' ');
+                    element.write_to(output);
+                }
+                output.extend_from_slice(b" ]");
+            },
+            RawObject::Dictionary(dict) | RawObject::Stream(dict, _) => {
+                output.extend_from_slice(b"<<");
+                for (name, value) in dict.iter() {
+                    output.push(b' ');
+                    write_name(name, output);
+                    output.push(b' ');
+                    value.write_to(output);
+                }
+                output.extend_from_slice(b" >>");
+            },
+            other => output.extend_from_slice(other.to_string().as_bytes()),
+        }
+    }
+}
+
+fn write_name(name: &str, output: &mut Vec<u8>) {
+    output.push(b'/');
+    for byte in name.bytes() {
+        if byte == b'#' || !(b'!'..=b'~').contains(&byte) || is_delimiter(byte) {
+            output.extend(format!("#{:02X}", byte).into_bytes());
+        } else {
+            output.push(byte);
+        }
+    }
+}
+
+
+/// Single line PDF syntax (stream data is omitted)
+impl std::fmt::Display for RawObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RawObject::Null => write!(f, "null"),
+            RawObject::Boolean(value) => write!(f, "{}", value),
+            RawObject::Integer(value) => write!(f, "{}", value),
+            RawObject::Real(value) => write!(f, "{}", value),
+            RawObject::String(bytes) => write!(f, "({})", String::from_utf8_lossy(bytes)),
+            RawObject::Name(name) => write!(f, "/{}", name),
+            RawObject::Array(array) => {
+                write!(f, "[")?;
+                for element in array {
+                    write!(f, " {}", element)?;
+                }
+                write!(f, " ]")
+            },
+            RawObject::Dictionary(dict) => write!(f, "{}", dict),
+            RawObject::Reference(id, gen) => write!(f, "{} {} R", id, gen),
+            RawObject::Stream(dict, range) => write!(f, "{} stream <{} bytes>", dict, range.len()),
+        }
+    }
+}
+
+
+/// Dictionary which keeps the entries in file order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawDictionary {
+    entries: Vec<(String, RawObject)>,
+}
+
+impl RawDictionary {
+    pub fn get(&self, key: &str) -> Option<&RawObject> {
+        self.entries.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, RawObject)> {
+        self.entries.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut (String, RawObject)> {
+        self.entries.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn insert(&mut self, key: String, value: RawObject) {
+        match self.entries.iter_mut().find(|(name, _)| *name == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key, value)),
+        }
+    }
+
+    /// Returns the name of the /Type entry
+    pub fn type_name(&self) -> Option<&str> {
+        self.get("Type").and_then(RawObject::as_name)
+    }
+}
+
+
+impl std::fmt::Display for RawDictionary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<<")?;
+        for (name, value) in &self.entries {
+            write!(f, " /{} {}", name, value)?;
+        }
+        write!(f, " >>")
+    }
+}
+
+
+/// Recursive descent parser for PDF objects on the raw file bytes
+pub struct RawParser<'a> {
+    data: &'a [u8],
+    pub position: usize,
+    /// Current nesting depth of arrays and dictionaries
+    depth: usize,
+    limits: Limits,
+}
+
+impl<'a> RawParser<'a> {
+    pub fn new(data: &'a [u8], position: usize) -> RawParser<'a> {
+        RawParser { data, position: position.min(data.len()), depth: 0, limits: Limits::default() }
+    }
+
+    /// Uses the given limits instead of the default limits (only the maximum nesting depth applies to the parser)
+    pub fn with_limits(mut self, limits: Limits) -> RawParser<'a> {
+        self.limits = limits;
+        self
+    }
+
+    /// Parses an indirect object definition `id gen obj ... endobj` at the current position
+    pub fn parse_indirect_object(&mut self) -> Result<(u64, u16, RawObject), Error> {
+        self.parse_indirect_object_with(|_| None)
+    }
+
+    /// Like parse_indirect_object, but an indirect /Length of a stream is looked up with the given function instead of
+    /// searching for the endstream keyword, which would read through all of the stream data
+    pub fn parse_indirect_object_with(&mut self, indirect_length: impl Fn(u64) -> Option<i64>) -> Result<(u64, u16, RawObject), Error> {
+        let start = self.position;
+        let id = self.parse_unsigned().ok_or_else(|| self.error(start, "expected object number"))?;
+        let gen = self.parse_unsigned().ok_or_else(|| self.error(start, "expected generation number"))?;
+        if !self.consume_keyword(b"obj") {
+            return Err(self.error(start, "expected 'obj' keyword"));
+        }
+
+        let object = self.parse_object()?;
+        let object = match object {
+            RawObject::Dictionary(dict) if self.consume_keyword(b"stream") => {
+                let length = match dict.get("Length") {
+                    Some(RawObject::Reference(length_id, _)) if *length_id != id => indirect_length(*length_id),
+                    length => length.and_then(RawObject::as_integer),
+                };
+                let range = self.stream_range(length)?;
+                self.position = range.end;
+                self.consume_keyword(b"endstream");
+                RawObject::Stream(dict, range)
+            },
+            object => object,
+        };
+
+        Ok((id, gen as u16, object))
+    }
+
+    /// Parses the next direct object (or reference)
+    pub fn parse_object(&mut self) -> Result<RawObject, Error> {
+        self.skip_whitespace();
+        let start = self.position;
+
+        match self.peek().ok_or_else(|| self.error(start, "unexpected end of file"))? {
+            b'/' => {
+                self.position += 1;
+                Ok(RawObject::Name(self.parse_name()))
+            },
+            b'(' => {
+                self.position += 1;
+                Ok(RawObject::String(self.parse_literal_string()))
+            },
+            b'<' if self.data.get(self.position + 1) == Some(&b'<') => {
+                self.position += 2;
+                self.enter(start)?;
+                let dict = self.parse_dictionary()?;
+                self.depth -= 1;
+                Ok(RawObject::Dictionary(dict))
+            },
+            b'<' => {
+                self.position += 1;
+                Ok(RawObject::String(self.parse_hex_string()))
+            },
+            b'[' => {
+                self.position += 1;
+                self.enter(start)?;
+                let mut array = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b']') => { self.position += 1; break; },
+                        Some(_) => array.push(self.parse_object()?),
+                        None => return Err(self.error(start, "unterminated array")),
+                    }
+                }
+                self.depth -= 1;
+                Ok(RawObject::Array(array))
+            },
+            b'+' | b'-' | b'.' | b'0'..=b'9' => self.parse_number_or_reference(),
+            _ => {
+                let keyword = self.read_regular();
+                match keyword {
+                    b"true" => Ok(RawObject::Boolean(true)),
+                    b"false" => Ok(RawObject::Boolean(false)),
+                    b"null" => Ok(RawObject::Null),
+                    _ => Err(self.error(start, &format!("unexpected token '{}'", String::from_utf8_lossy(keyword)))),
+                }
+            }
+        }
+    }
+
+    /// Enters an array or dictionary, which must not exceed the maximum nesting depth
+    fn enter(&mut self, start: usize) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > self.limits.max_nesting_depth {
+            return Err(self.limits.nesting_error(start));
+        }
+        Ok(())
+    }
+
+    fn parse_dictionary(&mut self) -> Result<RawDictionary, Error> {
+        let start = self.position;
+        let mut dict = RawDictionary::default();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'>') if self.data.get(self.position + 1) == Some(&b'>') => {
+                    self.position += 2;
+                    return Ok(dict);
+                },
+                Some(b'/') => {
+                    self.position += 1;
+                    let key = self.parse_name();
+                    let value = self.parse_object()?;
+                    dict.insert(key, value);
+                },
+                Some(_) => return Err(self.error(self.position, "expected name as dictionary key")),
+                None => return Err(self.error(start, "unterminated dictionary")),
+            }
+        }
+    }
+
+    fn parse_number_or_reference(&mut self) -> Result<RawObject, Error> {
+        let start = self.position;
+        let token = self.read_regular();
+        let text = std::str::from_utf8(token).unwrap_or("");
+
+        if let Ok(value) = text.parse::<i64>() {
+            // Look ahead for "gen R"
+            let before_lookahead = self.position;
+            if value >= 0 {
+                if let Some(gen) = self.parse_unsigned() {
+                    if self.consume_keyword(b"R") {
+                        return Ok(RawObject::Reference(value as u64, gen as u16));
+                    }
+                }
+            }
+            self.position = before_lookahead;
+            return Ok(RawObject::Integer(value));
+        }
+
+        text.parse::<f64>().map(RawObject::Real).map_err(|_| self.error(start, &format!("invalid number '{}'", text)))
+    }
+
+    /// Parses an unsigned integer token, restoring the position if there is none
+    pub fn parse_unsigned(&mut self) -> Option<u64> {
+        let start = self.position;
+        self.skip_whitespace();
+        let token = self.read_regular();
+        match std::str::from_utf8(token).ok().filter(|t| t.bytes().all(|b| b.is_ascii_digit())).and_then(|t| t.parse::<u64>().ok()) {
+            Some(value) => Some(value),
+            None => { self.position = start; None }
+        }
+    }
+
+    /// Consumes the keyword if it is the next token, otherwise the position stays unchanged
+    pub fn consume_keyword(&mut self, keyword: &[u8]) -> bool {
+        let start = self.position;
+        self.skip_whitespace();
+        if self.read_regular() == keyword {
+            true
+        } else {
+            self.position = start;
+            false
+        }
+    }
+
+    fn parse_name(&mut self) -> String {
+        let raw = self.read_regular();
+        let mut name = Vec::with_capacity(raw.len());
+        let mut index = 0;
+        while index < raw.len() {
+            let decoded = if raw[index] == b'#' { raw.get(index + 1..index + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()) } else { None };
+            match decoded {
+                Some(byte) => { name.push(byte); index += 3; },
+                None => { name.push(raw[index]); index += 1; }
+            }
+        }
+        String::from_utf8_lossy(&name).to_string()
+    }
+
+    fn parse_literal_string(&mut self) -> Vec<u8> {
+        let mut result = Vec::new();
+        let mut depth = 1;
+        while let Some(byte) = self.next() {
+            match byte {
+                b'(' => { depth += 1; result.push(byte); },
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    result.push(byte);
+                },
+                b'\\' => match self.next() {
+                    Some(b'n') => result.push(b'\n'),
+                    Some(b'r') => result.push(b'\r'),
+                    Some(b't') => result.push(b'\t'),
+                    Some(b'b') => result.push(0x08),
+                    Some(b'f') => result.push(0x0C),
+                    Some(b'\r') => { if self.peek() == Some(b'\n') { self.position += 1; } },
+                    Some(b'\n') => {},
+                    Some(digit @ b'0'..=b'7') => {
+                        let mut value = (digit - b'0') as u32;
+                        for _ in 0..2 {
+                            match self.peek() {
+                                Some(next @ b'0'..=b'7') => { value = value * 8 + (next - b'0') as u32; self.position += 1; },
+                                _ => break,
+                            }
+                        }
+                        result.push(value as u8);
+                    },
+                    Some(other) => result.push(other),
+                    None => break,
+                },
+                _ => result.push(byte),
+            }
+        }
+        result
+    }
+
+    fn parse_hex_string(&mut self) -> Vec<u8> {
+        let mut digits = Vec::new();
+        while let Some(byte) = self.next() {
+            match byte {
+                b'>' => break,
+                b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' => digits.push(byte),
+                _ => {}
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(b'0');
+        }
+        digits.chunks(2).filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()).collect()
+    }
+
+    /// Determines the byte range of the stream data following the `stream` keyword
+    fn stream_range(&mut self, length: Option<i64>) -> Result<Range<usize>, Error> {
+        // The stream keyword is followed by CRLF or LF (some writers only use CR)
+        match (self.peek(), self.data.get(self.position + 1)) {
+            (Some(b'\r'), Some(b'\n')) => self.position += 2,
+            (Some(b'\r'), _) | (Some(b'\n'), _) => self.position += 1,
+            _ => {}
+        }
+        let start = self.position;
+
+        // Use the /Length if it fits, otherwise (unknown or wrong length) search for the endstream keyword
+        let length = length.filter(|length| *length >= 0).map(|length| length as usize);
+        if let Some(end) = length.and_then(|length| start.checked_add(length)).filter(|end| self.data.get(*end..).is_some_and(starts_with_endstream)) {
+            return Ok(start..end);
+        }
+
+        let end = find(self.data, b"endstream", start).ok_or_else(|| self.error(start, "missing endstream keyword"))?;
+        // Strip the EOL marker before endstream
+        let end = if end > start && self.data[end - 1] == b'\n' { end - 1 } else { end };
+        let end = if end > start && self.data[end - 1] == b'\r' { end - 1 } else { end };
+        Ok(start..end)
+    }
+
+    pub fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) {
+                self.position += 1;
+            } else if byte == b'%' {
+                while let Some(byte) = self.next() {
+                    if byte == b'\r' || byte == b'\n' {
+                        break;
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Reads a sequence of regular characters (keywords, numbers, names)
+    fn read_regular(&mut self) -> &'a [u8] {
+        let start = self.position;
+        while let Some(byte) = self.peek() {
+            if is_whitespace(byte) || is_delimiter(byte) {
+                break;
+            }
+            self.position += 1;
+        }
+        self.data.get(start..self.position).unwrap_or_default()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek();
+        if byte.is_some() {
+            self.position += 1;
+        }
+        byte
+    }
+
+    fn error(&self, position: usize, message: &str) -> Error {
+        Error::from(90, format!("PDF syntax error at offset {}: {}", position, message))
+    }
+}
+
+
+fn starts_with_endstream(data: &[u8]) -> bool {
+    let trimmed = data.iter().position(|byte| !is_whitespace(*byte)).map_or(data, |start| &data[start..]);
+    trimmed.starts_with(b"endstream")
+}
+
+pub fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, 0 | b'\t' | b'\n' | 0x0C | b'\r' | b' ')
+}
+
+pub fn is_delimiter(byte: u8) -> bool {
+    matches!(byte, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+/// Returns the position of the first occurrence of needle in data at or after start
+pub fn find(data: &[u8], needle: &[u8], start: usize) -> Option<usize> {
+    data.get(start..)?.windows(needle.len()).position(|window| window == needle).map(|position| start + position)
+}
+
+/// Returns the position of the last occurrence of needle in data
+pub fn rfind(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).rposition(|window| window == needle)
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use regex::bytes::Regex;
+
+use super::filter;
+use super::limits::Limits;
+use super::raw::{self, RawDictionary, RawObject, RawParser};
+use super::xref::{SectionKind, XRef, XRefEntry, XRefSection};
+use super::Error;
+
+
+/// Entries of the trailer which are taken over from the trailers and xref streams found in the file
+const TRAILER_KEYS: [&str; 4] = ["Root", "Info", "ID", "Encrypt"];
+
+
+/// Rebuilds the cross-reference table of a file with wrong xref offsets or a truncated trailer by scanning the whole
+/// file for `N G obj ... endobj` definitions and the objects inside object streams. Later definitions win like in
+/// incremental updates. The trailer is merged from all trailers and xref streams found in the file and /Root falls
+/// back to the last object with /Type /Catalog. The result has a single section of kind SectionKind::Recovered.
+/// Object streams are decoded within the given limits.
+pub fn recover_xref(data: &[u8], limits: &Limits) -> Result<XRef, Error> {
+    let keyword = Regex::new(r"trailer|\d{1,10}[\x00\t\n\x0C\r ]+\d{1,5}[\x00\t\n\x0C\r ]+obj").unwrap();
+    let mut entries = BTreeMap::new();
+    let mut trailer = RawDictionary::default();
+    let mut catalogs = Vec::new();
+    let mut position = 0;
+
+    while let Some(found) = keyword.find_at(data, position) {
+        position = found.end();
+        // Only accept keywords which start a token (and not something like "112 0 obj" found as "12 0 obj")
+        if found.start() > 0 && !raw::is_whitespace(data[found.start() - 1]) && !raw::is_delimiter(data[found.start() - 1]) {
+            continue;
+        }
+
+        let mut parser = RawParser::new(data, found.start());
+        if parser.consume_keyword(b"trailer") {
+            if let Ok(RawObject::Dictionary(dict)) = parser.parse_object() {
+                merge_trailer(&mut trailer, &dict);
+            }
+            continue;
+        }
+
+        let Ok((id, gen, object)) = parser.parse_indirect_object() else { continue; };
+        entries.insert(id, XRefEntry::InFile { offset: found.start(), gen });
+
+        match &object {
+            RawObject::Stream(dict, range) => {
+                // The stream data may contain anything that looks like an object
+                position = range.end;
+                match dict.type_name() {
+                    Some("ObjStm") => {
+                        for (index, (compressed_id, object)) in object_stream(data, dict, range.clone(), limits).into_iter().enumerate() {
+                            entries.insert(compressed_id, XRefEntry::InStream { stream_id: id, index: index as u32 });
+                            if is_catalog(&object) {
+                                catalogs.push(compressed_id);
+                            }
+                        }
+                    },
+                    Some("XRef") => merge_trailer(&mut trailer, dict),
+                    _ => {},
+                }
+            },
+            object if is_catalog(object) => catalogs.push(id),
+            _ => {},
+        }
+    }
+
+    if entries.is_empty() {
+        return Err(Error::from(98, String::from("Failed to recover the file: no objects found")));
+    }
+
+    // Keep the /Root of the trailer only if it still points to a catalog
+    let root = trailer.get("Root").and_then(RawObject::as_reference).map(|(id, _)| id);
+    if !root.is_some_and(|root| catalogs.contains(&root)) {
+        let catalog = catalogs.last().ok_or(Error::from(98, String::from("Failed to recover the file: no object with /Type /Catalog found")))?;
+        trailer.insert(String::from("Root"), RawObject::Reference(*catalog, entries[catalog].gen()));
+    }
+
+    let size = entries.keys().last().map_or(0, |id| id.saturating_add(1));
+    trailer.insert(String::from("Size"), RawObject::Integer(size as i64));
+
+    Ok(XRef { sections: vec![XRefSection { offset: 0, kind: SectionKind::Recovered, trailer, entries: entries.into_iter().collect() }] })
+}
+
+/// Checks whether the cross-reference sections of the file are broken: they cannot be parsed, the trailer has no /Root
+/// or an object is not found at the offset given for it
+pub fn needs_recovery(data: &[u8]) -> bool {
+    let Ok(xref) = XRef::parse(data) else { return true; };
+    if xref.trailer().and_then(|trailer| trailer.get("Root")).is_none() {
+        return true;
+    }
+
+    xref.entries().iter().any(|(id, (entry, _))| match entry {
+        XRefEntry::InFile { offset, .. } => {
+            let mut parser = RawParser::new(data, *offset);
+            parser.parse_unsigned() != Some(*id) || parser.parse_unsigned().is_none() || !parser.consume_keyword(b"obj")
+        },
+        _ => false,
+    })
+}
+
+/// Appends the recovered cross-reference table (see recover_xref) as xref stream to the file, so that PDF readers
+/// which only follow the startxref of the file can load the objects of a broken file. The xref stream may not exceed
+/// `max_stream_size` of the limits.
+pub fn repair(data: &[u8], limits: &Limits) -> Result<Vec<u8>, Error> {
+    let xref = recover_xref(data, limits)?;
+    let section = &xref.sections[0];
+    let mut entries: BTreeMap<u64, XRefEntry> = section.entries.iter().copied().collect();
+    let stream_id = entries.keys().last().map_or(Some(1), |id| id.checked_add(1))
+        .ok_or(Error::from(98, String::from("Failed to repair the file: no object number left for the xref stream")))?;
+
+    let mut output = data.to_vec();
+    if !output.ends_with(b"\n") {
+        output.push(b'\n');
+    }
+    let offset = output.len();
+
+    // Only the objects found get a row (in subsections of consecutive ids), besides the head of the free list and the xref stream itself
+    entries.entry(0).or_insert(XRefEntry::Free { next: 0, gen: 65535 });
+    entries.insert(stream_id, XRefEntry::InFile { offset, gen: 0 });
+    limits.check_stream_size(entries.len().saturating_mul(11))?;
+
+    // Uncompressed rows of type (1 byte), offset or object stream (8 bytes) and generation or index (2 bytes)
+    let mut rows = Vec::new();
+    let mut index: Vec<(u64, u64)> = Vec::new();
+    for (id, entry) in &entries {
+        let (entry_type, field2, field3) = match entry {
+            XRefEntry::InFile { offset, gen } => (1, *offset as u64, *gen as u64),
+            XRefEntry::InStream { stream_id, index } => (2, *stream_id, *index as u64),
+            XRefEntry::Free { gen, .. } => (0, 0, *gen as u64),
+        };
+        rows.push(entry_type);
+        rows.extend_from_slice(&field2.to_be_bytes());
+        rows.extend_from_slice(&(field3 as u16).to_be_bytes());
+
+        match index.last_mut() {
+            Some((start, count)) if *start + *count == *id => *count += 1,
+            _ => index.push((*id, 1)),
+        }
+    }
+
+    let mut dict = RawDictionary::default();
+    dict.insert(String::from("Type"), RawObject::Name(String::from("XRef")));
+    dict.insert(String::from("Size"), RawObject::Integer(stream_id.saturating_add(1) as i64));
+    dict.insert(String::from("Index"), RawObject::Array(index.iter().flat_map(|(start, count)| [RawObject::Integer(*start as i64), RawObject::Integer(*count as i64)]).collect()));
+    dict.insert(String::from("W"), RawObject::Array(vec![RawObject::Integer(1), RawObject::Integer(8), RawObject::Integer(2)]));
+    dict.insert(String::from("Length"), RawObject::Integer(rows.len() as i64));
+    for key in TRAILER_KEYS {
+        if let Some(value) = section.trailer.get(key) {
+            dict.insert(String::from(key), value.clone());
+        }
+    }
+
+    output.extend(format!("{} 0 obj\n", stream_id).into_bytes());
+    RawObject::Dictionary(dict).write_to(&mut output);
+    output.extend_from_slice(b"\nstream\n");
+    output.extend_from_slice(&rows);
+    output.extend(format!("\nendstream\nendobj\nstartxref\n{}\n%%EOF\n", offset).into_bytes());
+    Ok(output)
+}
+
+
+/// Returns the id and the object of all objects inside an object stream (nothing if it cannot be decoded)
+fn object_stream(data: &[u8], dict: &RawDictionary, range: std::ops::Range<usize>, limits: &Limits) -> Vec<(u64, RawObject)> {
+    let Ok(decoded) = filter::decode(&data[range], dict, limits) else { return Vec::new(); };
+    let count = dict.get("N").and_then(RawObject::as_integer).unwrap_or(0).max(0) as usize;
+    let first = dict.get("First").and_then(RawObject::as_integer).unwrap_or(0).max(0) as usize;
+
+    let mut header = RawParser::new(&decoded, 0);
+    let mut objects = Vec::new();
+    for _ in 0..count {
+        let Some((id, offset)) = header.parse_unsigned().zip(header.parse_unsigned()) else { break; };
+        // Offsets which overflow leave the object out (but keep the indices of the following ones)
+        let object = first.checked_add(offset as usize).and_then(|position| RawParser::new(&decoded, position).with_limits(*limits).parse_object().ok());
+        objects.push((id, object.unwrap_or(RawObject::Null)));
+    }
+    objects
+}
+
+fn merge_trailer(trailer: &mut RawDictionary, dict: &RawDictionary) {
+    for key in TRAILER_KEYS {
+        if let Some(value) = dict.get(key) {
+            trailer.insert(String::from(key), value.clone());
+        }
+    }
+}
+
+fn is_catalog(object: &RawObject) -> bool {
+    object.as_dictionary().and_then(RawDictionary::type_name) == Some("Catalog")
+}
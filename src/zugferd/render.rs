@@ -0,0 +1,360 @@
+use std::time::SystemTime;
+
+use super::font::{self, Font};
+use super::invoice::{Decimal, Invoice};
+use super::writer::{self, PdfWriter};
+
+
+const PAGE_WIDTH: f32 = 595.28;
+const PAGE_HEIGHT: f32 = 841.89;
+const MARGIN_LEFT: f32 = 50.0;
+const MARGIN_RIGHT: f32 = PAGE_WIDTH - 50.0;
+const MARGIN_TOP: f32 = PAGE_HEIGHT - 50.0;
+const MARGIN_BOTTOM: f32 = 70.0;
+
+const TEXT_SIZE: f32 = 9.0;
+const SMALL_SIZE: f32 = 7.0;
+const TITLE_SIZE: f32 = 16.0;
+const LINE_HEIGHT: f32 = 12.0;
+
+/// Column positions of the line item table (x, alignment, header)
+const LINE_COLUMNS: [(f32, Align, &str); 7] = [
+    (MARGIN_LEFT, Align::Left, "Pos"),
+    (80.0, Align::Left, "Description"),
+    (360.0, Align::Right, "Quantity"),
+    (366.0, Align::Left, "Unit"),
+    (450.0, Align::Right, "Unit price"),
+    (490.0, Align::Right, "VAT %"),
+    (MARGIN_RIGHT, Align::Right, "Total"),
+];
+
+/// Column positions of the VAT breakdown table
+const VAT_COLUMNS: [(f32, Align, &str); 4] = [
+    (300.0, Align::Left, "VAT category"),
+    (420.0, Align::Right, "Rate %"),
+    (480.0, Align::Right, "Basis"),
+    (MARGIN_RIGHT, Align::Right, "VAT"),
+];
+
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    Right,
+}
+
+
+/// Options for rendering a hybrid invoice
+pub struct RenderOptions<'a> {
+    /// Name of the embedded XML attachment (defaults to factur-x.xml for CII and xrechnung.xml for UBL)
+    pub attachment_name: Option<&'a str>,
+    /// ICC profile to embed as PDF/A output intent
+    pub icc_profile: Option<&'a [u8]>,
+}
+
+
+/// Lays out the invoice model onto A4 pages and embeds the original XML as Factur-X/ZUGFeRD attachment,
+/// producing a PDF/A-3 hybrid invoice.
+pub fn render_hybrid(invoice: &Invoice, xml: &[u8], font: &Font, options: &RenderOptions) -> Vec<u8> {
+    let attachment_name = options.attachment_name.unwrap_or(invoice.attachment_name());
+    let pages = Layout::new(font).render(invoice, attachment_name);
+    let (pdf_date, xmp_date) = writer::format_dates(SystemTime::now());
+
+    let mut pdf = PdfWriter::new();
+    let catalog_id = pdf.reserve();
+    let pages_id = pdf.reserve();
+
+    // Font
+    let font_file_id = pdf.add_stream(&format!("/Length1 {}", font.data.len()), &font.data, true);
+    let descriptor_id = pdf.add(format!("<< /Type /FontDescriptor /FontName {} /Flags 32 /FontBBox [{} {} {} {}] /ItalicAngle {} /Ascent {} /Descent {} /CapHeight {} /StemV 80 /FontFile2 {} 0 R >>",
+        writer::name(&font.name), font.bbox[0], font.bbox[1], font.bbox[2], font.bbox[3], font.italic_angle, font.ascent, font.descent, font.cap_height, font_file_id));
+    let widths = font.widths.iter().map(|width| width.to_string()).collect::<Vec<_>>().join(" ");
+    let font_id = pdf.add(format!("<< /Type /Font /Subtype /TrueType /BaseFont {} /FirstChar {} /LastChar {} /Widths [{}] /Encoding /WinAnsiEncoding /FontDescriptor {} 0 R >>",
+        writer::name(&font.name), font::FIRST_CHAR, font::LAST_CHAR, widths, descriptor_id));
+
+    // Pages
+    let page_ids: Vec<u32> = pages.iter().map(|content| {
+        let content_id = pdf.add_stream("", content.as_bytes(), true);
+        pdf.add(format!("<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 {} 0 R >> >> /Contents {} 0 R >>",
+            pages_id, PAGE_WIDTH, PAGE_HEIGHT, font_id, content_id))
+    }).collect();
+    let kids = page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" ");
+    pdf.set(pages_id, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, page_ids.len()).into_bytes());
+
+    // Invoice attachment
+    let embedded_file_id = pdf.add_stream(&format!("/Type /EmbeddedFile /Subtype /text#2Fxml /Params << /ModDate {} /Size {} >>", writer::literal_string(pdf_date.as_bytes()), xml.len()), xml, true);
+    let filespec_id = pdf.add(format!("<< /Type /Filespec /F {} /UF {} /Desc (Factur-X/ZUGFeRD invoice) /AFRelationship /Alternative /EF << /F {} 0 R /UF {} 0 R >> >>",
+        writer::literal_string(attachment_name.as_bytes()), writer::text_string(attachment_name), embedded_file_id, embedded_file_id));
+
+    let metadata = xmp_metadata(invoice, attachment_name, &xmp_date);
+    let metadata_id = pdf.add_stream("/Type /Metadata /Subtype /XML", metadata.as_bytes(), false);
+
+    let output_intents = options.icc_profile.map(|profile| {
+        let profile_id = pdf.add_stream(&format!("/N {}", icc_components(profile)), profile, true);
+        format!(" /OutputIntents [<< /Type /OutputIntent /S /GTS_PDFA1 /OutputConditionIdentifier (Custom) /DestOutputProfile {} 0 R >>]", profile_id)
+    }).unwrap_or_default();
+
+    pdf.set(catalog_id, format!("<< /Type /Catalog /Pages {} 0 R /Metadata {} 0 R /AF [{} 0 R] /Names << /EmbeddedFiles << /Names [{} {} 0 R] >> >> /PageMode /UseAttachments /ViewerPreferences << /DisplayDocTitle true >>{} >>",
+        pages_id, metadata_id, filespec_id, writer::literal_string(attachment_name.as_bytes()), filespec_id, output_intents).into_bytes());
+
+    pdf.finish(catalog_id)
+}
+
+
+/// Returns the number of color components of the given ICC profile (read from the color space in the profile header)
+fn icc_components(profile: &[u8]) -> u8 {
+    match profile.get(16..20) {
+        Some(b"GRAY") => 1,
+        Some(b"CMYK") => 4,
+        _ => 3,
+    }
+}
+
+
+/// Builds the XMP metadata with the PDF/A-3 identification and the Factur-X extension schema
+fn xmp_metadata(invoice: &Invoice, attachment_name: &str, date: &str) -> String {
+    let property = |name: &str, description: &str| format!(
+        "<rdf:li rdf:parseType=\"Resource\"><pdfaProperty:name>{}</pdfaProperty:name><pdfaProperty:valueType>Text</pdfaProperty:valueType><pdfaProperty:category>external</pdfaProperty:category><pdfaProperty:description>{}</pdfaProperty:description></rdf:li>\n",
+        name, description);
+
+    format!(r#"<?xpacket begin="{bom}" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description rdf:about="" xmlns:pdfaid="http://www.aiim.org/pdfa/ns/id/">
+<pdfaid:part>3</pdfaid:part>
+<pdfaid:conformance>B</pdfaid:conformance>
+</rdf:Description>
+<rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:title><rdf:Alt><rdf:li xml:lang="x-default">{title}</rdf:li></rdf:Alt></dc:title>
+</rdf:Description>
+<rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+<xmp:CreatorTool>zugferd render {version}</xmp:CreatorTool>
+<xmp:CreateDate>{date}</xmp:CreateDate>
+<xmp:ModifyDate>{date}</xmp:ModifyDate>
+</rdf:Description>
+<rdf:Description rdf:about="" xmlns:pdf="http://ns.adobe.com/pdf/1.3/">
+<pdf:Producer>zugferd {version}</pdf:Producer>
+</rdf:Description>
+<rdf:Description rdf:about="" xmlns:fx="urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#">
+<fx:DocumentType>INVOICE</fx:DocumentType>
+<fx:DocumentFileName>{file_name}</fx:DocumentFileName>
+<fx:Version>1.0</fx:Version>
+<fx:ConformanceLevel>{conformance}</fx:ConformanceLevel>
+</rdf:Description>
+<rdf:Description rdf:about="" xmlns:pdfaExtension="http://www.aiim.org/pdfa/ns/extension/" xmlns:pdfaSchema="http://www.aiim.org/pdfa/ns/schema#" xmlns:pdfaProperty="http://www.aiim.org/pdfa/ns/property#">
+<pdfaExtension:schemas><rdf:Bag><rdf:li rdf:parseType="Resource">
+<pdfaSchema:schema>Factur-X PDFA Extension Schema</pdfaSchema:schema>
+<pdfaSchema:namespaceURI>urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#</pdfaSchema:namespaceURI>
+<pdfaSchema:prefix>fx</pdfaSchema:prefix>
+<pdfaSchema:property><rdf:Seq>
+{properties}</rdf:Seq></pdfaSchema:property>
+</rdf:li></rdf:Bag></pdfaExtension:schemas>
+</rdf:Description>
+</rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#,
+        bom = '\u{FEFF}',
+        title = xml_escape(&format!("{} {}", document_title(invoice), invoice.number)),
+        version = env!("CARGO_PKG_VERSION"),
+        date = date,
+        file_name = xml_escape(attachment_name),
+        conformance = invoice.conformance_level(),
+        properties = [
+            property("DocumentFileName", "name of the embedded XML invoice file"),
+            property("DocumentType", "INVOICE"),
+            property("Version", "The actual version of the Factur-X XML schema"),
+            property("ConformanceLevel", "The conformance level of the embedded Factur-X data"),
+        ].concat())
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Returns the document title for the invoice type code (UNTDID 1001)
+fn document_title(invoice: &Invoice) -> &'static str {
+    match invoice.type_code.as_deref() {
+        Some("381") => "Credit note",
+        Some("384") => "Corrected invoice",
+        Some("389") => "Self-billed invoice",
+        Some("326") => "Partial invoice",
+        Some("386") => "Prepayment invoice",
+        _ => "Invoice",
+    }
+}
+
+fn amount(value: &Option<Decimal>) -> String {
+    value.map(|value| value.with_min_scale(2).unwrap_or(value).to_string()).unwrap_or_default()
+}
+
+
+/// Builds the content streams of all pages
+struct Layout<'a> {
+    font: &'a Font,
+    pages: Vec<String>,
+    content: String,
+    y: f32,
+}
+
+impl<'a> Layout<'a> {
+    fn new(font: &'a Font) -> Layout<'a> {
+        Layout { font, pages: Vec::new(), content: String::new(), y: MARGIN_TOP }
+    }
+
+    fn render(mut self, invoice: &Invoice, attachment_name: &str) -> Vec<String> {
+        self.header(invoice);
+        self.line_items(invoice);
+        self.vat_breakdown(invoice);
+        self.totals(invoice);
+        self.finish_page();
+
+        // Now that the page count is known, we can add the footers
+        let page_count = self.pages.len();
+        let note = format!("Visual representation of the embedded {} - only the XML data is legally binding.", attachment_name);
+        let font = self.font;
+        self.pages.iter_mut().enumerate().map(|(index, content)| {
+            let mut footer = Layout::new(font);
+            footer.text(MARGIN_LEFT, 40.0, SMALL_SIZE, &note, Align::Left);
+            footer.text(MARGIN_RIGHT, 40.0, SMALL_SIZE, &format!("Page {} of {}", index + 1, page_count), Align::Right);
+            std::mem::take(content) + &footer.content
+        }).collect()
+    }
+
+    fn header(&mut self, invoice: &Invoice) {
+        let seller = &invoice.seller;
+        let seller_line = [Some(seller.name.clone()), seller.street.clone(), join_city(seller.postcode.as_deref(), seller.city.as_deref()), seller.country_code.clone()]
+            .into_iter().flatten().filter(|part| !part.is_empty()).collect::<Vec<_>>().join(" · ");
+        self.text(MARGIN_LEFT, self.y, SMALL_SIZE, &seller_line, Align::Left);
+        self.y -= 3.0 * LINE_HEIGHT;
+
+        // Buyer address block on the left and document info on the right
+        let top = self.y;
+        let buyer = &invoice.buyer;
+        for line in [Some(buyer.name.clone()), buyer.street.clone(), join_city(buyer.postcode.as_deref(), buyer.city.as_deref()), buyer.country_code.clone()].into_iter().flatten() {
+            self.text(MARGIN_LEFT, self.y, TEXT_SIZE, &line, Align::Left);
+            self.y -= LINE_HEIGHT;
+        }
+        let address_bottom = self.y;
+
+        self.y = top;
+        let info = [
+            ("Invoice number", Some(invoice.number.clone())),
+            ("Issue date", invoice.issue_date.clone()),
+            ("Due date", invoice.due_date.clone()),
+            ("Buyer reference", invoice.buyer_reference.clone()),
+            ("Currency", invoice.currency.clone()),
+            ("Seller VAT ID", seller.vat_id.clone()),
+            ("Buyer VAT ID", buyer.vat_id.clone()),
+        ];
+        for (label, value) in info.iter().filter_map(|(label, value)| value.as_ref().map(|value| (label, value))) {
+            self.text(350.0, self.y, TEXT_SIZE, label, Align::Left);
+            self.text(MARGIN_RIGHT, self.y, TEXT_SIZE, value, Align::Right);
+            self.y -= LINE_HEIGHT;
+        }
+
+        self.y = self.y.min(address_bottom) - 2.0 * LINE_HEIGHT;
+        self.text(MARGIN_LEFT, self.y, TITLE_SIZE, &format!("{} {}", document_title(invoice), invoice.number), Align::Left);
+        self.y -= 2.0 * LINE_HEIGHT;
+    }
+
+    fn line_items(&mut self, invoice: &Invoice) {
+        self.table_header(&LINE_COLUMNS);
+        let description_width = LINE_COLUMNS[2].0 - LINE_COLUMNS[1].0 - 45.0;
+
+        for line in &invoice.lines {
+            if self.y < MARGIN_BOTTOM + LINE_HEIGHT {
+                self.finish_page();
+                self.table_header(&LINE_COLUMNS);
+            }
+
+            let description = self.font.fit_text(&line.name, TEXT_SIZE, description_width);
+            let values = [
+                line.id.clone(),
+                description,
+                line.quantity.map(|quantity| quantity.to_string()).unwrap_or_default(),
+                line.unit_code.clone().unwrap_or_default(),
+                amount(&line.net_price),
+                line.vat_rate.map(|rate| rate.to_string()).unwrap_or_default(),
+                amount(&line.line_total),
+            ];
+            self.table_row(&LINE_COLUMNS, &values);
+        }
+        self.y -= LINE_HEIGHT;
+    }
+
+    fn vat_breakdown(&mut self, invoice: &Invoice) {
+        if invoice.vat_breakdown.is_empty() {
+            return;
+        }
+
+        if self.y < MARGIN_BOTTOM + (invoice.vat_breakdown.len() as f32 + 2.0) * LINE_HEIGHT {
+            self.finish_page();
+        }
+
+        self.table_header(&VAT_COLUMNS);
+        for vat in &invoice.vat_breakdown {
+            let values = [vat.category.clone(), vat.rate.map(|rate| rate.to_string()).unwrap_or_default(), amount(&vat.basis), amount(&vat.amount)];
+            self.table_row(&VAT_COLUMNS, &values);
+        }
+        self.y -= LINE_HEIGHT;
+    }
+
+    fn totals(&mut self, invoice: &Invoice) {
+        let currency = invoice.currency.clone().unwrap_or_default();
+        let totals = [
+            ("Sum of line amounts", &invoice.totals.line_total),
+            ("Total without VAT", &invoice.totals.tax_basis),
+            ("VAT total", &invoice.totals.tax_total),
+            ("Total with VAT", &invoice.totals.grand_total),
+            ("Amount due", &invoice.totals.due_payable),
+        ];
+
+        if self.y < MARGIN_BOTTOM + totals.len() as f32 * LINE_HEIGHT {
+            self.finish_page();
+        }
+
+        for (label, value) in totals.iter().filter(|(_, value)| value.is_some()) {
+            self.text(300.0, self.y, TEXT_SIZE, label, Align::Left);
+            self.text(MARGIN_RIGHT, self.y, TEXT_SIZE, &format!("{} {}", amount(value), currency), Align::Right);
+            self.y -= LINE_HEIGHT;
+        }
+    }
+
+    fn table_header(&mut self, columns: &[(f32, Align, &str)]) {
+        let values: Vec<String> = columns.iter().map(|(_, _, header)| String::from(*header)).collect();
+        self.table_row(columns, &values);
+        self.line(columns[0].0, self.y + LINE_HEIGHT - 3.0, MARGIN_RIGHT, self.y + LINE_HEIGHT - 3.0);
+    }
+
+    fn table_row(&mut self, columns: &[(f32, Align, &str)], values: &[String]) {
+        for ((x, align, _), value) in columns.iter().zip(values) {
+            self.text(*x, self.y, TEXT_SIZE, value, *align);
+        }
+        self.y -= LINE_HEIGHT;
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, text: &str, align: Align) {
+        let x = match align {
+            Align::Left => x,
+            Align::Right => x - self.font.text_width(text, size),
+        };
+        let encoded = writer::literal_string(&font::encode_win_ansi(text));
+        self.content.push_str(&format!("BT /F1 {} Tf {:.2} {:.2} Td {} Tj ET\n", size, x, y, encoded));
+    }
+
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.content.push_str(&format!("0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n", x1, y1, x2, y2));
+    }
+
+    fn finish_page(&mut self) {
+        self.pages.push(std::mem::take(&mut self.content));
+        self.y = MARGIN_TOP;
+    }
+}
+
+fn join_city(postcode: Option<&str>, city: Option<&str>) -> Option<String> {
+    match (postcode, city) {
+        (None, None) => None,
+        (postcode, city) => Some(format!("{} {}", postcode.unwrap_or(""), city.unwrap_or("")).trim().to_string()),
+    }
+}
@@ -0,0 +1,65 @@
+use super::raw::{self, RawParser};
+use super::xref::{self, SectionKind, XRefEntry};
+use super::Error;
+
+
+/// One revision of the document, which is either the original document or an incremental update appended to it
+#[derive(Clone, Debug)]
+pub struct Revision {
+    /// 1 based number of the revision (1 = original document)
+    pub number: usize,
+    /// Offset of the cross-reference section of this revision (the value of its startxref)
+    pub startxref: usize,
+    pub kind: SectionKind,
+    /// End of the revision in the file (after its %%EOF marker), so `data[..end]` is the document as of this revision
+    pub end: usize,
+    /// Objects which have been added, changed or deleted (free entries) by this revision
+    pub objects: Vec<(u64, XRefEntry)>,
+}
+
+
+/// Splits the file into its revisions (oldest first) by looking at every %%EOF marker and the startxref before it.
+/// Markers without a valid cross-reference section (like the dummy first page trailer of linearized files) are skipped.
+pub fn revisions(data: &[u8]) -> Result<Vec<Revision>, Error> {
+    let mut revisions: Vec<Revision> = Vec::new();
+    let mut position = 0;
+
+    while let Some(marker) = raw::find(data, b"%%EOF", position) {
+        position = marker + b"%%EOF".len();
+        let end = match data.get(position..) {
+            Some([b'\r', b'\n', ..]) => position + 2,
+            Some([b'\r' | b'\n', ..]) => position + 1,
+            _ => position,
+        };
+
+        let Some(startxref) = raw::rfind(&data[..marker], b"startxref")
+            .and_then(|keyword| RawParser::new(data, keyword + b"startxref".len()).parse_unsigned())
+            .map(|offset| offset as usize) else { continue; };
+
+        // Some writers repeat the trailer of the previous revision
+        if revisions.last().is_some_and(|previous| previous.startxref == startxref) {
+            continue;
+        }
+
+        if let Ok(section) = xref::parse_section(data, startxref) {
+            revisions.push(Revision { number: revisions.len() + 1, startxref, kind: section.kind, end, objects: section.entries });
+        }
+    }
+
+    // Files without (valid) %%EOF marker consist of a single revision up to the end of the file
+    if revisions.is_empty() {
+        let startxref = xref::find_startxref(data)?;
+        let section = xref::parse_section(data, startxref)?;
+        revisions.push(Revision { number: 1, startxref, kind: section.kind, end: data.len(), objects: section.entries });
+    }
+
+    Ok(revisions)
+}
+
+/// Returns the file data as of the given revision (1 = original document)
+pub fn revision_data(data: &[u8], number: usize) -> Result<&[u8], Error> {
+    let revisions = revisions(data)?;
+    let revision = number.checked_sub(1).and_then(|index| revisions.get(index))
+        .ok_or(Error::from(97, format!("Revision {} does not exist, the document has {} revision(s)", number, revisions.len())))?;
+    Ok(&data[..revision.end])
+}
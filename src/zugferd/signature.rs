@@ -0,0 +1,247 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::raw::{RawDictionary, RawObject};
+use super::revision;
+use super::xref::{RawFile, XRefEntry};
+use super::Error;
+
+
+/// A digital signature (or document timestamp) of the document
+#[derive(Clone, Debug)]
+pub struct Signature {
+    /// Object id of the signature dictionary (or of the signature field if it contains the dictionary directly)
+    pub id: u64,
+    /// The signed parts of the file as pairs of offset and length
+    pub byte_range: Vec<(usize, usize)>,
+    /// Signature format like adbe.pkcs7.detached or ETSI.CAdES.detached
+    pub sub_filter: Option<String>,
+    /// The signature value (usually a DER encoded CMS object padded with zeros)
+    pub contents: Vec<u8>,
+    /// Name (/T) of the signature field
+    pub field: Option<String>,
+    /// Name of the signer (/Name) as claimed by the signature dictionary
+    pub name: Option<String>,
+    /// Signing time (/M) as claimed by the signature dictionary
+    pub time: Option<String>,
+}
+
+impl Signature {
+    /// Builds the signature from a signature dictionary, which must have a valid /ByteRange
+    fn from_dictionary(id: u64, dict: &RawDictionary) -> Option<Signature> {
+        let values = dict.get("ByteRange")?.as_array()?.iter()
+            .map(|value| value.as_integer().and_then(|value| usize::try_from(value).ok()))
+            .collect::<Option<Vec<usize>>>()?;
+        if values.is_empty() || values.len() % 2 != 0 {
+            return None;
+        }
+
+        let byte_range = values.chunks(2).map(|pair| (pair[0], pair[1])).collect();
+        let sub_filter = dict.get("SubFilter").and_then(RawObject::as_name).map(String::from);
+        let contents = match dict.get("Contents") {
+            Some(RawObject::String(contents)) => contents.clone(),
+            _ => Vec::new(),
+        };
+        Some(Signature { id, byte_range, sub_filter, contents, field: None, name: dict.get("Name").and_then(RawObject::as_text), time: dict.get("M").and_then(RawObject::as_text) })
+    }
+
+    /// End of the signed data. Everything after this offset was appended after signing.
+    pub fn signed_end(&self) -> usize {
+        self.byte_range.iter().map(|(offset, length)| offset.saturating_add(*length)).max().unwrap_or(0)
+    }
+}
+
+
+/// An attachment related object, which was added, replaced or deleted by an incremental update after a signature
+#[derive(Clone, Debug)]
+pub struct UnsignedChange {
+    pub id: u64,
+    /// Number of the (newest) revision which changed the object
+    pub revision: usize,
+    /// What the object is used for like "embedded file stream" or "file specification"
+    pub description: &'static str,
+    pub deleted: bool,
+}
+
+impl std::fmt::Display for UnsignedChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = if self.deleted { "deleted" } else { "changed" };
+        write!(f, "object {} ({}) {} in revision {}", self.id, self.description, action, self.revision)
+    }
+}
+
+
+/// Finds all signature dictionaries of the document by looking at every object with a /ByteRange.
+/// We don't only follow the /AcroForm fields, as an incremental update could simply remove them.
+pub fn signatures(file: &RawFile) -> Vec<Signature> {
+    let mut signatures = Vec::new();
+    let mut fields = HashMap::new();
+    for id in file.entries().keys() {
+        let Ok(object) = file.resolve(*id) else { continue; };
+        let Some(dict) = object.as_dictionary() else { continue; };
+
+        // Signature fields contain the signature dictionary in /V (as reference or directly)
+        let (dict, field) = match (dict.get("FT").and_then(RawObject::as_name), dict.get("V")) {
+            (Some("Sig"), Some(RawObject::Dictionary(value))) => (value, dict.get("T").and_then(RawObject::as_text)),
+            (Some("Sig"), Some(RawObject::Reference(value, _))) => {
+                fields.insert(*value, dict.get("T").and_then(RawObject::as_text));
+                continue;
+            },
+            _ => (dict, None),
+        };
+
+        if let Some(mut signature) = Signature::from_dictionary(*id, dict) {
+            signature.field = field;
+            signatures.push(signature);
+        }
+    }
+
+    for signature in signatures.iter_mut() {
+        if let Some(field) = fields.remove(&signature.id) {
+            signature.field = field;
+        }
+    }
+    signatures
+}
+
+/// Returns the attachment related objects (embedded file streams, file specifications, the /AF array,
+/// the /EmbeddedFiles name tree and the /AF and /Names entries of the catalog) which were modified
+/// by incremental updates outside of the signed byte range
+pub fn unsigned_attachment_changes(file: &RawFile, signature: &Signature) -> Result<Vec<UnsignedChange>, Error> {
+    let signed_end = signature.signed_end();
+    let revisions = revision::revisions(file.data)?;
+    let Some(signed) = revisions.iter().rev().find(|revision| revision.startxref < signed_end) else {
+        return Ok(Vec::new());
+    };
+
+    // Newest change of every object after the signed revision
+    let mut changed: BTreeMap<u64, (usize, XRefEntry)> = BTreeMap::new();
+    for revision in revisions.iter().filter(|revision| revision.startxref >= signed_end) {
+        for (id, entry) in &revision.objects {
+            changed.insert(*id, (revision.number, *entry));
+        }
+    }
+    if changed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Attachments may have been replaced (current document) or removed (signed document)
+    let signed_file = RawFile::open(&file.data[..signed.end])?;
+    let mut attachments = attachment_objects(&signed_file);
+    for (id, description) in attachment_objects(file) {
+        attachments.entry(id).or_insert(description);
+    }
+
+    let mut changes: Vec<UnsignedChange> = changed.iter()
+        .filter(|(id, _)| **id != 0)
+        .filter_map(|(id, (revision, entry))| attachments.get(id).map(|description| UnsignedChange {
+            id: *id,
+            revision: *revision,
+            description,
+            deleted: matches!(entry, XRefEntry::Free { .. }),
+        }))
+        .collect();
+
+    // Changing the catalog itself is fine as long as the references to the attachments stay the same
+    let (root_id, current_catalog) = resolve_catalog(file);
+    let (_, signed_catalog) = resolve_catalog(&signed_file);
+    if let Some((root_id, (revision, _))) = root_id.and_then(|id| changed.get(&id).map(|change| (id, change))) {
+        for (key, description) in [("AF", "/AF entry of the catalog"), ("Names", "/Names entry of the catalog")] {
+            let entry = |catalog: &RawObject| catalog.as_dictionary().and_then(|dict| dict.get(key)).cloned();
+            if entry(&current_catalog) != entry(&signed_catalog) {
+                changes.push(UnsignedChange { id: root_id, revision: *revision, description, deleted: false });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+
+/// Returns the object id and the resolved catalog (/Root) of the file
+fn resolve_catalog(file: &RawFile) -> (Option<u64>, RawObject) {
+    let root = file.xref.trailer().and_then(|trailer| trailer.get("Root")).cloned().unwrap_or(RawObject::Null);
+    let id = root.as_reference().map(|(id, _)| id);
+    (id, file.resolve_if_ref(&root).unwrap_or(RawObject::Null))
+}
+
+/// Collects the ids of all indirect objects which make up the attachments of the document.
+/// Broken objects are skipped, as we only want to know which objects belong to the attachments.
+fn attachment_objects(file: &RawFile) -> HashMap<u64, &'static str> {
+    let mut objects = HashMap::new();
+    let (_, catalog) = resolve_catalog(file);
+    let Some(catalog) = catalog.as_dictionary() else { return objects; };
+
+    if let Some(af) = catalog.get("AF") {
+        mark(af, "/AF array", &mut objects);
+        if let Ok(RawObject::Array(file_specs)) = file.resolve_if_ref(af) {
+            for file_spec in &file_specs {
+                collect_file_spec(file, file_spec, &mut objects);
+            }
+        }
+    }
+
+    if let Some(names) = catalog.get("Names") {
+        mark(names, "names dictionary", &mut objects);
+        let names = file.resolve_if_ref(names).unwrap_or(RawObject::Null);
+        if let Some(tree) = names.as_dictionary().and_then(|names| names.get("EmbeddedFiles")) {
+            collect_name_tree(file, tree, 0, &mut objects, &mut HashSet::new());
+        }
+    }
+
+    objects
+}
+
+/// Collects the file specification and the embedded file streams of its /EF dictionary
+fn collect_file_spec(file: &RawFile, file_spec: &RawObject, objects: &mut HashMap<u64, &'static str>) {
+    mark(file_spec, "file specification", objects);
+    let file_spec = file.resolve_if_ref(file_spec).unwrap_or(RawObject::Null);
+    let Some(ef) = file_spec.as_dictionary().and_then(|dict| dict.get("EF")) else { return; };
+
+    mark(ef, "/EF dictionary", objects);
+    if let Ok(RawObject::Dictionary(ef)) = file.resolve_if_ref(ef) {
+        for (_, stream) in ef.iter() {
+            mark(stream, "embedded file stream", objects);
+        }
+    }
+}
+
+/// Collects the nodes of the /EmbeddedFiles name tree and the file specifications in its leaves
+/// (down to the maximum nesting depth of the file's limits)
+fn collect_name_tree(file: &RawFile, node: &RawObject, depth: usize, objects: &mut HashMap<u64, &'static str>, visited: &mut HashSet<u64>) {
+    if depth > file.limits().max_nesting_depth {
+        return;
+    }
+    if let Some((id, _)) = node.as_reference() {
+        if !visited.insert(id) {
+            return;
+        }
+    }
+
+    mark(node, "/EmbeddedFiles name tree node", objects);
+    let node = file.resolve_if_ref(node).unwrap_or(RawObject::Null);
+    let Some(dict) = node.as_dictionary() else { return; };
+
+    if let Some(names) = dict.get("Names") {
+        mark(names, "/EmbeddedFiles name tree node", objects);
+        if let Ok(RawObject::Array(names)) = file.resolve_if_ref(names) {
+            // Pairs of name and file specification
+            for file_spec in names.iter().skip(1).step_by(2) {
+                collect_file_spec(file, file_spec, objects);
+            }
+        }
+    }
+
+    if let Some(kids) = dict.get("Kids") {
+        if let Ok(RawObject::Array(kids)) = file.resolve_if_ref(kids) {
+            for kid in &kids {
+                collect_name_tree(file, kid, depth + 1, objects, visited);
+            }
+        }
+    }
+}
+
+fn mark(object: &RawObject, description: &'static str, objects: &mut HashMap<u64, &'static str>) {
+    if let Some((id, _)) = object.as_reference() {
+        objects.entry(id).or_insert(description);
+    }
+}
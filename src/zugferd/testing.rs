@@ -0,0 +1,219 @@
+use super::raw;
+use super::writer::{self, PdfWriter};
+
+
+/// A minimal CII invoice (Factur-X/ZUGFeRD EN 16931 profile), which Invoice::parse accepts
+pub const SAMPLE_INVOICE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rsm:CrossIndustryInvoice xmlns:rsm="urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100" xmlns:ram="urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100" xmlns:udt="urn:un:unece:uncefact:data:standard:UnqualifiedDataType:100">
+<rsm:ExchangedDocumentContext><ram:GuidelineSpecifiedDocumentContextParameter><ram:ID>urn:cen.eu:en16931:2017</ram:ID></ram:GuidelineSpecifiedDocumentContextParameter></rsm:ExchangedDocumentContext>
+<rsm:ExchangedDocument><ram:ID>TEST-0001</ram:ID><ram:TypeCode>380</ram:TypeCode><ram:IssueDateTime><udt:DateTimeString format="102">20240101</udt:DateTimeString></ram:IssueDateTime></rsm:ExchangedDocument>
+<rsm:SupplyChainTradeTransaction>
+<ram:IncludedSupplyChainTradeLineItem><ram:AssociatedDocumentLineDocument><ram:LineID>1</ram:LineID></ram:AssociatedDocumentLineDocument><ram:SpecifiedTradeProduct><ram:Name>Test article</ram:Name></ram:SpecifiedTradeProduct><ram:SpecifiedLineTradeAgreement><ram:NetPriceProductTradePrice><ram:ChargeAmount>100.00</ram:ChargeAmount></ram:NetPriceProductTradePrice></ram:SpecifiedLineTradeAgreement><ram:SpecifiedLineTradeDelivery><ram:BilledQuantity unitCode="H87">1</ram:BilledQuantity></ram:SpecifiedLineTradeDelivery><ram:SpecifiedLineTradeSettlement><ram:ApplicableTradeTax><ram:TypeCode>VAT</ram:TypeCode><ram:CategoryCode>S</ram:CategoryCode><ram:RateApplicablePercent>19</ram:RateApplicablePercent></ram:ApplicableTradeTax><ram:SpecifiedTradeSettlementLineMonetarySummation><ram:LineTotalAmount>100.00</ram:LineTotalAmount></ram:SpecifiedTradeSettlementLineMonetarySummation></ram:SpecifiedLineTradeSettlement></ram:IncludedSupplyChainTradeLineItem>
+<ram:ApplicableHeaderTradeAgreement>
+<ram:SellerTradeParty><ram:Name>Seller GmbH</ram:Name><ram:PostalTradeAddress><ram:CountryID>DE</ram:CountryID></ram:PostalTradeAddress><ram:SpecifiedTaxRegistration><ram:ID schemeID="VA">DE123456789</ram:ID></ram:SpecifiedTaxRegistration></ram:SellerTradeParty>
+<ram:BuyerTradeParty><ram:Name>Buyer AG</ram:Name><ram:PostalTradeAddress><ram:CountryID>DE</ram:CountryID></ram:PostalTradeAddress></ram:BuyerTradeParty>
+</ram:ApplicableHeaderTradeAgreement>
+<ram:ApplicableHeaderTradeSettlement><ram:InvoiceCurrencyCode>EUR</ram:InvoiceCurrencyCode>
+<ram:ApplicableTradeTax><ram:CalculatedAmount>19.00</ram:CalculatedAmount><ram:TypeCode>VAT</ram:TypeCode><ram:BasisAmount>100.00</ram:BasisAmount><ram:CategoryCode>S</ram:CategoryCode><ram:RateApplicablePercent>19</ram:RateApplicablePercent></ram:ApplicableTradeTax>
+<ram:SpecifiedTradeSettlementHeaderMonetarySummation><ram:LineTotalAmount>100.00</ram:LineTotalAmount><ram:TaxBasisTotalAmount>100.00</ram:TaxBasisTotalAmount><ram:TaxTotalAmount currencyID="EUR">19.00</ram:TaxTotalAmount><ram:GrandTotalAmount>119.00</ram:GrandTotalAmount><ram:DuePayableAmount>119.00</ram:DuePayableAmount></ram:SpecifiedTradeSettlementHeaderMonetarySummation>
+</ram:ApplicableHeaderTradeSettlement>
+</rsm:SupplyChainTradeTransaction>
+</rsm:CrossIndustryInvoice>
+"#;
+
+
+/// A defect of a generated PDF. Each defect breaks exactly one step of the extraction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Defect {
+    /// The catalog has no /Metadata stream
+    MissingMetadata,
+    /// The /Metadata object cannot be parsed (a truncated dictionary)
+    UnparsableMetadata,
+    /// The /Metadata stream claims /FlateDecode, but is not compressed
+    CorruptMetadataStream,
+    /// The XMP metadata is not valid UTF-8 (a Latin-1 encoded title)
+    NonUtf8Metadata,
+    /// The XMP metadata has no rdf:Description of the Factur-X extension schema
+    MissingFacturXSchema,
+    /// The Factur-X rdf:Description has no fx:DocumentFileName
+    MissingDocumentFileName,
+    /// The fx:DocumentFileName is empty
+    EmptyDocumentFileName,
+    /// The fx:DocumentFileName names another file ("invoice.xml") than the attachment
+    WrongDocumentFileName,
+    /// The file specifications name the attachment with the extension .txt instead of .xml (fx:DocumentFileName is unchanged)
+    NonXmlAttachmentName,
+    /// The catalog has no /AF array, so the attachment is only listed in /EmbeddedFiles
+    MissingAssociatedFiles,
+    /// The catalog has no /Names dictionary, so the attachment is only listed in /AF
+    MissingEmbeddedFiles,
+    /// The /Names dictionary has no /EmbeddedFiles name tree
+    MissingEmbeddedFilesTree,
+    /// The /EmbeddedFiles name tree has a kid which cannot be parsed instead of the /Names array
+    UnparsableEmbeddedFilesTree,
+    /// The file specification has no /EF dictionary and the embedded file stream is left out
+    MissingEmbeddedFileStream,
+    /// The /EF dictionary has neither /F nor /UF (the embedded file stream is still in the file)
+    MissingEmbeddedFileReference,
+    /// The embedded file stream object cannot be parsed (a truncated dictionary)
+    UnparsableEmbeddedFileStream,
+    /// The embedded file stream claims /FlateDecode, but is not compressed
+    CorruptEmbeddedFileStream,
+    /// No file specification refers to the embedded file stream (neither /AF nor /EmbeddedFiles exist)
+    UnreferencedAttachment,
+}
+
+
+/// Generator for synthetic e-invoice PDFs: without defects the PDF is a conformant Factur-X/ZUGFeRD invoice with a single
+/// blank page, the XMP metadata and the attachment listed in /AF and /EmbeddedFiles.
+#[derive(Clone, Debug)]
+pub struct TestPdf {
+    /// Name of the attachment in the file specification and in fx:DocumentFileName
+    pub attachment_name: String,
+    /// Content of the embedded file stream
+    pub attachment: Vec<u8>,
+    pub defects: Vec<Defect>,
+    /// Compress the attachment with /FlateDecode
+    pub compress: bool,
+    /// Size of an uncompressed gray image covering the page, like the scan of a paper invoice (0 = blank page).
+    /// As written by many scanners, the image stream has an indirect /Length.
+    pub scan_size: usize,
+}
+
+impl Default for TestPdf {
+    fn default() -> Self {
+        TestPdf {
+            attachment_name: String::from("factur-x.xml"),
+            attachment: SAMPLE_INVOICE.as_bytes().to_vec(),
+            defects: Vec::new(),
+            compress: true,
+            scan_size: 0,
+        }
+    }
+}
+
+impl TestPdf {
+    /// The conformant PDF with a single defect
+    pub fn with_defect(defect: Defect) -> TestPdf {
+        TestPdf { defects: vec![defect], ..TestPdf::default() }
+    }
+
+    pub fn has(&self, defect: Defect) -> bool {
+        self.defects.contains(&defect)
+    }
+
+    /// Serializes the PDF
+    pub fn build(&self) -> Vec<u8> {
+        let mut pdf = PdfWriter::new();
+        let catalog_id = pdf.reserve();
+        let pages_id = pdf.reserve();
+
+        let page_id = match self.scan_size {
+            0 => pdf.add(format!("<< /Type /Page /Parent {} 0 R /MediaBox [0 0 595 842] >>", pages_id)),
+            _ => {
+                let image_id = self.add_scan(&mut pdf);
+                let content_id = pdf.add_stream("", b"q 595 0 0 842 0 0 cm /Scan Do Q", false);
+                pdf.add(format!("<< /Type /Page /Parent {} 0 R /MediaBox [0 0 595 842] /Resources << /XObject << /Scan {} 0 R >> >> /Contents {} 0 R >>", pages_id, image_id, content_id))
+            },
+        };
+        pdf.set(pages_id, format!("<< /Type /Pages /Kids [{} 0 R] /Count 1 >>", page_id).into_bytes());
+
+        let mut catalog = format!("<< /Type /Catalog /Pages {} 0 R", pages_id);
+        if !self.has(Defect::MissingMetadata) {
+            let metadata_id = if self.has(Defect::UnparsableMetadata) {
+                pdf.add(String::from("<< /Type /Metadata /Subtype /XML"))
+            } else if self.has(Defect::CorruptMetadataStream) {
+                pdf.add_stream("/Type /Metadata /Subtype /XML /Filter /FlateDecode", &self.metadata(), false)
+            } else {
+                pdf.add_stream("/Type /Metadata /Subtype /XML", &self.metadata(), false)
+            };
+            catalog.push_str(&format!(" /Metadata {} 0 R", metadata_id));
+        }
+
+        let mut embedded_file = String::new();
+        if !self.has(Defect::MissingEmbeddedFileStream) {
+            let dict_entries = format!("/Type /EmbeddedFile /Subtype /text#2Fxml /Params << /Size {} >>", self.attachment.len());
+            let stream_id = if self.has(Defect::UnparsableEmbeddedFileStream) {
+                pdf.add(format!("<< {}", dict_entries))
+            } else if self.has(Defect::CorruptEmbeddedFileStream) {
+                pdf.add_stream(&format!("{} /Filter /FlateDecode", dict_entries), &self.attachment, false)
+            } else {
+                pdf.add_stream(&dict_entries, &self.attachment, self.compress)
+            };
+            embedded_file = if self.has(Defect::MissingEmbeddedFileReference) {
+                String::from(" /EF << >>")
+            } else {
+                format!(" /EF << /F {} 0 R /UF {} 0 R >>", stream_id, stream_id)
+            };
+        }
+
+        if !self.has(Defect::UnreferencedAttachment) {
+            let file_spec_id = pdf.add(format!("<< /Type /Filespec /F {} /UF {} /AFRelationship /Alternative{} >>", self.name(), self.name(), embedded_file));
+            if !self.has(Defect::MissingAssociatedFiles) {
+                catalog.push_str(&format!(" /AF [{} 0 R]", file_spec_id));
+            }
+            if self.has(Defect::MissingEmbeddedFilesTree) {
+                catalog.push_str(" /Names << >>");
+            } else if self.has(Defect::UnparsableEmbeddedFilesTree) {
+                let kid_id = pdf.add(format!("<< /Names [{} {} 0 R", self.name(), file_spec_id));
+                catalog.push_str(&format!(" /Names << /EmbeddedFiles << /Kids [{} 0 R] >> >>", kid_id));
+            } else if !self.has(Defect::MissingEmbeddedFiles) {
+                catalog.push_str(&format!(" /Names << /EmbeddedFiles << /Names [{} {} 0 R] >> >>", self.name(), file_spec_id));
+            }
+        }
+
+        catalog.push_str(" >>");
+        pdf.set(catalog_id, catalog.into_bytes());
+        pdf.finish(catalog_id)
+    }
+
+    /// Adds the scanned page image and returns its id
+    fn add_scan(&self, pdf: &mut PdfWriter) -> u32 {
+        let width = 1000;
+        let height = self.scan_size.div_ceil(width);
+        let length_id = pdf.add((width * height).to_string());
+
+        let mut image = format!("<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 8 /Length {} 0 R >>\nstream\n", width, height, length_id).into_bytes();
+        image.resize(image.len() + width * height, 0xF0);
+        image.extend_from_slice(b"\nendstream");
+        let image_id = pdf.reserve();
+        pdf.set(image_id, image);
+        image_id
+    }
+
+    /// The attachment name as PDF string for the file specifications
+    fn name(&self) -> String {
+        let name = match self.attachment_name.strip_suffix(".xml") {
+            Some(stem) if self.has(Defect::NonXmlAttachmentName) => format!("{}.txt", stem),
+            _ => self.attachment_name.clone(),
+        };
+        writer::literal_string(name.as_bytes())
+    }
+
+    /// The XMP metadata with the Factur-X extension schema (as far as the defects allow)
+    fn metadata(&self) -> Vec<u8> {
+        let file_name = if self.has(Defect::MissingDocumentFileName) {
+            String::new()
+        } else if self.has(Defect::EmptyDocumentFileName) {
+            String::from("<fx:DocumentFileName></fx:DocumentFileName>\n")
+        } else if self.has(Defect::WrongDocumentFileName) {
+            String::from("<fx:DocumentFileName>invoice.xml</fx:DocumentFileName>\n")
+        } else {
+            format!("<fx:DocumentFileName>{}</fx:DocumentFileName>\n", self.attachment_name)
+        };
+
+        let factur_x = if self.has(Defect::MissingFacturXSchema) {
+            String::new()
+        } else {
+            format!("<rdf:Description rdf:about=\"\" xmlns:fx=\"urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#\">\n<fx:DocumentType>INVOICE</fx:DocumentType>\n{}<fx:Version>1.0</fx:Version>\n<fx:ConformanceLevel>EN 16931</fx:ConformanceLevel>\n</rdf:Description>\n", file_name)
+        };
+
+        let mut metadata = format!("<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n<rdf:Description rdf:about=\"\" xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n<pdfaid:part>3</pdfaid:part>\n<pdfaid:conformance>B</pdfaid:conformance>\n</rdf:Description>\n<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">Rechnung TEST-0001</rdf:li></rdf:Alt></dc:title>\n</rdf:Description>\n{}</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>", factur_x).into_bytes();
+        if self.has(Defect::NonUtf8Metadata) {
+            // "Rechnung" becomes "Rechnungsprüfung" with a Latin-1 encoded ü
+            let position = raw::find(&metadata, b"Rechnung", 0).unwrap_or(0) + b"Rechnung".len();
+            metadata.splice(position..position, b"spr\xFCfung".iter().copied());
+        }
+        metadata
+    }
+}
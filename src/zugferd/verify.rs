@@ -0,0 +1,425 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+use x509_cert::der::asn1::{ObjectIdentifier, OctetString};
+use x509_cert::der::{Decode, Encode, SliceReader};
+use x509_cert::ext::pkix::{BasicConstraints, KeyUsage, SubjectKeyIdentifier};
+use x509_cert::spki::SubjectPublicKeyInfoOwned;
+use x509_cert::time::Time;
+use x509_cert::Certificate;
+
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use super::signature::Signature;
+use super::{Error, PdfDate};
+
+
+const ID_SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+const ID_MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+const ID_SIGNING_TIME: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.5");
+const ID_SUBJECT_KEY_IDENTIFIER: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.14");
+const RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const ID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const SECP256R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const SECP384R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+
+/// Maximum number of certificates between the signer and a trust anchor
+const MAX_CHAIN_LENGTH: usize = 10;
+
+
+/// Certificates which are trusted as issuers of signer certificates (trust anchors)
+#[derive(Clone, Debug, Default)]
+pub struct TrustStore {
+    pub certificates: Vec<Certificate>,
+}
+
+impl TrustStore {
+    /// Loads all PEM encoded certificates from the .pem, .crt and .cer files of the given directory
+    pub fn load(directory: &Path) -> Result<TrustStore, Error> {
+        let entries = std::fs::read_dir(directory).map_err(|err| Error::from(100, format!("Failed to read trust store {}: {}", directory.display(), err)))?;
+        let mut certificates = Vec::new();
+
+        for entry in entries {
+            let path = entry.map_err(|err| Error::from(100, format!("Failed to read trust store {}: {}", directory.display(), err)))?.path();
+            let extension = path.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase);
+            if !matches!(extension.as_deref(), Some("pem" | "crt" | "cer")) {
+                continue;
+            }
+
+            let pem = std::fs::read(&path).map_err(|err| Error::from(100, format!("Failed to read {}: {}", path.display(), err)))?;
+            let chain = Certificate::load_pem_chain(&pem).map_err(|err| Error::from(101, format!("Failed to parse PEM certificate(s) in {}: {}", path.display(), err)))?;
+            certificates.extend(chain);
+        }
+
+        Ok(TrustStore { certificates })
+    }
+
+    /// Builds the chain from the signer certificate to one of our trust anchors using the certificates of the signature.
+    /// Each certificate of the chain must be valid at the given time (seconds since 1970-01-01T00:00:00Z) and each issuer
+    /// must be a CA which may sign certificates, so the key of a trusted end-entity certificate cannot issue trusted signers.
+    pub fn trust(&self, signer: &Certificate, certificates: &[Certificate], time: i64) -> Trust {
+        let mut current = signer;
+        for depth in 0..MAX_CHAIN_LENGTH {
+            if let Err(reason) = check_validity(current, time) {
+                return Trust::Untrusted(reason);
+            }
+            if self.certificates.contains(current) {
+                return Trust::Trusted(current.tbs_certificate.subject.to_string());
+            }
+
+            let issuer = self.certificates.iter().chain(certificates)
+                .find(|issuer| issuer.tbs_certificate.subject == current.tbs_certificate.issuer && verify_certificate(current, issuer));
+            match issuer {
+                Some(issuer) if issuer == current => return Trust::Untrusted(format!("Self-signed certificate {} is not in the trust store", current.tbs_certificate.subject)),
+                Some(issuer) => {
+                    // The issuer is followed by the certificates between it and the signer (depth - 1) and the signer itself
+                    if let Err(reason) = check_issuer(issuer, depth) {
+                        return Trust::Untrusted(reason);
+                    }
+                    current = issuer;
+                },
+                None => return Trust::Untrusted(format!("No trusted issuer found for {}", current.tbs_certificate.subject)),
+            }
+        }
+
+        Trust::Untrusted(format!("Certificate chain is longer than {} certificates", MAX_CHAIN_LENGTH))
+    }
+}
+
+
+/// Result of the cryptographic check of a signature
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Integrity {
+    /// The signed bytes are unchanged and the signature matches the signer certificate
+    Valid,
+    /// The signed bytes have been changed after signing
+    DigestMismatch,
+    /// The signature value doesn't match the signer certificate
+    InvalidSignature,
+    /// The signature could not be checked (unsupported format or algorithm, broken CMS object)
+    Unknown(String),
+}
+
+impl std::fmt::Display for Integrity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Integrity::Valid => write!(f, "valid"),
+            Integrity::DigestMismatch => write!(f, "invalid (the signed bytes have been modified)"),
+            Integrity::InvalidSignature => write!(f, "invalid (the signature doesn't match the signer certificate)"),
+            Integrity::Unknown(reason) => write!(f, "unknown ({})", reason),
+        }
+    }
+}
+
+/// Whether the signer certificate chains up to a certificate of the trust store
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Trust {
+    /// Trusted through the given trust anchor
+    Trusted(String),
+    /// Not trusted for the given reason
+    Untrusted(String),
+}
+
+impl std::fmt::Display for Trust {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trust::Trusted(anchor) => write!(f, "trusted (via {})", anchor),
+            Trust::Untrusted(reason) => write!(f, "not trusted ({})", reason),
+        }
+    }
+}
+
+
+/// Result of the offline verification of a signature
+#[derive(Clone, Debug)]
+pub struct SignatureVerification {
+    /// Subject of the signer certificate
+    pub signer: Option<String>,
+    /// Signing time from the signed attributes or as claimed by the signature dictionary
+    pub signing_time: Option<String>,
+    pub integrity: Integrity,
+    pub trust: Trust,
+    /// Whether the signature covers the whole file except for its own /Contents
+    pub covers_whole_file: bool,
+}
+
+impl SignatureVerification {
+    pub fn is_valid(&self) -> bool {
+        self.integrity == Integrity::Valid && matches!(self.trust, Trust::Trusted(_))
+    }
+}
+
+
+/// Verifies an adbe.pkcs7.detached or ETSI.CAdES.detached signature without any network access
+/// (revocation is not checked)
+pub fn verify_signature(data: &[u8], signature: &Signature, trust_store: &TrustStore) -> SignatureVerification {
+    let mut verification = SignatureVerification {
+        signer: None,
+        signing_time: signature.time.clone(),
+        integrity: Integrity::Unknown(String::from("Not checked")),
+        trust: Trust::Untrusted(String::from("Unknown signer")),
+        covers_whole_file: covers_whole_file(data, signature),
+    };
+
+    if !matches!(signature.sub_filter.as_deref(), Some("adbe.pkcs7.detached" | "ETSI.CAdES.detached")) {
+        verification.integrity = Integrity::Unknown(format!("Unsupported signature format {}", signature.sub_filter.as_deref().unwrap_or("(none)")));
+        return verification;
+    }
+
+    let signed_data = match parse_signed_data(&signature.contents) {
+        Ok(signed_data) => signed_data,
+        Err(reason) => {
+            verification.integrity = Integrity::Unknown(reason);
+            return verification;
+        }
+    };
+
+    let certificates: Vec<Certificate> = signed_data.certificates.iter().flat_map(|set| set.0.iter()).filter_map(|choice| match choice {
+        CertificateChoices::Certificate(certificate) => Some(certificate.clone()),
+        _ => None,
+    }).collect();
+
+    let Some(signer_info) = signed_data.signer_infos.0.iter().next() else {
+        verification.integrity = Integrity::Unknown(String::from("No signer info"));
+        return verification;
+    };
+    let Some(signer) = certificates.iter().find(|certificate| is_signer(certificate, &signer_info.sid)) else {
+        verification.integrity = Integrity::Unknown(String::from("Signer certificate is not included in the signature"));
+        return verification;
+    };
+
+    verification.signer = Some(signer.tbs_certificate.subject.to_string());
+    if let Some(time) = signing_time(signer_info) {
+        verification.signing_time = Some(time.to_string());
+    }
+    verification.integrity = check_integrity(data, signature, signer_info, signer);
+
+    // The chain is validated at the signing time (revocation is not checked, so it doesn't matter if it expired since)
+    let time = signing_time(signer_info).map(|time| time.to_unix_duration().as_secs() as i64)
+        .or_else(|| signature.time.as_deref().and_then(PdfDate::parse).map(|date| date.timestamp()))
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs() as i64));
+    verification.trust = trust_store.trust(signer, &certificates, time);
+    verification
+}
+
+
+/// The signature covers the whole file if the byte range starts at the beginning, only skips the /Contents hex string
+/// and ends at the end of the file (trailing whitespace is ignored)
+fn covers_whole_file(data: &[u8], signature: &Signature) -> bool {
+    let ranges = &signature.byte_range;
+    let end = signature.signed_end();
+    ranges.first().is_some_and(|(offset, _)| *offset == 0)
+        && ranges.windows(2).all(|pair| {
+            let gap = data.get(pair[0].0 + pair[0].1..pair[1].0).unwrap_or_default();
+            gap.first() == Some(&b'<') && gap.last() == Some(&b'>')
+        })
+        && data.get(end..).is_some_and(|rest| rest.iter().all(u8::is_ascii_whitespace))
+}
+
+/// Parses the CMS SignedData from the /Contents (ignoring the zero padding after the DER object)
+fn parse_signed_data(contents: &[u8]) -> Result<SignedData, String> {
+    let mut reader = SliceReader::new(contents).map_err(|err| format!("Invalid /Contents: {}", err))?;
+    let content_info = ContentInfo::decode(&mut reader).map_err(|err| format!("Invalid CMS object: {}", err))?;
+    if content_info.content_type != ID_SIGNED_DATA {
+        return Err(format!("Unexpected CMS content type {}", content_info.content_type));
+    }
+    content_info.content.decode_as::<SignedData>().map_err(|err| format!("Invalid CMS SignedData: {}", err))
+}
+
+fn is_signer(certificate: &Certificate, sid: &SignerIdentifier) -> bool {
+    match sid {
+        SignerIdentifier::IssuerAndSerialNumber(issuer_and_serial) => {
+            certificate.tbs_certificate.issuer == issuer_and_serial.issuer && certificate.tbs_certificate.serial_number == issuer_and_serial.serial_number
+        },
+        SignerIdentifier::SubjectKeyIdentifier(SubjectKeyIdentifier(key_id)) => {
+            certificate.tbs_certificate.extensions.iter().flatten()
+                .find(|extension| extension.extn_id == ID_SUBJECT_KEY_IDENTIFIER)
+                .and_then(|extension| OctetString::from_der(extension.extn_value.as_bytes()).ok())
+                .is_some_and(|value| value == *key_id)
+        },
+    }
+}
+
+fn signing_time(signer_info: &SignerInfo) -> Option<Time> {
+    let attribute = signer_info.signed_attrs.as_ref()?.iter().find(|attribute| attribute.oid == ID_SIGNING_TIME)?;
+    let value = attribute.values.iter().next()?;
+    Time::from_der(&value.to_der().ok()?).ok()
+}
+
+/// Compares the digest of the signed bytes with the message digest attribute and checks the signature value
+fn check_integrity(data: &[u8], signature: &Signature, signer_info: &SignerInfo, signer: &Certificate) -> Integrity {
+    let Some(algorithm) = DigestAlgorithm::from_oid(&signer_info.digest_alg.oid) else {
+        return Integrity::Unknown(format!("Unsupported digest algorithm {}", signer_info.digest_alg.oid));
+    };
+
+    let Some(parts) = signature.byte_range.iter().map(|(offset, length)| data.get(*offset..offset.saturating_add(*length))).collect::<Option<Vec<&[u8]>>>() else {
+        return Integrity::Unknown(String::from("The /ByteRange exceeds the end of the file"));
+    };
+    let content_digest = algorithm.digest(&parts);
+
+    // With signed attributes the signature is calculated over the attributes, which contain the digest of the content
+    let signed_digest = match &signer_info.signed_attrs {
+        Some(attributes) => {
+            let message_digest = attributes.iter()
+                .find(|attribute| attribute.oid == ID_MESSAGE_DIGEST)
+                .and_then(|attribute| attribute.values.iter().next())
+                .and_then(|value| value.decode_as::<OctetString>().ok());
+            match message_digest {
+                Some(message_digest) if message_digest.as_bytes() == content_digest.as_slice() => {},
+                Some(_) => return Integrity::DigestMismatch,
+                None => return Integrity::Unknown(String::from("Missing message digest attribute")),
+            }
+
+            match attributes.to_der() {
+                Ok(encoded) => algorithm.digest(&[&encoded]),
+                Err(err) => return Integrity::Unknown(format!("Failed to encode the signed attributes: {}", err)),
+            }
+        },
+        None => content_digest,
+    };
+
+    match verify_digest(&signer.tbs_certificate.subject_public_key_info, algorithm, &signed_digest, signer_info.signature.as_bytes()) {
+        Ok(true) => Integrity::Valid,
+        Ok(false) => Integrity::InvalidSignature,
+        Err(reason) => Integrity::Unknown(reason),
+    }
+}
+
+/// Fails if the certificate is not valid at the given time (seconds since 1970-01-01T00:00:00Z)
+fn check_validity(certificate: &Certificate, time: i64) -> Result<(), String> {
+    let validity = &certificate.tbs_certificate.validity;
+    if time < validity.not_before.to_unix_duration().as_secs() as i64 {
+        return Err(format!("Certificate {} is not valid before {}", certificate.tbs_certificate.subject, validity.not_before));
+    }
+    if time > validity.not_after.to_unix_duration().as_secs() as i64 {
+        return Err(format!("Certificate {} expired on {}", certificate.tbs_certificate.subject, validity.not_after));
+    }
+    Ok(())
+}
+
+/// Fails if the certificate may not issue certificates: it must be a CA (basicConstraints), may sign certificates
+/// (keyUsage, if present) and allow the given number of intermediate certificates below it (pathLenConstraint)
+fn check_issuer(issuer: &Certificate, intermediates: usize) -> Result<(), String> {
+    let subject = &issuer.tbs_certificate.subject;
+    match issuer.tbs_certificate.get::<BasicConstraints>() {
+        Ok(Some((_, constraints))) if constraints.ca => {
+            if constraints.path_len_constraint.is_some_and(|path_length| usize::from(path_length) < intermediates) {
+                return Err(format!("Certificate {} may only issue {} levels of intermediate certificates", subject, constraints.path_len_constraint.unwrap_or_default()));
+            }
+        },
+        Ok(_) => return Err(format!("Certificate {} is no CA certificate, but issued another certificate", subject)),
+        Err(err) => return Err(format!("Certificate {} has invalid basic constraints: {}", subject, err)),
+    }
+
+    match issuer.tbs_certificate.get::<KeyUsage>() {
+        Ok(Some((_, key_usage))) if !key_usage.key_cert_sign() => Err(format!("Certificate {} must not sign certificates (no keyCertSign key usage)", subject)),
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("Certificate {} has an invalid key usage: {}", subject, err)),
+    }
+}
+
+/// Checks the signature of the certificate with the public key of the issuer
+fn verify_certificate(certificate: &Certificate, issuer: &Certificate) -> bool {
+    let (Some(algorithm), Ok(tbs), Some(signature)) = (
+        DigestAlgorithm::from_signature_oid(&certificate.signature_algorithm.oid),
+        certificate.tbs_certificate.to_der(),
+        certificate.signature.as_bytes(),
+    ) else {
+        return false;
+    };
+
+    verify_digest(&issuer.tbs_certificate.subject_public_key_info, algorithm, &algorithm.digest(&[&tbs]), signature) == Ok(true)
+}
+
+/// Verifies the signature of the digest with the given public key (RSA PKCS#1 v1.5 or ECDSA P-256/P-384)
+fn verify_digest(key: &SubjectPublicKeyInfoOwned, algorithm: DigestAlgorithm, digest: &[u8], signature: &[u8]) -> Result<bool, String> {
+    if key.algorithm.oid == RSA_ENCRYPTION {
+        let encoded = key.to_der().map_err(|err| format!("Invalid public key: {}", err))?;
+        let public_key = RsaPublicKey::from_public_key_der(&encoded).map_err(|err| format!("Invalid RSA public key: {}", err))?;
+        return Ok(public_key.verify(algorithm.pkcs1v15(), digest, signature).is_ok());
+    }
+
+    if key.algorithm.oid == ID_EC_PUBLIC_KEY {
+        let curve = key.algorithm.parameters.as_ref().and_then(|parameters| parameters.decode_as::<ObjectIdentifier>().ok());
+        let point = key.subject_public_key.raw_bytes();
+        return match curve {
+            Some(curve) if curve == SECP256R1 => {
+                let public_key = p256::ecdsa::VerifyingKey::from_sec1_bytes(point).map_err(|err| format!("Invalid P-256 public key: {}", err))?;
+                Ok(p256::ecdsa::Signature::from_der(signature).is_ok_and(|signature| public_key.verify_prehash(digest, &signature).is_ok()))
+            },
+            Some(curve) if curve == SECP384R1 => {
+                let public_key = p384::ecdsa::VerifyingKey::from_sec1_bytes(point).map_err(|err| format!("Invalid P-384 public key: {}", err))?;
+                Ok(p384::ecdsa::Signature::from_der(signature).is_ok_and(|signature| public_key.verify_prehash(digest, &signature).is_ok()))
+            },
+            _ => Err(String::from("Unsupported elliptic curve")),
+        };
+    }
+
+    Err(format!("Unsupported public key algorithm {}", key.algorithm.oid))
+}
+
+
+#[derive(Clone, Copy, Debug)]
+enum DigestAlgorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    /// Digest algorithm of the CMS signer info
+    fn from_oid(oid: &ObjectIdentifier) -> Option<DigestAlgorithm> {
+        match oid.to_string().as_str() {
+            "1.3.14.3.2.26" => Some(DigestAlgorithm::Sha1),
+            "2.16.840.1.101.3.4.2.1" => Some(DigestAlgorithm::Sha256),
+            "2.16.840.1.101.3.4.2.2" => Some(DigestAlgorithm::Sha384),
+            "2.16.840.1.101.3.4.2.3" => Some(DigestAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Digest algorithm of a certificate signature algorithm like sha256WithRSAEncryption
+    fn from_signature_oid(oid: &ObjectIdentifier) -> Option<DigestAlgorithm> {
+        match oid.to_string().as_str() {
+            "1.2.840.113549.1.1.5" | "1.2.840.10045.4.1" => Some(DigestAlgorithm::Sha1),
+            "1.2.840.113549.1.1.11" | "1.2.840.10045.4.3.2" => Some(DigestAlgorithm::Sha256),
+            "1.2.840.113549.1.1.12" | "1.2.840.10045.4.3.3" => Some(DigestAlgorithm::Sha384),
+            "1.2.840.113549.1.1.13" | "1.2.840.10045.4.3.4" => Some(DigestAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn digest(&self, parts: &[&[u8]]) -> Vec<u8> {
+        fn hash<D: Digest>(parts: &[&[u8]]) -> Vec<u8> {
+            let mut hasher = D::new();
+            for part in parts {
+                hasher.update(part);
+            }
+            hasher.finalize().to_vec()
+        }
+
+        match self {
+            DigestAlgorithm::Sha1 => hash::<Sha1>(parts),
+            DigestAlgorithm::Sha256 => hash::<Sha256>(parts),
+            DigestAlgorithm::Sha384 => hash::<Sha384>(parts),
+            DigestAlgorithm::Sha512 => hash::<Sha512>(parts),
+        }
+    }
+
+    fn pkcs1v15(&self) -> Pkcs1v15Sign {
+        match self {
+            DigestAlgorithm::Sha1 => Pkcs1v15Sign::new::<Sha1>(),
+            DigestAlgorithm::Sha256 => Pkcs1v15Sign::new::<Sha256>(),
+            DigestAlgorithm::Sha384 => Pkcs1v15Sign::new::<Sha384>(),
+            DigestAlgorithm::Sha512 => Pkcs1v15Sign::new::<Sha512>(),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::{write::ZlibEncoder, Compression};
+
+
+/// Minimal serializer for new PDF files. Objects are passed in as already formatted PDF syntax and
+/// are written out in id order together with a classic xref table and trailer.
+pub struct PdfWriter {
+    objects: Vec<Option<Vec<u8>>>,
+}
+
+
+impl PdfWriter {
+    pub fn new() -> PdfWriter {
+        PdfWriter { objects: Vec::new() }
+    }
+
+    /// Reserves an object id, which is needed to reference objects before their content is known
+    pub fn reserve(&mut self) -> u32 {
+        self.objects.push(None);
+        self.objects.len() as u32
+    }
+
+    /// Sets the content of a previously reserved object
+    pub fn set(&mut self, id: u32, content: Vec<u8>) {
+        self.objects[id as usize - 1] = Some(content);
+    }
+
+    /// Adds a new object and returns its id
+    pub fn add(&mut self, content: String) -> u32 {
+        let id = self.reserve();
+        self.set(id, content.into_bytes());
+        id
+    }
+
+    /// Adds a stream object with the given additional dictionary entries. The /Length (and /Filter when compressing)
+    /// entries are added automatically.
+    pub fn add_stream(&mut self, dict_entries: &str, data: &[u8], compress: bool) -> u32 {
+        let id = self.reserve();
+        self.set_stream(id, dict_entries, data, compress);
+        id
+    }
+
+    /// Sets the content of a previously reserved object to a stream
+    pub fn set_stream(&mut self, id: u32, dict_entries: &str, data: &[u8], compress: bool) {
+        let (data, filter) = if compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            // Writing into a Vec cannot fail
+            encoder.write_all(data).and_then(|_| encoder.finish()).map_or((data.to_vec(), ""), |compressed| (compressed, " /Filter /FlateDecode"))
+        } else {
+            (data.to_vec(), "")
+        };
+
+        let mut content = format!("<< {} /Length {}{} >>\nstream\n", dict_entries, data.len(), filter).into_bytes();
+        content.extend_from_slice(&data);
+        content.extend_from_slice(b"\nendstream");
+        self.set(id, content);
+    }
+
+    /// Serializes all objects into a complete PDF file with the given catalog object as /Root
+    pub fn finish(self, root: u32) -> Vec<u8> {
+        // The binary comment marks the file as binary for transfer programs (required by PDF/A)
+        let mut output = b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(self.objects.len());
+
+        for (index, object) in self.objects.iter().enumerate() {
+            offsets.push(output.len());
+            output.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+            output.extend_from_slice(object.as_deref().unwrap_or(b"null"));
+            output.extend_from_slice(b"\nendobj\n");
+        }
+
+        let file_id = hex_string(&file_id(&output));
+        let xref_offset = output.len();
+        output.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f\r\n", self.objects.len() + 1).as_bytes());
+        for offset in offsets {
+            output.extend_from_slice(format!("{:010} 00000 n\r\n", offset).as_bytes());
+        }
+
+        output.extend_from_slice(format!("trailer\n<< /Size {} /Root {} 0 R /ID [{} {}] >>\nstartxref\n{}\n%%EOF\n",
+            self.objects.len() + 1, root, file_id, file_id, xref_offset).as_bytes());
+        output
+    }
+}
+
+
+/// Formats the bytes as PDF literal string, escaping the special characters
+pub fn literal_string(bytes: &[u8]) -> String {
+    let mut result = String::from("(");
+    for byte in bytes {
+        match byte {
+            b'(' | b')' | b'\\' => { result.push('\\'); result.push(*byte as char); },
+            b'\r' => result.push_str("\\r"),
+            b'\n' => result.push_str("\\n"),
+            0x20..=0x7E => result.push(*byte as char),
+            _ => result.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    result.push(')');
+    result
+}
+
+/// Formats the bytes as PDF hex string
+pub fn hex_string(bytes: &[u8]) -> String {
+    format!("<{}>", bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<String>())
+}
+
+/// Formats the text as PDF text string (UTF-16BE with BOM, so any character can be represented)
+pub fn text_string(text: &str) -> String {
+    if text.is_ascii() {
+        return literal_string(text.as_bytes());
+    }
+
+    let bytes: Vec<u8> = [0xFEFFu16].iter().copied().chain(text.encode_utf16()).flat_map(|unit| unit.to_be_bytes()).collect();
+    hex_string(&bytes)
+}
+
+/// Formats the name as PDF name object, escaping all characters outside the regular character set
+pub fn name(name: &str) -> String {
+    let mut result = String::from("/");
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.+".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            result.push_str(&format!("#{:02X}", byte));
+        }
+    }
+    result
+}
+
+
+/// Returns the given time as UTC PDF date string (D:YYYYMMDDHHmmSS+00'00') and as ISO 8601 string for XMP
+pub fn format_dates(time: SystemTime) -> (String, String) {
+    let [year, month, day, hour, minute, second] = utc_components(time);
+
+    (
+        format!("D:{:04}{:02}{:02}{:02}{:02}{:02}+00'00'", year, month, day, hour, minute, second),
+        format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second),
+    )
+}
+
+/// Splits the given time into its UTC components [year, month, day, hour, minute, second]
+pub fn utc_components(time: SystemTime) -> [i64; 6] {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs() as i64);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let second_of_day = seconds.rem_euclid(86400);
+    [year, month, day, second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60]
+}
+
+/// Converts days since 1970-01-01 into (year, month, day) of the proleptic gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // Algorithm from Howard Hinnant's "chrono-Compatible Low-Level Date Algorithms"
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Derives a file identifier from the file content (only needs to be unique, not cryptographically secure)
+fn file_id(content: &[u8]) -> [u8; 16] {
+    use std::hash::{Hash, Hasher};
+
+    let mut id = [0u8; 16];
+    for (index, chunk) in id.chunks_mut(8).enumerate() {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        index.hash(&mut hasher);
+        content.hash(&mut hasher);
+        chunk.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+    id
+}
@@ -0,0 +1,492 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::rc::Rc;
+use std::time::Instant;
+
+use super::crypt::SecurityHandler;
+use super::filter;
+use super::limits::Limits;
+use super::recovery;
+use super::raw::{self, RawParser, RawDictionary, RawObject};
+use super::Error;
+
+
+/// A single cross-reference entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XRefEntry {
+    Free { next: u64, gen: u16 },
+    /// Object stored directly in the file at the given byte offset
+    InFile { offset: usize, gen: u16 },
+    /// Object stored compressed inside an object stream at the given index
+    InStream { stream_id: u64, index: u32 },
+}
+
+impl XRefEntry {
+    pub fn gen(&self) -> u16 {
+        match self {
+            XRefEntry::Free { gen, .. } | XRefEntry::InFile { gen, .. } => *gen,
+            XRefEntry::InStream { .. } => 0,
+        }
+    }
+}
+
+
+/// Whether a section is a classic xref table, an xref stream (with the object id of the stream) or recovered by scanning
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectionKind {
+    Table,
+    Stream(u64),
+    /// Rebuilt by scanning the whole file for objects, as the cross-reference sections of the file are broken (offset 0)
+    Recovered,
+}
+
+/// One cross-reference section (xref table with trailer or xref stream) of the file
+#[derive(Clone, Debug)]
+pub struct XRefSection {
+    /// Byte offset of the section as referenced by startxref or /Prev
+    pub offset: usize,
+    pub kind: SectionKind,
+    pub trailer: RawDictionary,
+    pub entries: Vec<(u64, XRefEntry)>,
+}
+
+
+/// The chain of cross-reference sections of a file, newest section first
+#[derive(Clone, Debug)]
+pub struct XRef {
+    pub sections: Vec<XRefSection>,
+}
+
+impl XRef {
+    /// Parses all sections starting at the last startxref of the file
+    pub fn parse(data: &[u8]) -> Result<XRef, Error> {
+        Self::parse_from(data, find_startxref(data)?)
+    }
+
+    /// Parses all sections starting with the section at the given offset and following the /Prev entries
+    pub fn parse_from(data: &[u8], offset: usize) -> Result<XRef, Error> {
+        let mut sections = Vec::new();
+        let mut visited = HashSet::new();
+        let mut next = Some(offset);
+
+        while let Some(offset) = next.filter(|offset| visited.insert(*offset)) {
+            let section = parse_section(data, offset)?;
+            next = section.trailer.get("Prev").and_then(RawObject::as_integer).map(|prev| prev as usize);
+            sections.push(section);
+        }
+
+        Ok(XRef { sections })
+    }
+
+    /// Returns the effective entry of every object id (newer sections override older ones)
+    /// together with the index of the section which defines it
+    pub fn entries(&self) -> BTreeMap<u64, (XRefEntry, usize)> {
+        let mut entries = BTreeMap::new();
+        for (index, section) in self.sections.iter().enumerate().rev() {
+            for (id, entry) in &section.entries {
+                entries.insert(*id, (*entry, index));
+            }
+        }
+        entries
+    }
+
+    /// The trailer of the newest section
+    pub fn trailer(&self) -> Option<&RawDictionary> {
+        self.sections.first().map(|section| &section.trailer)
+    }
+}
+
+
+/// Returns the offset of the last startxref keyword of the file
+pub fn find_startxref(data: &[u8]) -> Result<usize, Error> {
+    let position = raw::rfind(data, b"startxref").ok_or(Error::from(93, String::from("No startxref found")))?;
+    let mut parser = RawParser::new(data, position + b"startxref".len());
+    parser.parse_unsigned().map(|offset| offset as usize).ok_or(Error::from(93, String::from("Invalid startxref offset")))
+}
+
+/// Parses the xref table or xref stream at the given offset. Hybrid files (/XRefStm in the trailer)
+/// get the entries of the referenced xref stream merged into the section.
+pub fn parse_section(data: &[u8], offset: usize) -> Result<XRefSection, Error> {
+    let mut parser = RawParser::new(data, offset);
+    if parser.consume_keyword(b"xref") {
+        let mut section = parse_table(&mut parser, offset)?;
+        if let Some(stream_offset) = section.trailer.get("XRefStm").and_then(RawObject::as_integer) {
+            let stream = parse_stream(data, stream_offset as usize)?;
+            let table_ids: HashSet<u64> = section.entries.iter().filter(|(_, entry)| !matches!(entry, XRefEntry::Free { .. })).map(|(id, _)| *id).collect();
+            section.entries.extend(stream.entries.into_iter().filter(|(id, _)| !table_ids.contains(id)));
+        }
+        Ok(section)
+    } else {
+        parse_stream(data, offset)
+    }
+}
+
+fn parse_table(parser: &mut RawParser, offset: usize) -> Result<XRefSection, Error> {
+    let mut entries = Vec::new();
+    loop {
+        if parser.consume_keyword(b"trailer") {
+            let trailer = match parser.parse_object()? {
+                RawObject::Dictionary(dict) => dict,
+                _ => return Err(Error::from(94, format!("Trailer of xref table at offset {} is not a dictionary", offset))),
+            };
+            return Ok(XRefSection { offset, kind: SectionKind::Table, trailer, entries });
+        }
+
+        let (start, count) = parser.parse_unsigned().zip(parser.parse_unsigned())
+            .ok_or(Error::from(94, format!("Invalid subsection header in xref table at offset {}", offset)))?;
+
+        let end = start.checked_add(count).ok_or(Error::from(94, format!("Invalid subsection header in xref table at offset {}", offset)))?;
+        for id in start..end {
+            let (field1, field2) = parser.parse_unsigned().zip(parser.parse_unsigned())
+                .ok_or(Error::from(94, format!("Invalid entry for object {} in xref table at offset {}", id, offset)))?;
+            let entry = if parser.consume_keyword(b"n") {
+                XRefEntry::InFile { offset: field1 as usize, gen: field2 as u16 }
+            } else if parser.consume_keyword(b"f") {
+                XRefEntry::Free { next: field1, gen: field2 as u16 }
+            } else {
+                return Err(Error::from(94, format!("Invalid entry type for object {} in xref table at offset {}", id, offset)));
+            };
+            entries.push((id, entry));
+        }
+    }
+}
+
+fn parse_stream(data: &[u8], offset: usize) -> Result<XRefSection, Error> {
+    let (id, _gen, object) = RawParser::new(data, offset).parse_indirect_object()?;
+    let (dict, range) = match object {
+        RawObject::Stream(dict, range) if dict.type_name() == Some("XRef") => (dict, range),
+        _ => return Err(Error::from(95, format!("Object {} at offset {} is neither an xref table nor an xref stream", id, offset))),
+    };
+
+    let decoded = filter::decode(&data[range], &dict, &Limits::default())?;
+    let widths: Vec<usize> = dict.get("W").and_then(RawObject::as_array).map(|widths| widths.iter().filter_map(RawObject::as_integer).map(|width| width as usize).collect()).unwrap_or_default();
+    if widths.len() != 3 || widths.iter().any(|width| *width > 8) {
+        return Err(Error::from(95, format!("Invalid /W array in xref stream {}", id)));
+    }
+
+    let size = dict.get("Size").and_then(RawObject::as_integer).unwrap_or(0) as u64;
+    let index: Vec<u64> = match dict.get("Index").and_then(RawObject::as_array) {
+        Some(index) => index.iter().filter_map(RawObject::as_integer).map(|value| value as u64).collect(),
+        None => vec![0, size],
+    };
+
+    let entry_length: usize = widths.iter().sum();
+    let mut rows = decoded.chunks_exact(entry_length.max(1));
+    let mut entries = Vec::new();
+
+    for subsection in index.chunks_exact(2) {
+        let end = subsection[0].checked_add(subsection[1]).ok_or(Error::from(95, format!("Invalid /Index array in xref stream {}", id)))?;
+        for id in subsection[0]..end {
+            let Some(row) = rows.next() else { break; };
+            let (type_field, rest) = row.split_at(widths[0]);
+            let (field2, field3) = rest.split_at(widths[1]);
+            // A missing type field defaults to type 1
+            let entry_type = if widths[0] == 0 { 1 } else { read_be(type_field) };
+
+            let entry = match entry_type {
+                0 => XRefEntry::Free { next: read_be(field2), gen: read_be(field3) as u16 },
+                1 => XRefEntry::InFile { offset: read_be(field2) as usize, gen: read_be(field3) as u16 },
+                2 => XRefEntry::InStream { stream_id: read_be(field2), index: read_be(field3) as u32 },
+                // Unknown types must be treated as null references
+                _ => continue,
+            };
+            entries.push((id, entry));
+        }
+    }
+
+    Ok(XRefSection { offset, kind: SectionKind::Stream(id), trailer: dict, entries })
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+
+/// Resolves objects directly from the file bytes through the cross-reference sections
+/// (including objects compressed in object streams). Strings and streams of encrypted files are decrypted.
+pub struct RawFile<'a> {
+    pub data: &'a [u8],
+    pub xref: XRef,
+    entries: BTreeMap<u64, (XRefEntry, usize)>,
+    object_streams: RefCell<HashMap<u64, Rc<Vec<RawObject>>>>,
+    /// Object streams being parsed right now, to detect streams whose /Filter or /DecodeParms refer to objects inside themselves
+    loading_object_streams: RefCell<HashSet<u64>>,
+    security_handler: Option<SecurityHandler>,
+    /// Id of the encryption dictionary, which is never encrypted itself
+    encrypt_id: Option<u64>,
+    /// Id and generation of the object each resolved stream (by the start of its data) belongs to, as the key to decrypt a stream depends on them
+    stream_owners: RefCell<HashMap<usize, (u64, u16)>>,
+    limits: Limits,
+    /// When the file has been opened (for the maximum processing time)
+    opened: Instant,
+}
+
+impl<'a> RawFile<'a> {
+    pub fn new(data: &'a [u8], xref: XRef) -> RawFile<'a> {
+        let entries = xref.entries();
+        RawFile { data, xref, entries, object_streams: RefCell::new(HashMap::new()), loading_object_streams: RefCell::new(HashSet::new()), security_handler: None, encrypt_id: None, stream_owners: RefCell::new(HashMap::new()), limits: Limits::default(), opened: Instant::now() }
+    }
+
+    /// Opens the file and tries to decrypt it with the empty user password, which most encrypted files use
+    /// as they only restrict the permissions. If that fails strings and streams stay encrypted.
+    pub fn open(data: &'a [u8]) -> Result<RawFile<'a>, Error> {
+        let mut file = Self::new(data, XRef::parse(data)?);
+        let _ = file.set_password(b"");
+        Ok(file)
+    }
+
+    /// Opens a file with broken cross-reference sections by scanning it for objects (see recover_xref)
+    /// and tries to decrypt it with the empty user password like open(). The given limits apply to the scan and to the file.
+    pub fn recover(data: &'a [u8], limits: Limits) -> Result<RawFile<'a>, Error> {
+        let mut file = Self::new(data, recovery::recover_xref(data, &limits)?);
+        file.limits = limits;
+        let _ = file.set_password(b"");
+        Ok(file)
+    }
+
+    /// Opens the file and decrypts it with the given user or owner password (which is ignored for unencrypted files)
+    pub fn open_with_password(data: &'a [u8], password: &[u8]) -> Result<RawFile<'a>, Error> {
+        let mut file = Self::new(data, XRef::parse(data)?);
+        file.set_password(password)?;
+        Ok(file)
+    }
+
+    /// Sets up the decryption with the given user or owner password if the file is encrypted
+    pub fn set_password(&mut self, password: &[u8]) -> Result<(), Error> {
+        let Some(encrypt) = self.xref.trailer().and_then(|trailer| trailer.get("Encrypt")).cloned() else {
+            return Ok(());
+        };
+
+        self.security_handler = None;
+        self.object_streams.borrow_mut().clear();
+        self.encrypt_id = encrypt.as_reference().map(|(id, _)| id);
+
+        let encrypt = self.resolve_if_ref(&encrypt)?;
+        let encrypt = encrypt.as_dictionary().ok_or(Error::from(110, String::from("/Encrypt is not a dictionary")))?;
+        let id = match self.xref.trailer().and_then(|trailer| trailer.get("ID")).and_then(RawObject::as_array).and_then(|id| id.first()) {
+            Some(RawObject::String(id)) => id.clone(),
+            _ => Vec::new(),
+        };
+
+        self.security_handler = Some(SecurityHandler::new(encrypt, &id, password)?);
+        Ok(())
+    }
+
+    /// Applies the given resource limits to everything resolved or decoded from now on (the processing time counts from opening the file)
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.object_streams.borrow_mut().clear();
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// The security handler of encrypted files (None if the file is not encrypted or the password was wrong)
+    pub fn security_handler(&self) -> Option<&SecurityHandler> {
+        self.security_handler.as_ref()
+    }
+
+    /// Whether the file has an /Encrypt dictionary
+    pub fn is_encrypted(&self) -> bool {
+        self.xref.trailer().is_some_and(|trailer| trailer.get("Encrypt").is_some())
+    }
+
+    /// The effective entries of all objects (id -> entry and index of the defining section)
+    pub fn entries(&self) -> &BTreeMap<u64, (XRefEntry, usize)> {
+        &self.entries
+    }
+
+    /// Resolves the document catalog (the /Root of the trailer, Null if it is missing)
+    pub fn catalog(&self) -> Result<RawObject, Error> {
+        match self.xref.trailer().and_then(|trailer| trailer.get("Root")) {
+            Some(root) => self.resolve_if_ref(root),
+            None => Ok(RawObject::Null),
+        }
+    }
+
+    /// Resolves the object with the given id
+    pub fn resolve(&self, id: u64) -> Result<RawObject, Error> {
+        self.resolve_entry(id, None)
+    }
+
+    /// Resolves the object with the given id and generation. Fails if the cross-reference entry or the object itself
+    /// has another generation, so that a reference like `12 1 R` never resolves to `12 0 obj`.
+    pub fn resolve_ref(&self, id: u64, gen: u16) -> Result<RawObject, Error> {
+        self.resolve_entry(id, Some(gen))
+    }
+
+    fn resolve_entry(&self, id: u64, gen: Option<u16>) -> Result<RawObject, Error> {
+        if self.limits.max_time.is_some_and(|max_time| self.opened.elapsed() > max_time) {
+            return Err(self.limits.time_error());
+        }
+
+        let entry = self.entries.get(&id).map(|(entry, _)| *entry);
+        if let (Some(entry @ (XRefEntry::InFile { .. } | XRefEntry::InStream { .. })), Some(gen)) = (entry, gen) {
+            if entry.gen() != gen {
+                return Err(Error::from(96, format!("Object {} {} R not found, the cross-reference entry has generation {}", id, gen, entry.gen())));
+            }
+        }
+
+        match entry {
+            Some(XRefEntry::InFile { offset, .. }) => self.object_at(offset, id, gen),
+            Some(XRefEntry::InStream { stream_id, index }) => {
+                let objects = self.object_stream(stream_id)?;
+                objects.get(index as usize).cloned().ok_or(Error::from(96, format!("Object stream {} has no object at index {}", stream_id, index)))
+            },
+            Some(XRefEntry::Free { .. }) | None => Ok(RawObject::Null),
+        }
+    }
+
+    /// Resolves the object if it is a reference, otherwise returns a copy of it
+    pub fn resolve_if_ref(&self, object: &RawObject) -> Result<RawObject, Error> {
+        match object {
+            RawObject::Reference(id, _) => self.resolve(*id),
+            _ => Ok(object.clone()),
+        }
+    }
+
+    /// Returns the decoded data of a stream object
+    pub fn stream_data(&self, object: &RawObject) -> Result<Vec<u8>, Error> {
+        filter::decode_chain(&self.decrypted_stream_data(object)?, &self.filter_chain(object)?, &self.limits)
+    }
+
+    /// Returns the still encoded data of a stream object, but decrypted if the file is encrypted
+    pub fn decrypted_stream_data(&self, object: &RawObject) -> Result<Cow<'a, [u8]>, Error> {
+        let data = self.raw_stream_data(object)?;
+        let (Some(handler), RawObject::Stream(dict, range)) = (self.security_handler.as_ref(), object) else {
+            return Ok(Cow::Borrowed(data));
+        };
+        let Some((id, gen)) = self.stream_owners.borrow().get(&range.start).copied() else {
+            return Ok(Cow::Borrowed(data));
+        };
+
+        let method = match dict.type_name() {
+            Some("XRef") => return Ok(Cow::Borrowed(data)),
+            Some("Metadata") if !handler.encrypt_metadata => return Ok(Cow::Borrowed(data)),
+            Some("EmbeddedFile") => handler.file_method,
+            _ => handler.stream_method,
+        };
+        handler.decrypt(method, id, gen, data).map(Cow::Owned)
+    }
+
+    /// Returns the still encoded data of a stream object as stored in the file
+    pub fn raw_stream_data(&self, object: &RawObject) -> Result<&'a [u8], Error> {
+        match object {
+            RawObject::Stream(_, range) => self.data.get(range.clone()).ok_or(Error::from(96, String::from("Stream data exceeds the end of the file"))),
+            _ => Err(Error::from(96, String::from("Object is not a stream"))),
+        }
+    }
+
+    /// Returns the /Filter chain of a stream object with the matching /DecodeParms (indirect entries are resolved)
+    pub fn filter_chain(&self, object: &RawObject) -> Result<Vec<(String, Option<RawDictionary>)>, Error> {
+        let dict = object.as_dictionary().ok_or(Error::from(96, String::from("Object is not a stream")))?;
+        let mut resolved = dict.clone();
+        for key in ["Filter", "DecodeParms", "DP"] {
+            let value = match dict.get(key) {
+                Some(RawObject::Array(array)) => RawObject::Array(array.iter().map(|element| self.resolve_if_ref(element)).collect::<Result<_, _>>()?),
+                Some(value) => self.resolve_if_ref(value)?,
+                None => continue,
+            };
+            resolved.insert(String::from(key), value);
+        }
+        Ok(filter::filter_chain(&resolved))
+    }
+
+    /// Parses the object at the given offset, which must have the given id (and generation, if given)
+    fn object_at(&self, offset: usize, id: u64, expected_gen: Option<u16>) -> Result<RawObject, Error> {
+        let (found_id, gen, mut object) = RawParser::new(self.data, offset).with_limits(self.limits).parse_indirect_object_with(|length_id| self.direct_integer(length_id))?;
+        if found_id != id {
+            return Err(Error::from(96, format!("Expected object {} at offset {} but found object {}", id, offset, found_id)));
+        }
+        if let Some(expected_gen) = expected_gen.filter(|expected_gen| *expected_gen != gen) {
+            return Err(Error::from(96, format!("Expected object {} {} at offset {} but found generation {}", id, expected_gen, offset, gen)));
+        }
+
+        if let Some(handler) = self.security_handler.as_ref().filter(|_| self.encrypt_id != Some(id)) {
+            if let RawObject::Stream(_, range) = &object {
+                self.stream_owners.borrow_mut().insert(range.start, (id, gen));
+            }
+            decrypt_strings(handler, &mut object, id, gen);
+        }
+        Ok(object)
+    }
+
+    /// Reads an integer stored directly in the file, like the indirect /Length of a stream. Object streams are not
+    /// looked at, so this can be used while parsing any object (including object streams) without recursion.
+    fn direct_integer(&self, id: u64) -> Option<i64> {
+        let Some((XRefEntry::InFile { offset, .. }, _)) = self.entries.get(&id) else { return None; };
+        match RawParser::new(self.data, *offset).parse_indirect_object() {
+            Ok((found_id, _, object)) if found_id == id => object.as_integer(),
+            _ => None,
+        }
+    }
+
+    /// Parses all objects of the object stream with the given id (cached)
+    fn object_stream(&self, stream_id: u64) -> Result<Rc<Vec<RawObject>>, Error> {
+        if let Some(objects) = self.object_streams.borrow().get(&stream_id) {
+            return Ok(objects.clone());
+        }
+
+        // Resolving the filters of an object stream may need objects of other object streams, but never of itself
+        let offset = match self.entries.get(&stream_id).map(|(entry, _)| *entry) {
+            Some(XRefEntry::InFile { offset, .. }) => offset,
+            _ => return Err(Error::from(96, format!("Object stream {} is not stored directly in the file", stream_id))),
+        };
+        if self.loading_object_streams.borrow().len() >= self.limits.max_nesting_depth {
+            return Err(self.limits.nesting_error(offset));
+        }
+        if !self.loading_object_streams.borrow_mut().insert(stream_id) {
+            return Err(Error::from(96, format!("Object stream {} needs an object inside itself to be decoded", stream_id)));
+        }
+        let objects = self.parse_object_stream(stream_id, offset);
+        self.loading_object_streams.borrow_mut().remove(&stream_id);
+
+        let objects = Rc::new(objects?);
+        self.object_streams.borrow_mut().insert(stream_id, objects.clone());
+        Ok(objects)
+    }
+
+    fn parse_object_stream(&self, stream_id: u64, offset: usize) -> Result<Vec<RawObject>, Error> {
+        let stream = self.object_at(offset, stream_id, None)?;
+        let dict = stream.as_dictionary().cloned().unwrap_or_default();
+        let data = self.stream_data(&stream)?;
+        let count = dict.get("N").and_then(RawObject::as_integer).unwrap_or(0).max(0) as usize;
+        let first = dict.get("First").and_then(RawObject::as_integer).unwrap_or(0).max(0) as usize;
+
+        // The header contains pairs of object id and offset relative to /First
+        let mut header = RawParser::new(&data, 0);
+        let mut objects = Vec::with_capacity(count.min(data.len()));
+        for _ in 0..count {
+            let Some((_id, offset)) = header.parse_unsigned().zip(header.parse_unsigned()) else { break; };
+            // Offsets which overflow leave the object out (but keep the indices of the following ones)
+            let object = first.checked_add(offset as usize).and_then(|position| RawParser::new(&data, position).with_limits(self.limits).parse_object().ok());
+            objects.push(object.unwrap_or(RawObject::Null));
+        }
+        Ok(objects)
+    }
+}
+
+
+/// Decrypts all strings of an object stored directly in the file (strings inside object streams are not encrypted on their own).
+/// The /Contents of signature dictionaries are not encrypted and strings which fail to decrypt are kept as they are.
+fn decrypt_strings(handler: &SecurityHandler, object: &mut RawObject, id: u64, gen: u16) {
+    match object {
+        RawObject::String(bytes) => {
+            if let Ok(decrypted) = handler.decrypt(handler.string_method, id, gen, bytes) {
+                *bytes = decrypted;
+            }
+        },
+        RawObject::Array(array) => array.iter_mut().for_each(|element| decrypt_strings(handler, element, id, gen)),
+        RawObject::Dictionary(dict) | RawObject::Stream(dict, _) => {
+            let signature = dict.get("ByteRange").is_some();
+            for (key, value) in dict.iter_mut() {
+                if !(signature && key == "Contents") {
+                    decrypt_strings(handler, value, id, gen);
+                }
+            }
+        },
+        _ => {},
+    }
+}
@@ -0,0 +1,72 @@
+//! Runs extract on ZIP archives and checks the limits against zip bombs and unsafe entry paths
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::process::{Command, ExitCode};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+use zugferd::testing::{TestPdf, SAMPLE_INVOICE};
+use zugferd::{Archive, Limits};
+
+
+/// Builds a deflated ZIP archive of the given entries
+fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in entries {
+        writer.start_file(*name, options).expect("failed to start the ZIP entry");
+        writer.write_all(data).expect("failed to write the ZIP entry");
+    }
+    writer.finish().expect("failed to finish the ZIP archive").into_inner()
+}
+
+/// Exit code of reading the first entry of the archive with the given limits
+fn read_first(archive: &[u8], limits: Limits) -> Result<(), ExitCode> {
+    let mut archive = Archive::open(archive, limits).map_err(|error| error.exit_code)?;
+    archive.entry(0).map(|_| ()).map_err(|error| error.exit_code)
+}
+
+
+#[test]
+fn zip_bomb() {
+    let bomb = zip(&[("invoice.xml", &vec![b' '; 4 * 1024 * 1024])]);
+    assert_eq!(read_first(&bomb, Limits::default()), Err(121.into()));
+    assert_eq!(read_first(&bomb, Limits { max_stream_size: 1024 * 1024, max_compression_ratio: 100_000, ..Limits::default() }), Err(120.into()));
+    assert_eq!(read_first(&bomb, Limits { max_compression_ratio: 100_000, ..Limits::default() }), Ok(()));
+}
+
+#[test]
+fn unsafe_paths() {
+    assert_eq!(read_first(&zip(&[("../invoice.xml", b"<Invoice/>")]), Limits::default()), Err(74.into()));
+    assert_eq!(read_first(&zip(&[("/tmp/invoice.xml", b"<Invoice/>")]), Limits::default()), Err(74.into()));
+    assert_eq!(read_first(b"PK\x03\x04 no archive", Limits::default()), Err(73.into()));
+}
+
+#[test]
+fn extract_archive() {
+    let directory = std::env::temp_dir().join(format!("zugferd-archive-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("failed to create the scratch directory");
+
+    let pdf = TestPdf::default().build();
+    let archive = zip(&[
+        ("2024-05/invoice.pdf", &pdf),
+        ("2024-05/xrechnung.xml", SAMPLE_INVOICE.as_bytes()),
+        ("readme.txt", b"Invoices of May"),
+        ("../escape.xml", SAMPLE_INVOICE.as_bytes()),
+    ]);
+    fs::write(directory.join("bundle.zip"), archive).expect("failed to write bundle.zip");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_extract")).arg(directory.join("bundle.zip")).arg(directory.join("out")).output().expect("failed to run extract").status;
+    let pdf_output = fs::read_to_string(directory.join("out/bundle/2024-05/invoice.pdf.xml"));
+    let xml_output = fs::read_to_string(directory.join("out/bundle/2024-05/xrechnung.xml"));
+    let escaped = directory.join("escape.xml").exists() || directory.join("out/escape.xml").exists();
+    let _ = fs::remove_dir_all(&directory);
+
+    // The unsafe entry fails the run, but does not stop the others
+    assert_eq!(status.code(), Some(74));
+    assert_eq!(pdf_output.expect("no output for the PDF"), SAMPLE_INVOICE);
+    assert_eq!(xml_output.expect("no output for the XML"), SAMPLE_INVOICE);
+    assert!(!escaped);
+}
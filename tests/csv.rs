@@ -0,0 +1,67 @@
+//! Writes the CSV tables of the sample invoice and compares them byte for byte with the files in tests/golden
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use zugferd::testing::SAMPLE_INVOICE;
+use zugferd::{CsvOptions, CsvTable, CsvWriter, Invoice};
+
+
+/// German Excel: ';' as delimiter, ',' as decimal separator and a byte order mark
+const GERMAN: CsvOptions = CsvOptions { delimiter: ';', decimal_separator: ',', bom: true };
+
+
+/// The sample invoice with the given number and a line name which needs quoting with ';' as delimiter
+fn invoice(number: &str) -> Invoice {
+    let xml = SAMPLE_INVOICE.replace("TEST-0001", number).replace("Test article", "Test article; &quot;large&quot;");
+    Invoice::parse(&xml).unwrap_or_else(|error| panic!("{}", error.message))
+}
+
+/// Appends two invoices to a new file and returns its content
+fn export(name: &str, table: CsvTable, options: CsvOptions) -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!("zugferd-csv-{}-{}.csv", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    for (number, source) in [("TEST-0001", "first.pdf"), ("TEST-0002", "second.pdf")] {
+        let result = CsvWriter::append(&path, table, options).and_then(|mut writer| writer.write_invoice(&invoice(number), source));
+        assert!(result.is_ok(), "failed to write invoice {}", number);
+    }
+    let content = fs::read(&path).expect("no CSV written");
+    let _ = fs::remove_file(&path);
+    content
+}
+
+fn golden(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name)
+}
+
+/// Compares the content with the golden file (set ZUGFERD_UPDATE_GOLDEN to rewrite the golden file instead)
+fn assert_golden(content: &[u8], name: &str) {
+    if std::env::var_os("ZUGFERD_UPDATE_GOLDEN").is_some() {
+        fs::write(golden(name), content).expect("failed to write the golden file");
+    }
+    let expected = fs::read(golden(name)).unwrap_or_else(|error| panic!("failed to read {}: {}", name, error));
+    assert_eq!(String::from_utf8_lossy(content), String::from_utf8_lossy(&expected), "differs from {}", name);
+}
+
+
+#[test]
+fn lines() {
+    assert_golden(&export("lines", CsvTable::Lines, CsvOptions::default()), "lines.csv");
+}
+
+#[test]
+fn lines_german() {
+    let content = export("lines-german", CsvTable::Lines, GERMAN);
+    assert!(content.starts_with("\u{FEFF}".as_bytes()), "no byte order mark");
+    assert_golden(&content, "lines-german.csv");
+}
+
+#[test]
+fn vat_breakdown() {
+    assert_golden(&export("vat", CsvTable::VatBreakdown, CsvOptions::default()), "vat-breakdown.csv");
+}
+
+#[test]
+fn vat_breakdown_german() {
+    assert_golden(&export("vat-german", CsvTable::VatBreakdown, GERMAN), "vat-breakdown-german.csv");
+}
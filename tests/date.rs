@@ -0,0 +1,109 @@
+//! Checks the lenient parsing of PDF date strings and XMP dates against the variants found in real world PDFs
+
+use zugferd::{xmp_dates, PdfDate};
+
+
+/// Parses the PDF date string and returns the ISO 8601 date with the non-conformant flag
+fn pdf(text: &str) -> Option<(String, bool)> {
+    PdfDate::parse(text).map(|date| (date.to_string(), date.non_conformant))
+}
+
+fn xmp(text: &str) -> Option<(String, bool)> {
+    PdfDate::parse_xmp(text).map(|date| (date.to_string(), date.non_conformant))
+}
+
+fn conformant(date: &str) -> Option<(String, bool)> {
+    Some((String::from(date), false))
+}
+
+fn non_conformant(date: &str) -> Option<(String, bool)> {
+    Some((String::from(date), true))
+}
+
+
+#[test]
+fn pdf_date_strings() {
+    assert_eq!(pdf("D:20240501100000+02'00'"), conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(pdf("D:20240501100000+02'00"), conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(pdf("D:20240501100000-05'30'"), conformant("2024-05-01T10:00:00-05:30"));
+    assert_eq!(pdf("D:20240501100000Z"), conformant("2024-05-01T10:00:00Z"));
+    assert_eq!(pdf("D:20240501100000Z00'00'"), conformant("2024-05-01T10:00:00Z"));
+    assert_eq!(pdf("D:20240501100000"), conformant("2024-05-01T10:00:00"));
+}
+
+#[test]
+fn pdf_date_strings_with_missing_parts() {
+    assert_eq!(pdf("D:2024"), conformant("2024-01-01T00:00:00"));
+    assert_eq!(pdf("D:202405"), conformant("2024-05-01T00:00:00"));
+    assert_eq!(pdf("D:20240501"), conformant("2024-05-01T00:00:00"));
+    assert_eq!(pdf("D:2024050110+01"), conformant("2024-05-01T10:00:00+01:00"));
+}
+
+#[test]
+fn pdf_date_strings_with_wrong_apostrophes() {
+    assert_eq!(pdf("D:20240501100000+02’00’"), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(pdf("D:20240501100000+02′00′"), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(pdf("D:20240501100000+02\"00\""), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(pdf("D:20240501100000+02:00"), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(pdf("D:20240501100000+0200"), non_conformant("2024-05-01T10:00:00+02:00"));
+}
+
+#[test]
+fn non_conformant_pdf_date_strings() {
+    assert_eq!(pdf("20240501100000+02'00'"), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(pdf(" D:20240501100000Z\0"), non_conformant("2024-05-01T10:00:00Z"));
+    assert_eq!(pdf("2024-05-01T10:00:00+02:00"), non_conformant("2024-05-01T10:00:00+02:00"));
+}
+
+#[test]
+fn invalid_pdf_date_strings() {
+    assert_eq!(pdf(""), None);
+    assert_eq!(pdf("D:"), None);
+    assert_eq!(pdf("D:24"), None);
+    assert_eq!(pdf("D:20241301"), None);
+    assert_eq!(pdf("D:20230229"), None);
+    assert_eq!(pdf("D:20240501250000"), None);
+    assert_eq!(pdf("D:20240501100000+02'00' GMT"), None);
+    assert_eq!(pdf("yesterday"), None);
+}
+
+#[test]
+fn xmp_dates_in_iso_8601() {
+    assert_eq!(xmp("2024-05-01T10:00:00+02:00"), conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(xmp("2024-05-01T10:00:00.123Z"), conformant("2024-05-01T10:00:00Z"));
+    assert_eq!(xmp("2024-05-01T10:00+02:00"), conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(xmp("2024-05-01T10:00:00"), conformant("2024-05-01T10:00:00"));
+    assert_eq!(xmp("2024-02-29"), conformant("2024-02-29T00:00:00"));
+    assert_eq!(xmp("2024-05"), conformant("2024-05-01T00:00:00"));
+    assert_eq!(xmp("2024"), conformant("2024-01-01T00:00:00"));
+}
+
+#[test]
+fn non_conformant_xmp_dates() {
+    assert_eq!(xmp("2024-05-01 10:00:00+02:00"), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(xmp("2024-05-01T10:00:00+0200"), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(xmp("2024-05-01T10:00:00-05"), non_conformant("2024-05-01T10:00:00-05:00"));
+    assert_eq!(xmp("D:20240501100000+02'00'"), non_conformant("2024-05-01T10:00:00+02:00"));
+    assert_eq!(xmp("2024-05-01T10"), None);
+}
+
+#[test]
+fn timestamps() {
+    assert_eq!(PdfDate::parse("D:19700101000000Z").map(|date| date.timestamp()), Some(0));
+    assert_eq!(PdfDate::parse("D:20240501100000+02'00'").map(|date| date.timestamp()), Some(1714550400));
+    assert_eq!(PdfDate::parse_xmp("2024-05-01T08:00:00Z").map(|date| date.timestamp()), Some(1714550400));
+}
+
+#[test]
+fn dates_of_xmp_metadata() {
+    let xmp = r#"<rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:MetadataDate="2024-05-01T10:00:00Z">
+<xmp:CreateDate>2024-05-01T10:00:00+02:00</xmp:CreateDate>
+<xmp:ModifyDate> 2024-05-02T10:00:00+02:00 </xmp:ModifyDate>
+</rdf:Description>"#;
+
+    assert_eq!(xmp_dates(xmp), vec![
+        (String::from("xmp:MetadataDate"), String::from("2024-05-01T10:00:00Z")),
+        (String::from("xmp:CreateDate"), String::from("2024-05-01T10:00:00+02:00")),
+        (String::from("xmp:ModifyDate"), String::from("2024-05-02T10:00:00+02:00")),
+    ]);
+}
@@ -0,0 +1,156 @@
+//! Writes the DATEV Buchungsstapel of the sample invoice, compares it with the golden file in tests/golden and checks the fiscal year
+//! of the header against the invoices
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use zugferd::testing::SAMPLE_INVOICE;
+use zugferd::{DatevMapping, DatevOptions, DatevWriter, Invoice};
+
+
+const MAPPING: &str = "*;*;3400;70000\n";
+
+/// Path of a scratch file, which is removed first
+fn scratch_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("zugferd-datev-{}-{}.csv", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn options(fiscal_year_start: Option<&str>) -> DatevOptions {
+    DatevOptions { consultant: 1001, client: 1, fiscal_year_start: fiscal_year_start.map(String::from), account_length: 4 }
+}
+
+/// The sample invoice issued on the given YYYYMMDD date
+fn invoice(issue_date: &str) -> Invoice {
+    sample_with(&SAMPLE_INVOICE.replace("20240101", issue_date))
+}
+
+fn sample_with(xml: &str) -> Invoice {
+    Invoice::parse(xml).unwrap_or_else(|error| panic!("{}", error.message))
+}
+
+/// Appends the invoice to the file and returns the exit code on failure
+fn append(path: &Path, options: &DatevOptions, invoice: &Invoice) -> Result<(), ExitCode> {
+    append_with_mapping(path, options, invoice, MAPPING)
+}
+
+fn append_with_mapping(path: &Path, options: &DatevOptions, invoice: &Invoice, mapping: &str) -> Result<(), ExitCode> {
+    let mapping = DatevMapping::parse(mapping).map_err(|error| error.exit_code)?;
+    DatevWriter::append(path, options, invoice).and_then(|mut writer| writer.write_invoice(invoice, &mapping)).map_err(|error| error.exit_code)
+}
+
+
+#[test]
+fn golden() {
+    // A G/L account of the SKR03 (padded to the Sachkontenlänge of 5), a creditor account and a credit note of a seller with umlauts
+    let mapping = "*;*;3400;700001\n";
+    let options = DatevOptions { consultant: 1001, client: 1, fiscal_year_start: None, account_length: 5 };
+    let seller = |xml: String| xml.replace("Seller GmbH", "Müller &amp; Söhne GmbH");
+    let invoice = Invoice::parse(&seller(String::from(SAMPLE_INVOICE))).unwrap_or_else(|error| panic!("{}", error.message));
+    let credit_note = Invoice::parse(&seller(SAMPLE_INVOICE.replace("TEST-0001", "GS_0002").replace("<ram:TypeCode>380", "<ram:TypeCode>381").replace("20240101", "20240315")))
+        .unwrap_or_else(|error| panic!("{}", error.message));
+
+    let path = scratch_file("golden");
+    let results = [append_with_mapping(&path, &options, &invoice, mapping), append_with_mapping(&path, &options, &credit_note, mapping)];
+    let mut content = fs::read(&path).unwrap_or_default();
+    let _ = fs::remove_file(&path);
+    assert_eq!(results, [Ok(()), Ok(())]);
+
+    // The creation time of the header (the sixth field) is replaced by the one of the golden file
+    let created = content.iter().enumerate().filter(|(_, byte)| **byte == b';').nth(4).map(|(index, _)| index + 1).expect("no header");
+    content.splice(created..created + 17, *b"20240101120000000");
+    let expected = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/datev.csv");
+    if std::env::var_os("ZUGFERD_UPDATE_GOLDEN").is_some() {
+        fs::write(&expected, &content).expect("failed to write the golden file");
+    }
+    assert_eq!(content, fs::read(&expected).expect("failed to read datev.csv"));
+}
+
+
+#[test]
+fn fiscal_year() {
+    let path = scratch_file("fiscal-year");
+    let results = [
+        append(&path, &options(None), &invoice("20240101")),
+        append(&path, &options(None), &invoice("20241231")),
+        append(&path, &options(None), &invoice("20250101")),
+    ];
+    let header = fs::read(&path).unwrap_or_default();
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(results, [Ok(()), Ok(()), Err(85.into())]);
+    assert!(String::from_utf8_lossy(&header).lines().next().unwrap_or_default().contains(";20240101;4;20240101;20241231;"));
+}
+
+#[test]
+fn fiscal_year_start() {
+    let path = scratch_file("fiscal-year-start");
+    let results = [
+        append(&path, &options(Some("20230701")), &invoice("20240101")),
+        append(&path, &options(Some("20230701")), &invoice("20240701")),
+    ];
+    let header = fs::read(&path).unwrap_or_default();
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(results, [Ok(()), Err(85.into())]);
+    assert!(String::from_utf8_lossy(&header).contains(";20230701;4;20230701;20240630;"));
+}
+
+#[test]
+fn invalid_fiscal_year_start() {
+    for start in ["20241301", "20240230", "20240000", "2024-1-1", "99990101"] {
+        let path = scratch_file("invalid");
+        assert_eq!(append(&path, &options(Some(start)), &invoice("20240101")), Err(85.into()), "fiscal year start {}", start);
+        let _ = fs::remove_file(&path);
+    }
+
+    // Years before 1000 are zero padded in the header instead of breaking the end of the fiscal year
+    let path = scratch_file("early");
+    let result = append(&path, &options(Some("09990301")), &invoice("10000101"));
+    let header = fs::read(&path).unwrap_or_default();
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(result, Ok(()));
+    assert!(String::from_utf8_lossy(&header).contains(";09990301;4;09990301;10000228;"));
+}
+
+#[test]
+fn conflicting_fiscal_year_start() {
+    // Appending without a fiscal year start or with the one of the header is fine
+    let path = scratch_file("conflicting-start");
+    let results = [
+        append(&path, &options(Some("20230701")), &invoice("20240101")),
+        append(&path, &options(Some("20230701")), &invoice("20240301")),
+        append(&path, &options(None), &invoice("20240401")),
+        append(&path, &options(Some("20240101")), &invoice("20240501")),
+    ];
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(results, [Ok(()), Ok(()), Ok(()), Err(85.into())]);
+}
+
+#[test]
+fn foreign_currency() {
+    let path = scratch_file("currency");
+    let result = append(&path, &options(None), &sample_with(&SAMPLE_INVOICE.replace(">EUR<", ">USD<").replace("\"EUR\"", "\"USD\"")));
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(result, Err(89.into()));
+}
+
+#[test]
+fn amount_decimals() {
+    // Zeros after the cents are dropped, but a fraction of a cent is not rounded away
+    let path = scratch_file("decimals");
+    let results = [
+        append(&path, &options(None), &sample_with(&SAMPLE_INVOICE.replace("<ram:BasisAmount>100.00<", "<ram:BasisAmount>100.0000<"))),
+        append(&path, &options(None), &sample_with(&SAMPLE_INVOICE.replace("<ram:BasisAmount>100.00<", "<ram:BasisAmount>100.005<"))),
+    ];
+    let content = fs::read(&path).unwrap_or_default();
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(results, [Ok(()), Err(88.into())]);
+    assert!(String::from_utf8_lossy(&content).lines().nth(2).is_some_and(|row| row.starts_with("119,00;")));
+}
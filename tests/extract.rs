@@ -0,0 +1,316 @@
+//! Runs the extract binary on generated PDFs (see zugferd::testing) and checks the documented exit codes
+//! in strict and in lenient mode.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde_json::Value;
+use zugferd::testing::{Defect, TestPdf, SAMPLE_INVOICE};
+use zugferd::INVOICE_JSON_SCHEMA;
+
+
+/// A scratch directory with the input.pdf for a single test
+struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str, pdf: Option<&[u8]>) -> Scratch {
+        let dir = std::env::temp_dir().join(format!("zugferd-extract-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create the scratch directory");
+        if let Some(pdf) = pdf {
+            fs::write(dir.join("input.pdf"), pdf).expect("failed to write input.pdf");
+        }
+        Scratch { dir }
+    }
+
+    fn input(&self) -> PathBuf {
+        self.dir.join("input.pdf")
+    }
+
+    fn output(&self) -> PathBuf {
+        self.dir.join("input.pdf.xml")
+    }
+
+    /// Runs extract on input.pdf with the given arguments and returns the exit code
+    fn extract(&self, args: &[&str]) -> i32 {
+        self.extract_with_stderr(args).0
+    }
+
+    /// Runs extract on input.pdf with the given arguments and returns the exit code and what was printed to stderr
+    fn extract_with_stderr(&self, args: &[&str]) -> (i32, String) {
+        let _ = fs::remove_file(self.output());
+        let output = Command::new(env!("CARGO_BIN_EXE_extract"))
+            .arg(self.input())
+            .args(args)
+            .output()
+            .expect("failed to run extract");
+        (output.status.code().expect("extract was terminated by a signal"), String::from_utf8_lossy(&output.stderr).into_owned())
+    }
+
+    /// Asserts that a lenient run succeeds and reports the errors it fell back from with the given messages
+    fn assert_fallbacks(&self, messages: &[&str]) {
+        let (code, stderr) = self.extract_with_stderr(&[]);
+        assert_eq!(code, 0, "exit code in lenient mode");
+        for message in messages {
+            assert!(stderr.contains(message), "{:?} is not reported in {:?}", message, stderr);
+        }
+    }
+
+    /// Asserts the exit codes of a run in strict mode and in lenient mode
+    fn assert_codes(&self, args: &[&str], strict: i32, lenient: i32) {
+        let strict_args: Vec<&str> = args.iter().copied().chain(["--strict"]).collect();
+        assert_eq!(self.extract(&strict_args), strict, "exit code in strict mode");
+        assert_eq!(self.extract(args), lenient, "exit code in lenient mode");
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+
+/// Builds the PDF with the given defect and checks the exit codes. A lenient run, which succeeds, must write the invoice.
+fn check_defect(name: &str, defect: Defect, strict: i32, lenient: i32) {
+    let scratch = Scratch::new(name, Some(&TestPdf::with_defect(defect).build()));
+    scratch.assert_codes(&[], strict, lenient);
+    if lenient == 0 {
+        // A carved invoice ends with the closing tag of the root element
+        assert_eq!(fs::read_to_string(scratch.output()).expect("no output written").trim_end(), SAMPLE_INVOICE.trim_end());
+    }
+}
+
+
+#[test]
+fn conformant() {
+    let scratch = Scratch::new("conformant", Some(&TestPdf::default().build()));
+    scratch.assert_codes(&[], 0, 0);
+    assert_eq!(fs::read_to_string(scratch.output()).expect("no output written"), SAMPLE_INVOICE);
+}
+
+#[test]
+fn conformant_uncompressed() {
+    let pdf = TestPdf { compress: false, ..TestPdf::default() };
+    let scratch = Scratch::new("uncompressed", Some(&pdf.build()));
+    scratch.assert_codes(&[], 0, 0);
+    assert_eq!(fs::read_to_string(scratch.output()).expect("no output written"), SAMPLE_INVOICE);
+}
+
+#[test]
+fn missing_metadata() {
+    check_defect("missing-metadata", Defect::MissingMetadata, 10, 0);
+}
+
+#[test]
+fn unparsable_metadata() {
+    check_defect("unparsable-metadata", Defect::UnparsableMetadata, 11, 0);
+}
+
+#[test]
+fn corrupt_metadata_stream() {
+    check_defect("corrupt-metadata", Defect::CorruptMetadataStream, 12, 0);
+}
+
+#[test]
+fn non_utf8_metadata() {
+    check_defect("non-utf8-metadata", Defect::NonUtf8Metadata, 13, 0);
+}
+
+#[test]
+fn missing_factur_x_schema() {
+    check_defect("missing-schema", Defect::MissingFacturXSchema, 14, 0);
+}
+
+#[test]
+fn missing_document_file_name() {
+    check_defect("missing-file-name", Defect::MissingDocumentFileName, 15, 0);
+}
+
+#[test]
+fn empty_document_file_name() {
+    check_defect("empty-file-name", Defect::EmptyDocumentFileName, 16, 0);
+}
+
+#[test]
+fn wrong_document_file_name() {
+    // Lenient mode takes any .xml file from /AF
+    check_defect("wrong-file-name", Defect::WrongDocumentFileName, 20, 0);
+}
+
+#[test]
+fn non_xml_attachment_name() {
+    // Neither /AF (20, 21) nor /EmbeddedFiles (34, 36) lists an .xml file, so lenient mode carves the invoice
+    check_defect("non-xml-name", Defect::NonXmlAttachmentName, 20, 0);
+    let scratch = Scratch::new("non-xml-name-messages", Some(&TestPdf::with_defect(Defect::NonXmlAttachmentName).build()));
+    scratch.assert_fallbacks(&["No embedded .xml file found in /AF array", "found in /EmbeddedFiles structure", "No embedded .xml files found in /EmbeddedFiles structure"]);
+}
+
+#[test]
+fn missing_associated_files() {
+    // Lenient mode searches /EmbeddedFiles instead
+    check_defect("missing-af", Defect::MissingAssociatedFiles, 2, 0);
+}
+
+#[test]
+fn missing_embedded_files() {
+    // /EmbeddedFiles is only the fallback for a missing /AF array
+    check_defect("missing-embedded-files", Defect::MissingEmbeddedFiles, 0, 0);
+}
+
+#[test]
+fn missing_names_dictionary() {
+    let scratch = Scratch::new("missing-names", Some(&TestPdf { defects: vec![Defect::MissingAssociatedFiles, Defect::MissingEmbeddedFiles], ..TestPdf::default() }.build()));
+    scratch.assert_codes(&[], 2, 0);
+    scratch.assert_fallbacks(&["names dictionary not found"]);
+}
+
+#[test]
+fn missing_embedded_files_tree() {
+    let scratch = Scratch::new("missing-tree", Some(&TestPdf { defects: vec![Defect::MissingAssociatedFiles, Defect::MissingEmbeddedFilesTree], ..TestPdf::default() }.build()));
+    scratch.assert_codes(&[], 2, 0);
+    scratch.assert_fallbacks(&["No /EmbeddedFiles found"]);
+}
+
+#[test]
+fn unparsable_embedded_files_tree() {
+    let scratch = Scratch::new("unparsable-tree", Some(&TestPdf { defects: vec![Defect::MissingAssociatedFiles, Defect::UnparsableEmbeddedFilesTree], ..TestPdf::default() }.build()));
+    scratch.assert_codes(&[], 2, 0);
+    scratch.assert_fallbacks(&["Iteration over /EmbeddedFiles failed"]);
+}
+
+#[test]
+fn missing_embedded_file_stream() {
+    // Carving finds no invoice either, as the stream does not exist
+    check_defect("missing-ef", Defect::MissingEmbeddedFileStream, 40, 44);
+}
+
+#[test]
+fn missing_embedded_file_reference() {
+    // Lenient mode carves the invoice out of the stream, which is still in the file
+    check_defect("missing-ef-reference", Defect::MissingEmbeddedFileReference, 41, 0);
+}
+
+#[test]
+fn unparsable_embedded_file_stream() {
+    check_defect("unparsable-ef", Defect::UnparsableEmbeddedFileStream, 42, 44);
+}
+
+#[test]
+fn corrupt_embedded_file_stream() {
+    // Carving only finds invoices in streams which decode
+    check_defect("corrupt-ef", Defect::CorruptEmbeddedFileStream, 43, 44);
+}
+
+#[test]
+fn unreferenced_attachment() {
+    // Lenient mode carves the invoice out of the unreferenced stream
+    check_defect("unreferenced", Defect::UnreferencedAttachment, 2, 0);
+}
+
+#[test]
+fn missing_input() {
+    let scratch = Scratch::new("missing-input", None);
+    scratch.assert_codes(&[], 1, 1);
+}
+
+#[test]
+fn unrecoverable_file() {
+    let scratch = Scratch::new("garbage", Some(b"This is not a PDF file at all"));
+    scratch.assert_codes(&[], 98, 98);
+}
+
+#[test]
+fn missing_revision() {
+    let scratch = Scratch::new("missing-revision", Some(&TestPdf::default().build()));
+    scratch.assert_codes(&["--revision", "2"], 97, 97);
+}
+
+/// Asserts that the JSON object has exactly the properties the schema object requires
+fn assert_schema_keys(json: &Value, schema: &Value) {
+    let mut keys: Vec<&str> = json.as_object().expect("not a JSON object").keys().map(String::as_str).collect();
+    let mut required: Vec<&str> = schema["required"].as_array().expect("no required properties").iter().filter_map(Value::as_str).collect();
+    keys.sort_unstable();
+    required.sort_unstable();
+    assert_eq!(keys, required);
+}
+
+#[test]
+fn invoice_json() {
+    let scratch = Scratch::new("invoice-json", Some(&TestPdf::default().build()));
+    scratch.assert_codes(&["--format", "invoice-json"], 0, 0);
+    let json: Value = serde_json::from_str(&fs::read_to_string(scratch.dir.join("input.pdf.json")).expect("no JSON written")).expect("invalid JSON");
+    let schema: Value = serde_json::from_str(INVOICE_JSON_SCHEMA).expect("invalid schema");
+
+    assert_schema_keys(&json, &schema);
+    assert_schema_keys(&json["seller"], &schema["$defs"]["party"]);
+    assert_schema_keys(&json["buyer"], &schema["$defs"]["party"]);
+    assert_schema_keys(&json["lines"][0], &schema["$defs"]["line"]);
+    assert_schema_keys(&json["vat_breakdown"][0], &schema["$defs"]["vatBreakdown"]);
+    assert_schema_keys(&json["totals"], &schema["$defs"]["totals"]);
+
+    // Amounts are strings with the exact value from the XML, missing values are null
+    assert_eq!(json["format_version"], 1);
+    assert_eq!(json["syntax"], "cii");
+    assert_eq!(json["number"], "TEST-0001");
+    assert_eq!(json["issue_date"], "2024-01-01");
+    assert_eq!(json["due_date"], Value::Null);
+    assert_eq!(json["seller"]["vat_id"], "DE123456789");
+    assert_eq!(json["buyer"]["vat_id"], Value::Null);
+    assert_eq!(json["lines"].as_array().map(Vec::len), Some(1));
+    assert_eq!(json["lines"][0]["quantity"], "1");
+    assert_eq!(json["lines"][0]["net_price"], "100.00");
+    assert_eq!(json["vat_breakdown"][0]["rate"], "19");
+    assert_eq!(json["totals"]["grand_total"], "119.00");
+}
+
+#[test]
+fn attachment_is_not_utf8() {
+    let pdf = TestPdf { attachment: b"\xFF\xFE no invoice".to_vec(), ..TestPdf::default() };
+    let scratch = Scratch::new("not-utf8", Some(&pdf.build()));
+    scratch.assert_codes(&["--format", "invoice-json"], 60, 60);
+}
+
+#[test]
+fn stream_size_limit() {
+    // Uncompressed, so the compression ratio limit cannot kick in first
+    let attachment = SAMPLE_INVOICE.replace("Test article", &"x".repeat(2 * 1024 * 1024));
+    let pdf = TestPdf { attachment: attachment.into_bytes(), compress: false, ..TestPdf::default() };
+    let scratch = Scratch::new("stream-size", Some(&pdf.build()));
+    scratch.assert_codes(&["--max-stream-size", "1"], 120, 120);
+}
+
+#[test]
+fn compression_ratio_limit() {
+    let attachment = SAMPLE_INVOICE.replace("Test article", &"x".repeat(2 * 1024 * 1024));
+    let pdf = TestPdf { attachment: attachment.into_bytes(), ..TestPdf::default() };
+    let scratch = Scratch::new("compression-ratio", Some(&pdf.build()));
+    scratch.assert_codes(&["--max-compression-ratio", "10"], 121, 121);
+}
+
+#[test]
+fn nesting_depth_limit() {
+    // The /Params dictionary of the embedded file stream is nested in the stream dictionary
+    let scratch = Scratch::new("nesting-depth", Some(&TestPdf::default().build()));
+    scratch.assert_codes(&["--max-nesting-depth", "1"], 122, 122);
+}
+
+#[test]
+fn name_tree_size_limit() {
+    // Strict mode does not look at /EmbeddedFiles, so only lenient mode walks the name tree
+    let scratch = Scratch::new("name-tree-size", Some(&TestPdf::with_defect(Defect::MissingAssociatedFiles).build()));
+    scratch.assert_codes(&["--max-name-tree-entries", "0"], 2, 123);
+}
+
+#[cfg(unix)]
+#[test]
+fn time_limit() {
+    // Opening a FIFO without a writer blocks the extraction thread until the time runs out
+    let scratch = Scratch::new("time", None);
+    let status = Command::new("mkfifo").arg(scratch.input()).status().expect("failed to run mkfifo");
+    assert!(status.success(), "mkfifo failed");
+    scratch.assert_codes(&["--max-time", "1"], 124, 124);
+}
@@ -0,0 +1,73 @@
+//! Runs the inspect binary on small handwritten PDFs and checks how references are resolved
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+
+/// Writes a PDF with the given objects (id, generation and content) and a classic cross-reference table
+fn write_pdf(name: &str, objects: &[(u64, u16, &str)]) -> PathBuf {
+    let mut pdf = String::from("%PDF-1.7\n");
+    let mut offsets = Vec::new();
+    for (id, gen, content) in objects {
+        offsets.push((*id, *gen, pdf.len()));
+        pdf.push_str(&format!("{} {} obj\n{}\nendobj\n", id, gen, content));
+    }
+
+    let size = objects.iter().map(|(id, _, _)| id + 1).max().unwrap_or(1);
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n", size));
+    for id in 0..size {
+        match offsets.iter().find(|(object_id, _, _)| *object_id == id) {
+            Some((_, gen, offset)) => pdf.push_str(&format!("{:010} {:05} n \n", offset, gen)),
+            None => pdf.push_str("0000000000 65535 f \n"),
+        }
+    }
+    pdf.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", size, xref));
+
+    let path = std::env::temp_dir().join(format!("zugferd-inspect-{}-{}.pdf", name, std::process::id()));
+    fs::write(&path, pdf).expect("failed to write the PDF");
+    path
+}
+
+/// Runs inspect with the given arguments and returns the exit code and the output
+fn inspect(path: &PathBuf, args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_inspect")).arg(path).args(args).output().expect("failed to run inspect");
+    (output.status.code().expect("inspect was terminated by a signal"), String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+
+#[test]
+fn reference_generation() {
+    let path = write_pdf("generation", &[
+        (1, 0, "<< /Type /Catalog /Pages 2 0 R /Info 3 1 R >>"),
+        (2, 0, "<< /Type /Pages /Kids [] /Count 0 >>"),
+        (3, 1, "<< /Title (Generation one) >>"),
+    ]);
+    let results = [
+        inspect(&path, &["--ref", "3 1 R"]),
+        inspect(&path, &["--query", "/Root/Info/Title"]),
+        inspect(&path, &["--ref", "3 0 R"]),
+        inspect(&path, &["--query", "3 2 R/Title"]),
+    ];
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(results[0].0, 0);
+    assert!(results[0].1.contains("Generation one"), "{}", results[0].1);
+    assert_eq!(results[1].0, 0);
+    assert!(results[1].1.contains("Generation one"), "{}", results[1].1);
+    // A reference with another generation must not resolve to the object
+    assert_eq!(results[2].0, 4);
+    assert_eq!(results[3].0, 4);
+}
+
+#[test]
+fn range_overflow() {
+    let path = write_pdf("range", &[(1, 0, "<< /Type /Catalog >>"), (2, 0, "<< /Length 4 >>\nstream\ntest\nendstream")]);
+    let output = Command::new(env!("CARGO_BIN_EXE_inspect")).arg(&path).args(["--print", "2", "--range", "0xffffffffffffffff+2"]).output().expect("failed to run inspect");
+    let _ = fs::remove_file(&path);
+
+    // Rejected by the argument parser instead of overflowing
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not a valid offset"));
+}
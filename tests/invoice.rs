@@ -0,0 +1,37 @@
+//! Checks the exact decimal arithmetic of the invoice model on amounts at the edge of its range
+
+use zugferd::Decimal;
+
+
+fn decimal(text: &str) -> Decimal {
+    Decimal::parse(text).unwrap_or_else(|| panic!("failed to parse {}", text))
+}
+
+
+#[test]
+fn decimal_format() {
+    assert_eq!(decimal("1234.5").with_min_scale(2).map(|value| value.format(',')).as_deref(), Some("1234,50"));
+    assert_eq!(decimal("-0.07").format('.'), "-0.07");
+    assert_eq!(decimal("19").checked_add(decimal("0.95")), Some(decimal("19.950")));
+    assert_eq!(decimal("119.0000").with_scale(2).map(|value| value.format('.')).as_deref(), Some("119.00"));
+    assert_eq!(decimal("1.5").with_scale(2).map(|value| value.format('.')).as_deref(), Some("1.50"));
+    assert_eq!(decimal("0.005").with_scale(2), None);
+}
+
+#[test]
+fn decimal_overflow() {
+    // Amounts which cannot be scaled to 18 fractional digits are unparsable instead of overflowing later
+    assert_eq!(Decimal::parse("170141183460469231731687303715884105727"), None);
+    assert_eq!(Decimal::parse("10000000000000000000.5"), None);
+
+    let largest = decimal("-10000000000000000000");
+    assert_eq!(largest.with_min_scale(18).map(|value| value.format('.')).as_deref(), Some("-10000000000000000000.000000000000000000"));
+    assert_eq!(largest.checked_add(largest).map(|value| value.format('.')).as_deref(), Some("-20000000000000000000"));
+
+    // Sums of values which cannot be scaled to the same number of fractional digits fail instead of being wrong
+    assert_eq!(decimal("1").with_min_scale(40), None);
+    let tiny = decimal("0.0000000001").with_min_scale(30).expect("scale 30 fits");
+    assert_eq!(decimal("10000000000000000000").checked_add(tiny), None);
+    let huge = decimal("1").with_min_scale(38).expect("scale 38 fits");
+    assert_eq!(huge.checked_add(huge), None);
+}
@@ -0,0 +1,114 @@
+//! Checks the MIME parser on the encodings found in real world invoice mails and runs extract on an e-mail with a PDF
+//! and an XML invoice attached
+
+use std::fs;
+use std::process::Command;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use zugferd::testing::{TestPdf, SAMPLE_INVOICE};
+use zugferd::{split_mbox, Limits, MailMessage};
+
+
+fn parse(message: &str) -> MailMessage {
+    MailMessage::parse(message.as_bytes(), &Limits::default()).unwrap_or_else(|error| panic!("{}", error.message))
+}
+
+/// Names of the attachments of the message
+fn names(message: &MailMessage) -> Vec<String> {
+    message.attachments.iter().map(|attachment| attachment.name.clone().unwrap_or_default()).collect()
+}
+
+/// A multipart/mixed message with a text body and the given attachment parts
+fn multipart(parts: &[&str]) -> String {
+    let mut message = String::from("From: billing@example.com\r\nMessage-ID: <1@example.com>\r\nContent-Type: multipart/mixed; boundary=\"b\"\r\n\r\n");
+    message.push_str("--b\r\nContent-Type: text/plain\r\n\r\nPlease find attached our invoice.\r\n");
+    for part in parts {
+        message.push_str(&format!("--b\r\n{}\r\n", part));
+    }
+    message.push_str("--b--\r\n");
+    message
+}
+
+
+#[test]
+fn headers() {
+    let message = parse("From: =?utf-8?Q?M=C3=BCller_GmbH?= <billing@example.com>\r\nSubject: =?iso-8859-1?B?UmVjaG51bmc=?=\r\n =?utf-8?Q?_f=C3=BCr_M=C3=A4rz?=\r\nMessage-ID: <1@example.com>\r\n\r\nText");
+    assert_eq!(message.from.as_deref(), Some("Müller GmbH <billing@example.com>"));
+    assert_eq!(message.subject.as_deref(), Some("Rechnung für März"));
+    assert_eq!(message.message_id.as_deref(), Some("<1@example.com>"));
+    assert!(message.attachments.is_empty());
+}
+
+#[test]
+fn attachment_names() {
+    let message = parse(&multipart(&[
+        "Content-Type: application/pdf\r\nContent-Disposition: attachment; filename=\"invoice 1.pdf\"\r\n\r\n%PDF-",
+        "Content-Type: application/pdf; name=invoice2.pdf\r\n\r\n%PDF-",
+        "Content-Type: application/pdf\r\nContent-Disposition: attachment; filename*=utf-8''Rechnung%20M%C3%A4rz.pdf\r\n\r\n%PDF-",
+        "Content-Type: application/pdf\r\nContent-Disposition: attachment;\r\n filename*0*=iso-8859-1''Gr%F6%DFe;\r\n filename*1=\".pdf\"\r\n\r\n%PDF-",
+        "Content-Type: application/xml\r\nContent-Disposition: attachment; filename=\"=?utf-8?B?eHJlY2hudW5nLnhtbA==?=\"\r\n\r\n<Invoice/>",
+    ]));
+    assert_eq!(names(&message), ["invoice 1.pdf", "invoice2.pdf", "Rechnung März.pdf", "Größe.pdf", "xrechnung.xml"]);
+}
+
+#[test]
+fn transfer_encodings() {
+    let message = parse(&multipart(&[
+        "Content-Type: application/pdf; name=a.pdf\r\nContent-Transfer-Encoding: base64\r\n\r\nJVBE\r\nRi0x\r\nLjc=",
+        "Content-Type: application/pdf; name=b.pdf\r\nContent-Transfer-Encoding: BASE64\r\n\r\nJVBERi0xLjc",
+        "Content-Type: text/xml; name=c.xml\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n<a x=3D\"1\">Gr=C3=B6=\r\n=C3=9Fe</a>",
+    ]));
+    let data: Vec<&[u8]> = message.attachments.iter().map(|attachment| attachment.data.as_slice()).collect();
+    assert_eq!(data, [b"%PDF-1.7".as_slice(), b"%PDF-1.7", "<a x=\"1\">Größe</a>".as_bytes()]);
+}
+
+#[test]
+fn forwarded_message() {
+    let forwarded = multipart(&["Content-Type: application/pdf; name=invoice.pdf\r\n\r\n%PDF-"]);
+    let message = parse(&multipart(&[&format!("Content-Type: message/rfc822\r\n\r\n{}", forwarded)]));
+    assert_eq!(names(&message), ["invoice.pdf"]);
+}
+
+#[test]
+fn limits() {
+    let limits = Limits { max_stream_size: 4, max_nesting_depth: 1, ..Limits::default() };
+    let large = multipart(&["Content-Type: application/pdf; name=a.pdf\r\nContent-Transfer-Encoding: base64\r\n\r\nJVBERi0xLjc="]);
+    let nested = multipart(&[&format!("Content-Type: multipart/mixed; boundary=\"c\"\r\n\r\n--c\r\n{}\r\n--c--", "Content-Type: application/pdf\r\n\r\n%PDF")]);
+
+    let code = |message: &str| MailMessage::parse(message.as_bytes(), &limits).map(|_| ()).map_err(|error| error.exit_code);
+    assert_eq!(code(&large), Err(120.into()));
+    assert_eq!(code(&nested), Err(122.into()));
+    assert_eq!(code("This is no e-mail"), Err(70.into()));
+}
+
+#[test]
+fn mbox() {
+    let mbox = "From a@example.com Mon Jan  1 00:00:00 2024\nFrom: a@example.com\n\n>From the start\n\nFrom b@example.com Mon Jan  1 00:00:00 2024\nFrom: b@example.com\n\nText\n";
+    let messages: Vec<&[u8]> = split_mbox(mbox.as_bytes());
+    assert_eq!(messages, [b"From: a@example.com\n\n>From the start\n\n".as_slice(), b"From: b@example.com\n\nText\n"]);
+}
+
+#[test]
+fn extract_mail() {
+    let directory = std::env::temp_dir().join(format!("zugferd-mail-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("failed to create the scratch directory");
+
+    let pdf = BASE64_STANDARD.encode(TestPdf::default().build());
+    let mail = multipart(&[
+        &format!("Content-Type: application/pdf; name=\"invoice.pdf\"\r\nContent-Transfer-Encoding: base64\r\n\r\n{}", pdf),
+        &format!("Content-Type: application/octet-stream; name=\"../xrechnung.xml\"\r\n\r\n{}", SAMPLE_INVOICE),
+    ]);
+    fs::write(directory.join("invoice.eml"), mail).expect("failed to write invoice.eml");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_extract")).arg(directory.join("invoice.eml")).output().expect("failed to run extract");
+    let report = String::from_utf8_lossy(&output.stdout).to_string();
+    let pdf_output = fs::read_to_string(directory.join("invoice.eml.invoice.pdf.xml"));
+    let xml_output = fs::read_to_string(directory.join("invoice.eml.xrechnung.xml"));
+    let _ = fs::remove_dir_all(&directory);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(report.contains("(message <1@example.com> from billing@example.com)"), "report: {}", report);
+    assert_eq!(pdf_output.expect("no output for the PDF"), SAMPLE_INVOICE);
+    assert_eq!(xml_output.expect("no output for the XML"), SAMPLE_INVOICE);
+}
@@ -0,0 +1,51 @@
+//! Runs the inputs the fuzzer found crashes with (fuzz/regressions) through the raw object layer, which must return
+//! errors for them instead of panicking, overflowing or recursing without end
+
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use zugferd::{repair, recover_xref, Limits, RawFile, RawObject};
+
+
+fn input(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions/pdf_bytes").join(name);
+    fs::read(&path).unwrap_or_else(|error| panic!("failed to read {}: {}", path.display(), error))
+}
+
+fn open_code(data: &[u8]) -> Result<(), ExitCode> {
+    RawFile::open(data).map(|_| ()).map_err(|error| error.exit_code)
+}
+
+
+#[test]
+fn xref_subsection_overflow() {
+    assert_eq!(open_code(&input("xref_table_subsection_overflow.pdf")), Err(94.into()));
+    assert_eq!(open_code(&input("xref_stream_index_overflow.pdf")), Err(95.into()));
+}
+
+#[test]
+fn object_stream_offset_overflow() {
+    let data = input("object_stream_offset_overflow.pdf");
+    let file = RawFile::open(&data).unwrap_or_else(|error| panic!("{}", error.message));
+    assert!(matches!(file.resolve(3), Ok(RawObject::Null)));
+    assert!(recover_xref(&data, &Limits::default()).is_ok());
+}
+
+#[test]
+fn object_stream_self_reference() {
+    let data = input("object_stream_self_reference.pdf");
+    let file = RawFile::open(&data).unwrap_or_else(|error| panic!("{}", error.message));
+    assert_eq!(file.resolve(3).map(|_| ()).map_err(|error| error.exit_code), Err(96.into()));
+}
+
+#[test]
+fn repair_huge_object_number() {
+    let data = input("repair_huge_object_number.pdf");
+    let repaired = repair(&data, &Limits::default()).unwrap_or_else(|error| panic!("{}", error.message));
+    assert!(repaired.len() < data.len() + 1024);
+
+    let file = RawFile::open(&repaired).unwrap_or_else(|error| panic!("{}", error.message));
+    assert!(file.catalog().is_ok_and(|catalog| catalog.as_dictionary().is_some()));
+    assert!(file.entries().contains_key(&9_999_999_999));
+}
@@ -0,0 +1,75 @@
+//! Renders the sample invoice into a hybrid PDF and extracts it again in strict mode, which only succeeds for a conformant
+//! Factur-X/ZUGFeRD attachment
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zugferd::testing::SAMPLE_INVOICE;
+
+
+const FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+
+/// A scratch directory with the invoice.xml for a single test
+struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Scratch {
+        let dir = std::env::temp_dir().join(format!("zugferd-render-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create the scratch directory");
+        fs::write(dir.join("invoice.xml"), SAMPLE_INVOICE).expect("failed to write invoice.xml");
+        Scratch { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+
+/// Runs the binary with the given arguments and returns the exit code
+fn run(binary: &str, args: &[&Path]) -> i32 {
+    let output = Command::new(binary).args(args).output().expect("failed to run the binary");
+    output.status.code().expect("the binary was terminated by a signal")
+}
+
+
+#[test]
+fn round_trip() {
+    if !Path::new(FONT).exists() {
+        eprintln!("Skipped, as {} is not installed", FONT);
+        return;
+    }
+
+    let scratch = Scratch::new("round-trip");
+    let (xml, pdf, extracted) = (scratch.path("invoice.xml"), scratch.path("invoice.pdf"), scratch.path("extracted.xml"));
+    assert_eq!(run(env!("CARGO_BIN_EXE_render"), &[&xml, &pdf, Path::new("--font"), Path::new(FONT)]), 0, "exit code of render");
+    assert_eq!(run(env!("CARGO_BIN_EXE_extract"), &[&pdf, &extracted, Path::new("--strict")]), 0, "exit code of extract --strict");
+    assert_eq!(fs::read_to_string(&extracted).expect("no output written"), SAMPLE_INVOICE);
+}
+
+#[test]
+fn unreadable_font() {
+    let scratch = Scratch::new("unreadable-font");
+    let (xml, pdf, font) = (scratch.path("invoice.xml"), scratch.path("invoice.pdf"), scratch.path("missing.ttf"));
+    assert_eq!(run(env!("CARGO_BIN_EXE_render"), &[&xml, &pdf, Path::new("--font"), &font]), 140);
+}
+
+#[test]
+fn unparsable_font() {
+    // The invoice is no TrueType font
+    let scratch = Scratch::new("unparsable-font");
+    let (xml, pdf) = (scratch.path("invoice.xml"), scratch.path("invoice.pdf"));
+    assert_eq!(run(env!("CARGO_BIN_EXE_render"), &[&xml, &pdf, Path::new("--font"), &xml]), 141);
+    assert!(!pdf.exists());
+}
@@ -0,0 +1,67 @@
+//! Checks the certificate chains of signers against a trust store with the test certificates in tests/certificates
+//! (Test Root CA -> Test Intermediate CA -> Test Signer, valid from 2020 to 2120)
+
+use std::fs;
+use std::path::Path;
+
+use x509_cert::Certificate;
+use zugferd::{Trust, TrustStore};
+
+
+/// 2024-01-01T00:00:00Z
+const SIGNING_TIME: i64 = 1_704_067_200;
+
+
+fn certificate(name: &str) -> Certificate {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/certificates").join(name);
+    let pem = fs::read(&path).unwrap_or_else(|error| panic!("failed to read {}: {}", path.display(), error));
+    Certificate::load_pem_chain(&pem).ok().and_then(|chain| chain.into_iter().next()).unwrap_or_else(|| panic!("failed to parse {}", name))
+}
+
+/// Builds the chain of the signer with the given certificates of the signature at the given time
+fn trust(signer: &str, certificates: &[&str], time: i64) -> Trust {
+    let trust_store = TrustStore { certificates: vec![certificate("root.pem")] };
+    let certificates: Vec<Certificate> = certificates.iter().map(|name| certificate(name)).collect();
+    trust_store.trust(&certificate(signer), &certificates, time)
+}
+
+fn assert_untrusted(trust: Trust, reason: &str) {
+    match trust {
+        Trust::Untrusted(message) => assert!(message.contains(reason), "{:?} is not the reason in {:?}", reason, message),
+        Trust::Trusted(anchor) => panic!("trusted via {}, but expected: {}", anchor, reason),
+    }
+}
+
+
+#[test]
+fn trusted_chain() {
+    assert_eq!(trust("signer.pem", &["signer.pem", "intermediate.pem"], SIGNING_TIME), Trust::Trusted(String::from("CN=Test Root CA")));
+}
+
+#[test]
+fn missing_intermediate() {
+    assert_untrusted(trust("signer.pem", &["signer.pem"], SIGNING_TIME), "No trusted issuer found for CN=Test Signer");
+}
+
+#[test]
+fn chain_through_end_entity() {
+    // The key of a trusted signer must not be able to issue further trusted signers
+    assert_untrusted(trust("forged.pem", &["forged.pem", "signer.pem", "intermediate.pem"], SIGNING_TIME), "CN=Test Signer is no CA certificate");
+}
+
+#[test]
+fn chain_through_ca_without_certificate_signing() {
+    assert_untrusted(trust("sub.pem", &["sub.pem", "nocertsign.pem"], SIGNING_TIME), "CN=Test Signing Key CA must not sign certificates");
+}
+
+#[test]
+fn expired_certificate() {
+    // Expired since 2021-01-01, but was valid when signed in 2020
+    assert_untrusted(trust("expired.pem", &["expired.pem", "intermediate.pem"], SIGNING_TIME), "CN=Expired Signer expired on 2021-01-01");
+    assert!(matches!(trust("expired.pem", &["expired.pem", "intermediate.pem"], SIGNING_TIME - 3 * 365 * 86400), Trust::Trusted(_)));
+}
+
+#[test]
+fn not_yet_valid_certificate() {
+    assert_untrusted(trust("signer.pem", &["signer.pem", "intermediate.pem"], 1_500_000_000), "CN=Test Signer is not valid before 2020-01-01");
+}